@@ -0,0 +1,536 @@
+// Functions as control-flow graphs of basic blocks.
+//
+// A block is a straight-line list of instructions ended by a terminator,
+// the only place where control leaves it. Blocks are numbered by their
+// position in `Function::blocks` and block 0 is the entry.
+
+use gen_ir::{Ir, Op, Operand};
+use node::Storage;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Term {
+    // to block n
+    Jmp(usize),
+    // to the first block if the operand is nonzero, else to the second
+    Br(Operand, usize, usize),
+    // return the value, if any, at the given width through the epilogue
+    Ret(Option<Operand>, usize),
+    // control never gets here
+    Unreachable,
+}
+
+impl Term {
+    pub fn succs(&self) -> Vec<usize> {
+        match self {
+            Term::Jmp(b) => vec![*b],
+            Term::Br(_, then, els) if then == els => vec![*then],
+            Term::Br(_, then, els) => vec![*then, *els],
+            Term::Ret(..) | Term::Unreachable => vec![],
+        }
+    }
+
+    // the operand the terminator reads
+    pub fn arg(&self) -> Option<&Operand> {
+        match self {
+            Term::Br(a, _, _) | Term::Ret(Some(a), _) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn arg_mut(&mut self) -> Option<&mut Operand> {
+        match self {
+            Term::Br(a, _, _) | Term::Ret(Some(a), _) => Some(a),
+            _ => None,
+        }
+    }
+
+    // redirects the edges to block `from` to block `to`
+    pub fn retarget(&mut self, from: usize, to: usize) {
+        match self {
+            Term::Jmp(b) => {
+                if *b == from {
+                    *b = to;
+                }
+            }
+            Term::Br(_, then, els) => {
+                if *then == from {
+                    *then = to;
+                }
+                if *els == from {
+                    *els = to;
+                }
+            }
+            Term::Ret(..) | Term::Unreachable => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub ins: Vec<Ir>,
+    pub term: Term,
+    // filled in by `Function::compute_edges`
+    pub preds: Vec<usize>,
+    pub succs: Vec<usize>,
+}
+
+impl Block {
+    pub fn new(term: Term) -> Self {
+        Block {
+            ins: vec![],
+            term,
+            preds: vec![],
+            succs: vec![],
+        }
+    }
+}
+
+// a parameter, stored to its frame slot on entry
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Param {
+    pub offset: isize,
+    pub size: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub storage: Storage,
+    pub params: Vec<Param>,
+    // size of the frame below rbp, a multiple of 16
+    pub frame: isize,
+    // bitmask of the physical registers used, set by the allocator
+    pub used: Option<isize>,
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    // recomputes the predecessors and successors of every block
+    pub fn compute_edges(&mut self) {
+        for b in self.blocks.iter_mut() {
+            b.succs = b.term.succs();
+            b.preds = vec![];
+        }
+        for i in 0..self.blocks.len() {
+            for s in self.blocks[i].succs.clone() {
+                self.blocks[s].preds.push(i);
+            }
+        }
+    }
+
+    // a register number above all the function uses
+    pub fn next_reg(&self) -> isize {
+        let mut next = 0;
+        for b in self.blocks.iter() {
+            for ir in b.ins.iter() {
+                for r in ir.uses().into_iter().chain(ir.dst) {
+                    next = next.max(r + 1);
+                }
+            }
+            if let Some(Operand::Reg(r)) = b.term.arg() {
+                next = next.max(r + 1);
+            }
+        }
+        next
+    }
+
+    // blocks reachable from the entry in reverse postorder
+    pub fn rpo(&self) -> Vec<usize> {
+        let mut order = vec![];
+        if self.blocks.is_empty() {
+            return order;
+        }
+        let mut visited = vec![false; self.blocks.len()];
+        // blocks with the index of the next successor to visit
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((b, i)) = stack.pop() {
+            let succs = &self.blocks[b].succs;
+            if i < succs.len() {
+                stack.push((b, i + 1));
+                let s = succs[i];
+                if !visited[s] {
+                    visited[s] = true;
+                    stack.push((s, 0));
+                }
+            } else {
+                order.push(b);
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    // Drops the blocks unreachable from the entry and renumbers the rest
+    // in their original order, along with the phi operands coming from
+    // the dropped ones.
+    pub fn remove_unreachable(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        for b in self.rpo() {
+            reachable[b] = true;
+        }
+        if reachable.iter().all(|r| *r) {
+            return;
+        }
+        let mut index = vec![None; self.blocks.len()];
+        let mut n = 0;
+        for (b, r) in reachable.iter().enumerate() {
+            if *r {
+                index[b] = Some(n);
+                n += 1;
+            }
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (b, mut block) in blocks.into_iter().enumerate() {
+            if index[b].is_none() {
+                continue;
+            }
+            for s in block.term.succs() {
+                block.term.retarget(s, index[s].unwrap());
+            }
+            for ir in block.ins.iter_mut() {
+                if let Op::Phi(preds) = &ir.op {
+                    let (preds, args) = preds
+                        .iter()
+                        .zip(ir.args.iter())
+                        .filter_map(|(p, a)| index[*p].map(|i| (i, a.clone())))
+                        .unzip();
+                    ir.op = Op::Phi(preds);
+                    ir.args = args;
+                }
+            }
+            self.blocks.push(block);
+        }
+        self.compute_edges();
+    }
+
+    // Shrinks the frame to the highest slot still referenced, by an
+    // instruction or as a parameter the prologue stores.
+    pub fn shrink_frame(&mut self) {
+        let mut size = self.params.iter().map(|p| p.offset).max().unwrap_or(0);
+        for b in self.blocks.iter() {
+            let args = b.ins.iter().flat_map(|ir| ir.args.iter());
+            for a in args.chain(b.term.arg()) {
+                if let Operand::Slot(n) = a {
+                    size = size.max(*n);
+                }
+            }
+        }
+        self.frame = self.frame.min((size + 15) / 16 * 16);
+    }
+
+    // Appends each block reached only by a jump from its single
+    // predecessor to that predecessor, its phis becoming copies, and
+    // drops the blocks emptied this way.
+    pub fn merge_blocks(&mut self) {
+        let mut merged = false;
+        for b in self.rpo() {
+            while let Term::Jmp(s) = self.blocks[b].term {
+                if s == 0 || s == b || self.blocks[s].preds != [b] {
+                    break;
+                }
+                let next = std::mem::replace(&mut self.blocks[s], Block::new(Term::Unreachable));
+                for mut ir in next.ins {
+                    if let Op::Phi(_) = ir.op {
+                        ir = Ir::new(Op::Mov, ir.dst, ir.args, 8);
+                    }
+                    self.blocks[b].ins.push(ir);
+                }
+                for t in next.term.succs() {
+                    for ir in self.blocks[t].ins.iter_mut() {
+                        if let Op::Phi(preds) = &mut ir.op {
+                            preds.iter_mut().filter(|p| **p == s).for_each(|p| *p = b);
+                        }
+                    }
+                }
+                self.blocks[b].term = next.term;
+                self.compute_edges();
+                merged = true;
+            }
+        }
+        if merged {
+            self.remove_unreachable();
+        }
+    }
+}
+
+// The dominator tree, built with the iterative algorithm of Cooper,
+// Harvey and Kennedy. Unreachable blocks are not part of it.
+#[derive(Debug, PartialEq)]
+pub struct DomTree {
+    // immediate dominator; `None` for the entry and unreachable blocks
+    pub idom: Vec<Option<usize>>,
+    pub children: Vec<Vec<usize>>,
+    // position of each reachable block in reverse postorder
+    order: Vec<Option<usize>>,
+}
+
+impl DomTree {
+    pub fn new(f: &Function) -> Self {
+        let n = f.blocks.len();
+        let rpo = f.rpo();
+        let mut order = vec![None; n];
+        for (i, b) in rpo.iter().enumerate() {
+            order[*b] = Some(i);
+        }
+        let mut idom: Vec<Option<usize>> = vec![None; n];
+        if n > 0 {
+            idom[0] = Some(0);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in rpo.iter().skip(1) {
+                let mut new = None;
+                for p in f.blocks[*b].preds.iter() {
+                    if idom[*p].is_none() {
+                        continue;
+                    }
+                    new = match new {
+                        None => Some(*p),
+                        Some(q) => Some(DomTree::intersect(&idom, &order, *p, q)),
+                    };
+                }
+                if new.is_some() && idom[*b] != new {
+                    idom[*b] = new;
+                    changed = true;
+                }
+            }
+        }
+        if n > 0 {
+            idom[0] = None;
+        }
+        let mut children = vec![vec![]; n];
+        for b in rpo.iter() {
+            if let Some(d) = idom[*b] {
+                children[d].push(*b);
+            }
+        }
+        DomTree {
+            idom,
+            children,
+            order,
+        }
+    }
+
+    // whether `a` dominates `b`; every block dominates itself
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        if self.order[a].is_none() || self.order[b].is_none() {
+            return false;
+        }
+        let mut b = b;
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom[b] {
+                Some(d) => b = d,
+                None => return false,
+            }
+        }
+    }
+
+    // reachable blocks in a preorder walk of the tree
+    pub fn preorder(&self) -> Vec<usize> {
+        let mut v = vec![];
+        if self.order.first().is_none_or(|o| o.is_none()) {
+            return v;
+        }
+        let mut stack = vec![0];
+        while let Some(b) = stack.pop() {
+            v.push(b);
+            stack.extend(self.children[b].iter().rev());
+        }
+        v
+    }
+
+    // The dominance frontier of each block: the blocks where its
+    // dominance ends, found by walking up from the predecessors of every
+    // join to its immediate dominator.
+    pub fn frontiers(&self, f: &Function) -> Vec<Vec<usize>> {
+        let mut df = vec![vec![]; f.blocks.len()];
+        for (b, block) in f.blocks.iter().enumerate() {
+            if self.order[b].is_none() || block.preds.len() < 2 {
+                continue;
+            }
+            for p in block.preds.iter() {
+                if self.order[*p].is_none() {
+                    continue;
+                }
+                let mut runner = *p;
+                while Some(runner) != self.idom[b] {
+                    if !df[runner].contains(&b) {
+                        df[runner].push(b);
+                    }
+                    match self.idom[runner] {
+                        Some(d) => runner = d,
+                        None => break,
+                    }
+                }
+            }
+        }
+        df
+    }
+
+    fn intersect(idom: &[Option<usize>], order: &[Option<usize>], a: usize, b: usize) -> usize {
+        let (mut a, mut b) = (a, b);
+        while a != b {
+            while order[a] > order[b] {
+                a = idom[a].expect("no idom");
+            }
+            while order[b] > order[a] {
+                b = idom[b].expect("no idom");
+            }
+        }
+        a
+    }
+}
+
+// if (r0) {} else {}; while (r1) {}; plus an unreachable block
+#[cfg(test)]
+const DIAMOND_LOOP: &str = "def f, 0
+        B0:
+          r0 = mov 1
+          br r0, B1, B2
+        B1:
+          jmp B3
+        B2:
+          jmp B3
+        B3:
+          r1 = mov 1
+          br r1, B4, B5
+        B4:
+          jmp B3
+        B5:
+          ret
+        B6:
+          jmp B5";
+
+// the first function of the IR text
+#[cfg(test)]
+pub fn function(text: &str) -> Function {
+    use ir_text::parse;
+    parse(text).unwrap().remove(0)
+}
+
+#[test]
+fn edges_test() {
+    let f = function(DIAMOND_LOOP);
+    assert_eq!(f.blocks[3].preds, vec![1, 2, 4]);
+    assert_eq!(f.blocks[3].succs, vec![4, 5]);
+    assert_eq!(f.blocks[5].preds, vec![3, 6]);
+    assert_eq!(f.rpo(), vec![0, 2, 1, 3, 5, 4]);
+}
+
+#[test]
+fn dom_tree_test() {
+    let f = function(DIAMOND_LOOP);
+    let dom = DomTree::new(&f);
+    assert_eq!(
+        dom.idom,
+        vec![None, Some(0), Some(0), Some(0), Some(3), Some(3), None]
+    );
+    assert_eq!(dom.children[0], vec![2, 1, 3]);
+    assert!(dom.dominates(0, 4));
+    assert!(dom.dominates(3, 3));
+    assert!(!dom.dominates(1, 3));
+    // unreachable blocks are dominated by nothing
+    assert!(!dom.dominates(0, 6));
+    assert_eq!(dom.preorder(), vec![0, 2, 1, 3, 5, 4]);
+    let df = dom.frontiers(&f);
+    assert_eq!(df[1], vec![3]);
+    assert_eq!(df[2], vec![3]);
+    assert_eq!(df[3], vec![3]);
+    assert_eq!(df[4], vec![3]);
+    assert!(df[0].is_empty() && df[5].is_empty());
+}
+
+#[test]
+fn remove_unreachable_test() {
+    use ir_text::print;
+    let mut f = function(
+        "def f, 0
+        B0:
+          r0 = mov 1
+          jmp B2
+        B1:
+          r1 = mov 2
+          jmp B2
+        B2:
+          r2 = phi B0: r0, B1: r1
+          br r2, B3, B4
+        B3:
+          unreachable
+        B4:
+          ret r2",
+    );
+    f.remove_unreachable();
+    assert_eq!(
+        print(&[f]),
+        "def f, 0
+B0:
+  r0 = mov 1
+  jmp B1
+B1:
+  r2 = phi B0: r0
+  br r2, B2, B3
+B2:
+  unreachable
+B3:
+  ret r2
+"
+    );
+}
+
+#[test]
+fn merge_blocks_test() {
+    use ir_text::print;
+    let mut f = function(
+        "def f, 0
+        B0:
+          r0 = mov 1
+          jmp B1
+        B1:
+          r1 = phi B0: r0
+          jmp B2
+        B2:
+          br r1, B3, B4
+        B3:
+          jmp B4
+        B4:
+          r2 = phi B2: r1, B3: r0
+          ret r2",
+    );
+    f.merge_blocks();
+    assert_eq!(
+        print(&[f]),
+        "def f, 0
+B0:
+  r0 = mov 1
+  r1 = mov r0
+  br r1, B1, B2
+B1:
+  jmp B2
+B2:
+  r2 = phi B0: r1, B1: r0
+  ret r2
+"
+    );
+}
+
+#[test]
+fn shrink_frame_test() {
+    let mut f = function(
+        "def f, 64
+          param.4 4
+        B0:
+          r0 = load.8 s24
+          ret r0",
+    );
+    f.shrink_frame();
+    assert_eq!(f.frame, 32);
+    f.blocks[0].ins.clear();
+    f.blocks[0].term = Term::Ret(None, 0);
+    f.shrink_frame();
+    assert_eq!(f.frame, 16);
+}
@@ -0,0 +1,101 @@
+// Copy propagation.
+//
+// In SSA form `rD = mov X` makes rD another name for X: X is an
+// immediate, an address, or a register whose single definition
+// dominates the copy and so every read of rD. Reads of rD are replaced
+// by X, following chains of copies, and the copies themselves are left
+// for `dce` to remove. Only full-width copies qualify, as a 4-byte move
+// clears the upper half of its value.
+
+use std::collections::HashMap;
+
+use cfg::{Function, Term};
+use gen_ir::{Op, Operand};
+
+pub fn copy_prop(f: &mut Function) {
+    let mut copies = HashMap::new();
+    for b in f.blocks.iter() {
+        for ir in b.ins.iter() {
+            if let (Op::Mov, Some(d), 8, false) = (&ir.op, ir.dst, ir.size, ir.volatile) {
+                copies.insert(d, ir.args[0].clone());
+            }
+        }
+    }
+    let source = |a: &Operand| {
+        let mut a = a.clone();
+        while let Operand::Reg(r) = a {
+            match copies.get(&r) {
+                Some(x) => a = x.clone(),
+                None => break,
+            }
+        }
+        a
+    };
+
+    for b in f.blocks.iter_mut() {
+        for ir in b.ins.iter_mut() {
+            let call = ir.op == Op::Call;
+            for (i, a) in ir.args.iter_mut().enumerate() {
+                let x = source(a);
+                // the backend calls a function by name or through a
+                // register
+                if !call || i != 0 || matches!(x, Operand::Reg(_) | Operand::Global(_)) {
+                    *a = x;
+                }
+            }
+        }
+        // and branches on a register
+        let br = matches!(b.term, Term::Br(..));
+        if let Some(a) = b.term.arg_mut() {
+            let x = source(a);
+            if !br || matches!(x, Operand::Reg(_) | Operand::Imm(_)) {
+                *a = x;
+            }
+        }
+    }
+}
+
+#[test]
+fn copy_prop_test() {
+    use ir_text::{parse, print};
+    let mut funcs = parse(
+        "def f, 16
+        B0:
+          r0 = mov @g
+          r1 = mov s8
+          r2 = mov r1
+          r3 = load.4 r2
+          r4 = mov.4 r3
+          r5 = call r0, r2, r4
+          r6 = mov r5
+          br r6, B1, B2
+        B1:
+          r7 = mov r2
+          br r7, B2, B2
+        B2:
+          r8 = phi B0: r6, B1: r7
+          ret r8",
+    )
+    .unwrap();
+    copy_prop(&mut funcs[0]);
+    assert_eq!(
+        print(&funcs),
+        "def f, 16
+B0:
+  r0 = mov @g
+  r1 = mov s8
+  r2 = mov s8
+  r3 = load.4 s8
+  r4 = mov.4 r3
+  r5 = call @g, s8, r4
+  r6 = mov r5
+  br r5, B1, B2
+B1:
+  r7 = mov s8
+  br r7, B2, B2
+B2:
+  r8 = phi B0: r5, B1: s8
+  ret r8
+"
+    );
+}
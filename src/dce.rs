@@ -0,0 +1,111 @@
+// Dead code elimination.
+//
+// Each block is walked backwards from the registers live out of it, and
+// an instruction whose result is not live at that point is dropped,
+// unless it has an effect besides its result: calls and volatile
+// accesses always stay, as do stores. Dropping an instruction can make
+// the values it read dead in turn, so liveness is recomputed until
+// nothing changes. Blocks unreachable from the entry are removed first,
+// along with the values only they read, and a block only jumped to from
+// one other is merged into it. Last, the frame is cut down to the slots
+// still in use, dropping those of inlined calls that were folded away.
+
+use std::collections::BTreeSet;
+
+use cfg::Function;
+use gen_ir::{Ir, Op, Operand};
+use liveness::Liveness;
+
+pub fn dce(f: &mut Function) {
+    // as in the allocator, kills would keep their registers alive
+    for b in f.blocks.iter_mut() {
+        b.ins.retain(|ir| ir.op != Op::Kill);
+    }
+    f.remove_unreachable();
+    f.merge_blocks();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let live = Liveness::new(f);
+        for (i, b) in f.blocks.iter_mut().enumerate() {
+            let mut live: BTreeSet<isize> = live.live_out[i].clone();
+            if let Some(Operand::Reg(r)) = b.term.arg() {
+                live.insert(*r);
+            }
+            let mut v = vec![];
+            for ir in std::mem::take(&mut b.ins).into_iter().rev() {
+                if ir.dst.is_some_and(|d| !live.contains(&d)) && !has_effect(&ir) {
+                    changed = true;
+                    continue;
+                }
+                // a phi reads its operands in the predecessors
+                if let Some(d) = ir.dst {
+                    live.remove(&d);
+                }
+                if !matches!(ir.op, Op::Phi(_)) {
+                    live.extend(ir.uses());
+                }
+                v.push(ir);
+            }
+            v.reverse();
+            b.ins = v;
+        }
+    }
+    f.shrink_frame();
+}
+
+fn has_effect(ir: &Ir) -> bool {
+    ir.volatile || matches!(ir.op, Op::Call | Op::Store | Op::Restore)
+}
+
+#[test]
+fn dce_test() {
+    use ir_text::{parse, print};
+    let mut funcs = parse(
+        "def f, 16
+        B0:
+          r0 = load.4 s4
+          r1 = add.4 r0, 4
+          r2 = sext.4 r1
+          kill r2
+          r3 = call @g
+          r4 = sext.4 r3
+          r5 = load.4 s8
+          volatile r6 = load.4 s12
+          r7 = mov 1
+          jmp B1
+        B1:
+          r8 = phi B0: r7, B2: r9
+          br r8, B2, B3
+        B2:
+          r9 = mov 0
+          r10 = mov r5
+          jmp B1
+        B3:
+          ret.4 r5
+        B4:
+          r11 = mov r4
+          ret.4 r11",
+    )
+    .unwrap();
+    dce(&mut funcs[0]);
+    assert_eq!(
+        print(&funcs),
+        "def f, 16
+B0:
+  r3 = call @g
+  r5 = load.4 s8
+  volatile r6 = load.4 s12
+  r7 = mov 1
+  jmp B1
+B1:
+  r8 = phi B0: r7, B2: r9
+  br r8, B2, B3
+B2:
+  r9 = mov 0
+  jmp B1
+B3:
+  ret.4 r5
+"
+    );
+}
@@ -0,0 +1,196 @@
+// Constant folding on the IR.
+//
+// In SSA form every register has a single definition, so once an
+// instruction is found to compute a constant, the constant can replace
+// the register at all of its uses, which may in turn make other
+// instructions constant. Values are computed by `interp::evaluate` at
+// the width of each instruction. An operation that would trap, like a
+// division by zero, is left in place, and so is signed arithmetic whose
+// result overflows the range of its width, which is undefined; only
+// instructions marked `wraps` are unsigned. A folded instruction is
+// dropped once nothing reads its register.
+
+use std::collections::{HashMap, HashSet};
+
+use cfg::Function;
+use gen_ir::{Ir, Op, Operand};
+use interp::evaluate;
+
+pub fn fold(f: &mut Function) {
+    let mut consts: HashMap<isize, i64> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in f.blocks.iter_mut() {
+            for ir in b.ins.iter_mut() {
+                changed |= substitute(ir, &consts);
+                let d = match ir.dst {
+                    Some(d) if !consts.contains_key(&d) => d,
+                    _ => continue,
+                };
+                if let Some(n) = constant(ir) {
+                    consts.insert(d, n);
+                    changed = true;
+                }
+            }
+            if let Some(a) = b.term.arg_mut() {
+                if let Operand::Reg(r) = a {
+                    if let Some(n) = consts.get(r) {
+                        *a = Operand::Imm(*n);
+                    }
+                }
+            }
+        }
+    }
+
+    let used: HashSet<isize> = f
+        .blocks
+        .iter()
+        .flat_map(|b| b.ins.iter().flat_map(|ir| ir.uses()))
+        .collect();
+    for b in f.blocks.iter_mut() {
+        b.ins.retain(|ir| {
+            ir.dst
+                .is_none_or(|d| !consts.contains_key(&d) || used.contains(&d))
+        });
+    }
+}
+
+// Replaces registers known to be constant by immediates, except for the
+// callee of a call, which the backend needs in a register.
+fn substitute(ir: &mut Ir, consts: &HashMap<isize, i64>) -> bool {
+    let skip = if ir.op == Op::Call { 1 } else { 0 };
+    let mut changed = false;
+    for a in ir.args.iter_mut().skip(skip) {
+        if let Operand::Reg(r) = a {
+            if let Some(n) = consts.get(r) {
+                *a = Operand::Imm(*n);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+// the value an instruction computes, if all its operands are constants
+fn constant(ir: &Ir) -> Option<i64> {
+    let mut args = vec![];
+    for a in ir.args.iter() {
+        match a {
+            Operand::Imm(n) => args.push(*n as u64),
+            _ => return None,
+        }
+    }
+    match ir.op {
+        Op::Phi(_) if args.windows(2).all(|w| w[0] == w[1]) => args.first().map(|n| *n as i64),
+        Op::Mov
+        | Op::Add
+        | Op::Sub
+        | Op::Mul
+        | Op::Div
+        | Op::Udiv
+        | Op::Mod
+        | Op::Umod
+        | Op::And
+        | Op::Or
+        | Op::Xor
+        | Op::Shl
+        | Op::Shr
+        | Op::Sar
+        | Op::Mulh
+        | Op::Umulh
+        | Op::Cmp(_)
+        | Op::Neg
+        | Op::Sext
+        | Op::Zext => {
+            if overflows(ir, &args) {
+                return None;
+            }
+            evaluate(&ir.op, &args, ir.size).ok().map(|n| n as i64)
+        }
+        _ => None,
+    }
+}
+
+// Whether an addition, subtraction, multiplication, negation or left
+// shift of constants is undefined: it shifts by the width or more, or it
+// is signed and leaves the range of its width.
+pub fn overflows(ir: &Ir, args: &[u64]) -> bool {
+    let bits = ir.size as u32 * 8;
+    if let (Op::Shl, [_, b]) = (&ir.op, args) {
+        if *b >= bits as u64 {
+            return true;
+        }
+    }
+    if ir.wraps {
+        return false;
+    }
+    let signed = |v: u64| ((v << (64 - bits)) as i64 >> (64 - bits)) as i128;
+    let v = match (&ir.op, args) {
+        (Op::Add, [a, b]) => signed(*a) + signed(*b),
+        (Op::Sub, [a, b]) => signed(*a) - signed(*b),
+        (Op::Mul, [a, b]) => signed(*a) * signed(*b),
+        (Op::Neg, [a]) => -signed(*a),
+        (Op::Shl, [a, b]) => signed(*a) << b,
+        _ => return false,
+    };
+    let min = -(1i128 << (bits - 1));
+    v < min || v >= -min
+}
+
+#[test]
+fn fold_test() {
+    use ir_text::{parse, print};
+    let mut funcs = parse(
+        "def f, 0
+        B0:
+          r0 = mov 2
+          r1 = mov 9
+          r2 = mul r1, 3
+          r3 = add.4 r0, r2
+          r4 = sub.4 r3, -2147483647
+          r5 = sext.4 r4
+          r6 = div r5, 0
+          r7 = call @g, r5, r6
+          wrap r8 = add.4 3000000000, 3000000000
+          r9 = zext.4 r8
+          r10 = cmp.ugt.4 r9, 5
+          r11 = call @g, r7, r10
+          ret r11",
+    )
+    .unwrap();
+    fold(&mut funcs[0]);
+    // the signed 4-byte subtraction overflows and the division would
+    // trap, but the unsigned addition wraps
+    assert_eq!(
+        print(&funcs),
+        "def f, 0
+B0:
+  r4 = sub.4 29, -2147483647
+  r5 = sext.4 r4
+  r6 = div r5, 0
+  r7 = call @g, r5, r6
+  r11 = call @g, r7, 1
+  ret r11
+"
+    );
+}
+
+#[test]
+fn overflows_test() {
+    let ir = |op: Op, size: usize, wraps: bool| {
+        let mut ir = Ir::new(op, Some(0), vec![], size);
+        ir.wraps = wraps;
+        ir
+    };
+    assert!(overflows(&ir(Op::Shl, 4, false), &[1, 31]));
+    assert!(!overflows(&ir(Op::Shl, 4, false), &[1, 30]));
+    assert!(!overflows(&ir(Op::Shl, 4, true), &[1, 31]));
+    assert!(overflows(&ir(Op::Shl, 4, true), &[1, 32]));
+    assert!(!overflows(&ir(Op::Add, 8, false), &[u64::MAX, 1]));
+    assert!(overflows(&ir(Op::Neg, 8, false), &[i64::MIN as u64]));
+    // 3000000000 + 3000000000 wraps to 1705032704 as unsigned int
+    assert!(overflows(&ir(Op::Add, 4, false), &[3000000000, 3000000000]));
+    assert!(!overflows(&ir(Op::Add, 4, true), &[3000000000, 3000000000]));
+    assert!(!overflows(&ir(Op::Sub, 4, true), &[0, 1]));
+}
@@ -1,44 +1,169 @@
-use node::{BinOp, Ctype, Node, NodeBase};
+use cfg::{Block, Function, Param, Term};
+use node::{BinOp, BitField, Ctype, Node, NodeBase, Storage, UnOp, Var};
 
-#[derive(Debug, PartialEq)]
+// Every instruction reads `args` and writes the register `dst`, if any.
+// `size` is the width in bytes the operation works at; see `Ir`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Op {
+    // dst = args[0] op args[1]
     Add,
     Sub,
     Mul,
     Div,
-    Imm,
+    Udiv,
+    Mod,
+    Umod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Sar,
+    // dst = the high half of the double-width product of args[0] and
+    // args[1], signed or unsigned
+    Mulh,
+    Umulh,
+    // dst = 1 if the condition holds for args[0] and args[1], else 0
+    Cmp(Cond),
+    Neg,
+    // dst = args[0], which may be any operand
     Mov,
-    Return,
-    DefFun(String),
-    StoreArg,
+    // dst = the value at address args[0], sign- or zero-extended
     Load,
-    Call(String, Vec<isize>),
+    LoadU,
+    // stores args[1] to address args[0]
+    Store,
+    // dst = args[0] extended from its low `size` bytes
+    Sext,
+    Zext,
+    // push the 8 bytes at address args[0]
+    Push,
+    // add args[0] to rsp
+    SpAdd,
+    // save physical register args[0] around a call, and restore dst
+    Save,
+    Restore,
+    // dst = the result of calling args[0] with the remaining args
+    Call,
+    // args[0] is dead from here on
     Kill,
     Nop,
+    // dst = args[i] when control came from the i-th of the listed
+    // blocks; phis only appear at the start of a block in SSA form
+    Phi(Vec<usize>),
 }
 
-#[derive(Debug, PartialEq)]
+// Comparison conditions. The `U` variants compare as unsigned.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Ult,
+    Ule,
+    Ugt,
+    Uge,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Operand {
+    // a virtual register before allocation, a physical one after it
+    Reg(isize),
+    Imm(i64),
+    // the address rbp-n of a frame slot
+    Slot(isize),
+    // the address of a global variable or function
+    Global(String),
+}
+
+// Arithmetic, comparisons and `Mov` work on the low 4 or 8 bytes of
+// their operands as x86 does, so a 4-byte result is zero-extended. Loads,
+// stores and extensions use `size` as the access or source width.
+#[derive(Debug, PartialEq, Clone)]
 pub struct Ir {
     pub op: Op,
-    pub lhs: isize,
-    pub rhs: isize,
+    pub dst: Option<isize>,
+    pub args: Vec<Operand>,
+    pub size: usize,
+    // set on loads and stores of volatile objects, which must never be
+    // removed, merged or reordered with each other
+    pub volatile: bool,
+    // set on arithmetic on unsigned values, which wraps around where
+    // signed overflow would be undefined
+    pub wraps: bool,
 }
 
 impl Ir {
-    pub fn new(op: Op, lhs: isize, rhs: isize) -> Ir {
+    pub fn new(op: Op, dst: Option<isize>, args: Vec<Operand>, size: usize) -> Ir {
         Ir {
-            op: op,
-            lhs: lhs,
-            rhs: rhs,
+            op,
+            dst,
+            args,
+            size,
+            volatile: false,
+            wraps: false,
         }
     }
+
+    // the registers the instruction reads
+    pub fn uses(&self) -> Vec<isize> {
+        self.args
+            .iter()
+            .filter_map(|a| match a {
+                Operand::Reg(r) => Some(*r),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
+// an object with static storage duration: a file-scope variable or a
+// static local
+#[derive(Debug, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub size: usize,
+    pub align: usize,
+    // little-endian bytes of the initial value; `None` goes to .bss
+    pub init: Option<Vec<u8>>,
+    pub storage: Storage,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    pub funcs: Vec<Function>,
+    pub globals: Vec<Global>,
+}
+
+// number of arguments passed in registers
+pub const NUM_ARGREGS: usize = 6;
+
+// Values live in 64-bit registers and are always kept sign- or
+// zero-extended according to their C type, so arithmetic can be done on
+// full registers and only narrow results need to be re-extended.
 #[derive(Debug, PartialEq)]
 pub struct GenIr {
     regc: isize,
+    // instructions of the current block
     ins: Vec<Ir>,
-    result: Vec<Vec<Ir>>,
+    blocks: Vec<Block>,
+    cur: usize,
+    result: Vec<Function>,
+    globals: Vec<Global>,
+    offsets: Vec<isize>,
+    // current frame size, which grows as temporary slots are allocated
+    frame: isize,
+    // register holding the value a compound assignment starts from
+    loaded: Option<isize>,
+}
+
+impl Default for GenIr {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GenIr {
@@ -46,58 +171,192 @@ impl GenIr {
         GenIr {
             regc: 0,
             ins: vec![],
+            blocks: vec![],
+            cur: 0,
             result: vec![],
+            globals: vec![],
+            offsets: vec![],
+            frame: 0,
+            loaded: None,
         }
     }
 
-    pub fn run(mut self, nodes: &Vec<Node>) -> Result<Vec<Vec<Ir>>, ()> {
+    #[allow(clippy::result_unit_err)]
+    pub fn run(mut self, nodes: &Vec<Node>) -> Result<Program, ()> {
         for node in nodes {
-            self.ins = vec![];
+            match &node.base {
+                NodeBase::DecFun(..) | NodeBase::Statements(_) => continue,
+                NodeBase::VarDef(t, name, init, storage) => {
+                    self.global_var(t, name, init, *storage)?;
+                    continue;
+                }
+                _ => {}
+            }
             self.global_def(node)?;
-            self.result.push(self.ins);
         }
-        Ok(self.result)
+        Ok(Program {
+            funcs: self.result,
+            globals: self.globals,
+        })
     }
 }
 
 impl GenIr {
     fn global_def(&mut self, node: &Node) -> Result<(), ()> {
         match &node.base {
-            NodeBase::DefFun(_, id, args, stmts) => {
-                let id = GenIr::ident(&**id)?;
-                self.ins.push(Ir::new(Op::DefFun(id), -1, -1));
-                self.args_def(&args);
-                let stmtsv = self.statement(&**stmts)?;
+            NodeBase::DefFun(_, id, args, stmts, vars, storage) => {
+                let id = GenIr::ident(id)?;
+                self.frame = self.frame_layout(vars);
+                self.blocks = vec![];
+                self.cur = self.new_block();
+                let params = self.args_def(args)?;
+                self.statement(stmts)?;
+                self.end_block(Term::Ret(None, 0));
+                let mut func = Function {
+                    name: id,
+                    storage: *storage,
+                    params,
+                    frame: (self.frame + 15) / 16 * 16,
+                    used: None,
+                    blocks: std::mem::take(&mut self.blocks),
+                };
+                func.compute_edges();
+                self.result.push(func);
                 Ok(())
             }
             _ => Err(()),
         }
     }
 
-    fn args_def(&mut self, args: &Vec<(Ctype, Node)>) {
-        for i in 0..args.len() {
-            self.ins.push(Ir::new(Op::StoreArg, i as isize, -1));
+    // sema has already folded the initializer into a `Number`
+    fn global_var(
+        &mut self,
+        t: &Ctype,
+        name: &str,
+        init: &Option<Box<Node>>,
+        storage: Storage,
+    ) -> Result<(), ()> {
+        let init = match init {
+            Some(e) => match e.base {
+                NodeBase::Number(n) => Some((n as u64).to_le_bytes()[..t.size()].to_vec()),
+                _ => return Err(()),
+            },
+            None => None,
+        };
+        self.globals.push(Global {
+            name: name.to_string(),
+            size: t.size(),
+            align: t.align(),
+            init,
+            storage,
+        });
+        Ok(())
+    }
+
+    // assign a stack slot to every local variable and return the frame size
+    fn frame_layout(&mut self, vars: &Vec<Var>) -> isize {
+        let mut off = 0;
+        self.offsets = vec![];
+        for var in vars {
+            let size = var.ctype.size() as isize;
+            let align = var.ctype.align() as isize;
+            off = (off + size + align - 1) / align * align;
+            self.offsets.push(off);
         }
+        off
+    }
+
+    // a fresh 8-byte slot in the frame; returns its offset below rbp
+    fn temp_slot(&mut self) -> isize {
+        self.frame = (self.frame + 8 + 7) / 8 * 8;
+        self.frame
+    }
+
+    fn args_def(&mut self, args: &[(Ctype, Node)]) -> Result<Vec<Param>, ()> {
+        let mut params = vec![];
+        for (t, arg) in args.iter() {
+            match arg.base {
+                NodeBase::Lvar(v) => params.push(Param {
+                    offset: self.offsets[v],
+                    size: t.size(),
+                }),
+                _ => return Err(()),
+            }
+        }
+        Ok(params)
     }
 
     fn statement(&mut self, node: &Node) -> Result<(), ()> {
         match &node.base {
+            // code after a return goes to a new, unreachable block
             NodeBase::Return(e) => {
-                let r = self.expr(&*e)?;
-                self.ins.push(Ir::new(Op::Return, r, 0));
-                self.ins.push(Ir::new(Op::Kill, r, -1));
-                return Ok(());
+                let term = match e {
+                    Some(e) => Term::Ret(Some(Operand::Reg(self.expr(e)?)), width(e.ty())),
+                    None => Term::Ret(None, 0),
+                };
+                self.end_block(term);
+                self.cur = self.new_block();
+                Ok(())
             }
             NodeBase::Statements(ndv) => {
                 for nd in ndv {
-                    self.statement(&*nd)?;
+                    self.statement(nd)?;
                 }
-                return Ok(());
+                Ok(())
+            }
+            NodeBase::If(cond, then, els) => {
+                let (x, y) = (self.new_block(), self.new_block());
+                let end = match els {
+                    Some(_) => self.new_block(),
+                    None => y,
+                };
+                self.branch(cond, x, y)?;
+                self.cur = x;
+                self.statement(then)?;
+                self.end_block(Term::Jmp(end));
+                if let Some(els) = els {
+                    self.cur = y;
+                    self.statement(els)?;
+                    self.end_block(Term::Jmp(end));
+                }
+                self.cur = end;
+                Ok(())
+            }
+            NodeBase::While(cond, body) => {
+                let (x, y, z) = (self.new_block(), self.new_block(), self.new_block());
+                self.end_block(Term::Jmp(x));
+                self.cur = x;
+                self.branch(cond, y, z)?;
+                self.cur = y;
+                self.statement(body)?;
+                self.end_block(Term::Jmp(x));
+                self.cur = z;
+                Ok(())
+            }
+            NodeBase::For(init, cond, inc, body) => {
+                if let Some(init) = init {
+                    self.statement(init)?;
+                }
+                let (x, y, z) = (self.new_block(), self.new_block(), self.new_block());
+                self.end_block(Term::Jmp(x));
+                self.cur = x;
+                match cond {
+                    Some(cond) => self.branch(cond, y, z)?,
+                    None => self.end_block(Term::Jmp(y)),
+                }
+                self.cur = y;
+                self.statement(body)?;
+                if let Some(inc) = inc {
+                    self.statement(inc)?;
+                }
+                self.end_block(Term::Jmp(x));
+                self.cur = z;
+                Ok(())
             }
             _ => {
-                let r = self.expr(&node)?;
-                self.ins.push(Ir::new(Op::Kill, r, -1));
-                return Ok(());
+                let r = self.expr(node)?;
+                self.kill(r);
+                Ok(())
             }
         }
     }
@@ -106,34 +365,300 @@ impl GenIr {
         match &node.base {
             NodeBase::Number(n) => {
                 let current = self.regc_step();
-                self.ins.push(Ir::new(Op::Imm, current, *n as isize));
-                return Ok(current);
+                self.mov(current, Operand::Imm(*n as i64));
+                Ok(current)
             }
-            NodeBase::Ident(s) => {
-                let r = self.regc_step();
-                self.ins.push(Ir::new(Op::Load, r, -1));
-                return Ok(r);
+            NodeBase::Lvar(_) | NodeBase::Gvar(_) | NodeBase::Deref(_) | NodeBase::Member(..) => {
+                let addr = self.lval(node)?;
+                let r = match addr {
+                    Operand::Reg(r) => r,
+                    _ => self.regc_step(),
+                };
+                match node.bit_field() {
+                    Some(bits) => self.load_bits(node.ty(), bits, r, &addr),
+                    None => self.load(node.ty(), r, &addr),
+                }
+                Ok(r)
+            }
+            NodeBase::Addr(e) => {
+                let addr = self.lval(e)?;
+                Ok(self.in_reg(addr))
             }
+            // The result takes over the register of the first argument or
+            // of the function pointer, which are dead after the call.
             NodeBase::Call(s, args) => {
-                let current = self.regc_step();
-                let args = self.call_args(&args)?;
-                self.ins
-                    .push(Ir::new(Op::Call((*s).to_string(), args), current, -1));
-                return Ok(current);
+                let (args, slots) = self.call_args(args)?;
+                let current = match args.first() {
+                    Some(r) => *r,
+                    None => self.regc_step(),
+                };
+                self.call(Operand::Global((*s).to_string()), &args, &slots, current);
+                for arg in args.into_iter().skip(1) {
+                    self.kill(arg);
+                }
+                self.normalize(node.ty(), current);
+                Ok(current)
+            }
+            NodeBase::CallPtr(f, args) => {
+                let f = self.expr(f)?;
+                let (args, slots) = self.call_args(args)?;
+                self.call(Operand::Reg(f), &args, &slots, f);
+                for arg in args {
+                    self.kill(arg);
+                }
+                self.normalize(node.ty(), f);
+                Ok(f)
+            }
+            NodeBase::Assign(lhs, rhs) => {
+                let rhs = self.expr(rhs)?;
+                let addr = self.lval(lhs)?;
+                self.store(lhs, addr, rhs);
+                Ok(rhs)
+            }
+            // The target's address is computed once; its current value is
+            // handed over to the single `Loaded` leaf of the new value.
+            NodeBase::OpAssign(_, lhs, value) => {
+                let addr = self.lval(lhs)?;
+                let cur = self.regc_step();
+                match lhs.bit_field() {
+                    Some(bits) => self.load_bits(lhs.ty(), bits, cur, &addr),
+                    None => self.load(lhs.ty(), cur, &addr),
+                }
+                let outer = self.loaded.replace(cur);
+                let v = self.expr(value)?;
+                self.loaded = outer;
+                self.store(lhs, addr, v);
+                Ok(v)
+            }
+            NodeBase::Loaded => self.loaded.take().ok_or(()),
+            NodeBase::Cast(e) => {
+                let r = self.expr(e)?;
+                self.convert(e.ty(), node.ty(), r);
+                Ok(r)
             }
-            NodeBase::BinaryOp(op, lhs, rhs) => {
-                return self.binary_op(op, &*lhs, &*rhs);
+            NodeBase::UnaryOp(UnOp::Neg, e) => {
+                let r = self.expr(e)?;
+                let w = width(node.ty());
+                self.emit(Op::Neg, Some(r), vec![Operand::Reg(r)], w).wraps =
+                    node.ty().is_unsigned();
+                self.normalize(node.ty(), r);
+                Ok(r)
             }
-            _ => return Err(()),
+            NodeBase::BinaryOp(op, lhs, rhs) => self.binary_op(node.ty(), op, lhs, rhs),
+            _ => Err(()),
         }
     }
 
-    fn call_args(&mut self, args: &Vec<Node>) -> Result<Vec<isize>, ()> {
-        let mut v = vec![];
-        for arg in args {
-            v.push(self.expr(&arg)?);
+    // evaluates a condition and ends the block with a branch on it
+    fn branch(&mut self, cond: &Node, then: usize, els: usize) -> Result<(), ()> {
+        let r = self.expr(cond)?;
+        self.end_block(Term::Br(Operand::Reg(r), then, els));
+        Ok(())
+    }
+
+    // reserves a block to be filled in once it becomes current
+    fn new_block(&mut self) -> usize {
+        self.blocks.push(Block::new(Term::Ret(None, 0)));
+        self.blocks.len() - 1
+    }
+
+    // ends the current block; another one must be made current before
+    // more code is generated
+    fn end_block(&mut self, term: Term) {
+        let b = &mut self.blocks[self.cur];
+        b.ins = std::mem::take(&mut self.ins);
+        b.term = term;
+    }
+
+    fn emit(&mut self, op: Op, dst: Option<isize>, args: Vec<Operand>, size: usize) -> &mut Ir {
+        self.ins.push(Ir::new(op, dst, args, size));
+        self.ins.last_mut().unwrap()
+    }
+
+    fn mov(&mut self, dst: isize, src: Operand) {
+        self.emit(Op::Mov, Some(dst), vec![src], 8);
+    }
+
+    fn kill(&mut self, r: isize) {
+        self.emit(Op::Kill, None, vec![Operand::Reg(r)], 8);
+    }
+
+    // r = r op n
+    fn op_imm(&mut self, op: Op, r: isize, n: i64) {
+        self.emit(op, Some(r), vec![Operand::Reg(r), Operand::Imm(n)], 8);
+    }
+
+    // The address of an object. Variables are addressed directly by
+    // their slot or symbol, so a local whose address is never taken is
+    // only ever loaded and stored.
+    fn lval(&mut self, node: &Node) -> Result<Operand, ()> {
+        match &node.base {
+            NodeBase::Lvar(v) => Ok(Operand::Slot(self.offsets[*v])),
+            NodeBase::Deref(e) => Ok(Operand::Reg(self.expr(e)?)),
+            // for a bit-field, the address of its storage unit
+            NodeBase::Member(e, name) => {
+                let addr = self.lval(e)?;
+                let r = self.in_reg(addr);
+                let offset = e.ty().member(name).ok_or(())?.offset;
+                if offset > 0 {
+                    self.op_imm(Op::Add, r, offset as i64);
+                }
+                Ok(Operand::Reg(r))
+            }
+            NodeBase::Gvar(name) | NodeBase::Func(name) => Ok(Operand::Global(name.to_string())),
+            _ => Err(()),
+        }
+    }
+
+    fn in_reg(&mut self, o: Operand) -> isize {
+        match o {
+            Operand::Reg(r) => r,
+            o => {
+                let r = self.regc_step();
+                self.mov(r, o);
+                r
+            }
+        }
+    }
+
+    fn load(&mut self, t: &Ctype, dst: isize, addr: &Operand) {
+        // a struct is used through its address
+        if let Ctype::Struct(_) = t.unqual() {
+            if *addr != Operand::Reg(dst) {
+                self.mov(dst, addr.clone());
+            }
+            return;
+        }
+        let op = if t.is_unsigned() { Op::LoadU } else { Op::Load };
+        let volatile = t.is_volatile();
+        self.emit(op, Some(dst), vec![addr.clone()], t.size())
+            .volatile = volatile;
+    }
+
+    // stores `v` to the object `lhs` at `addr`, consuming `addr`
+    fn store(&mut self, lhs: &Node, addr: Operand, v: isize) {
+        let t = lhs.ty();
+        match lhs.bit_field() {
+            Some(bits) => self.store_bits(t, bits, &addr, v),
+            None => {
+                let args = vec![addr.clone(), Operand::Reg(v)];
+                self.emit(Op::Store, None, args, t.size()).volatile = t.is_volatile();
+            }
+        }
+        if let Operand::Reg(r) = addr {
+            self.kill(r);
+        }
+    }
+
+    // Loads the storage unit and shifts the field to the top of the
+    // register, then back down to bit 0, filling with its sign or zeros.
+    fn load_bits(&mut self, t: &Ctype, bits: BitField, r: isize, addr: &Operand) {
+        self.emit(Op::LoadU, Some(r), vec![addr.clone()], t.size())
+            .volatile = t.is_volatile();
+        self.extract(t, 64 - bits.offset - bits.width, bits.width, r);
+    }
+
+    fn extract(&mut self, t: &Ctype, shl: usize, width: usize, r: isize) {
+        self.shift(Op::Shl, r, shl);
+        let op = if t.is_unsigned() { Op::Shr } else { Op::Sar };
+        self.shift(op, r, 64 - width);
+    }
+
+    // Read-modify-write of the storage unit. `v` is truncated to the
+    // field so it holds the value of the assignment expression.
+    fn store_bits(&mut self, t: &Ctype, bits: BitField, addr: &Operand, v: isize) {
+        let size = t.size();
+        self.extract(t, 64 - bits.width, bits.width, v);
+
+        let unit = self.regc_step();
+        self.emit(Op::LoadU, Some(unit), vec![addr.clone()], size)
+            .volatile = t.is_volatile();
+        let mask = (u64::MAX >> (64 - bits.width)) << bits.offset;
+        self.op_imm(Op::And, unit, !mask as i64);
+
+        let field = self.regc_step();
+        self.mov(field, Operand::Reg(v));
+        self.shift(Op::Shl, field, 64 - bits.width);
+        self.shift(Op::Shr, field, 64 - bits.width - bits.offset);
+        let args = vec![Operand::Reg(unit), Operand::Reg(field)];
+        self.emit(Op::Or, Some(unit), args, 8);
+        self.kill(field);
+
+        let args = vec![addr.clone(), Operand::Reg(unit)];
+        self.emit(Op::Store, None, args, size).volatile = t.is_volatile();
+        self.kill(unit);
+    }
+
+    fn shift(&mut self, op: Op, r: isize, n: usize) {
+        if n > 0 {
+            self.op_imm(op, r, n as i64);
+        }
+    }
+
+    // re-extend a value whose type is narrower than a register
+    fn normalize(&mut self, t: &Ctype, r: isize) {
+        let size = t.size();
+        if size >= 8 || !t.is_scalar() {
+            return;
+        }
+        let op = if t.is_unsigned() { Op::Zext } else { Op::Sext };
+        self.emit(op, Some(r), vec![Operand::Reg(r)], size);
+    }
+
+    fn convert(&mut self, from: &Ctype, to: &Ctype, r: isize) {
+        if from.unqual() == to.unqual() || to.size() >= 8 || !to.is_scalar() {
+            return;
+        }
+        // any nonzero scalar becomes 1
+        if *to.unqual() == Ctype::Bool {
+            self.op_imm(Op::Cmp(Cond::Ne), r, 0);
+            return;
+        }
+        // widening keeps the value unless a signed value becomes unsigned
+        if from.size() < to.size() && (from.is_unsigned() || !to.is_unsigned()) {
+            return;
+        }
+        self.normalize(to, r);
+    }
+
+    // Arguments past the sixth are evaluated first and spilled to
+    // temporary slots, so no more than six values are ever live, and
+    // pushed right before the call. Returns the register arguments and
+    // the slots.
+    fn call_args(&mut self, args: &[Node]) -> Result<(Vec<isize>, Vec<isize>), ()> {
+        let mut regs = vec![];
+        let mut slots = vec![];
+        for arg in args.iter().skip(NUM_ARGREGS) {
+            let r = self.expr(arg)?;
+            let off = self.temp_slot();
+            let store = vec![Operand::Slot(off), Operand::Reg(r)];
+            self.emit(Op::Store, None, store, 8);
+            self.kill(r);
+            slots.push(off);
+        }
+        for arg in args.iter().take(NUM_ARGREGS) {
+            regs.push(self.expr(arg)?);
+        }
+        Ok((regs, slots))
+    }
+
+    // Pushes stack arguments right to left, padding first so rsp is
+    // 16-byte aligned at the call, and pops them afterwards.
+    fn call(&mut self, f: Operand, args: &[isize], slots: &[isize], dst: isize) {
+        let pad = slots.len() as i64 % 2 * 8;
+        if pad > 0 {
+            self.emit(Op::SpAdd, None, vec![Operand::Imm(-pad)], 8);
+        }
+        for off in slots.iter().rev() {
+            self.emit(Op::Push, None, vec![Operand::Slot(*off)], 8);
+        }
+        let mut v = vec![f];
+        v.extend(args.iter().map(|r| Operand::Reg(*r)));
+        self.emit(Op::Call, Some(dst), v, 8);
+        let pop = slots.len() as i64 * 8 + pad;
+        if pop > 0 {
+            self.emit(Op::SpAdd, None, vec![Operand::Imm(pop)], 8);
         }
-        Ok(v)
     }
 
     fn ident(node: &Node) -> Result<String, ()> {
@@ -143,19 +668,48 @@ impl GenIr {
         }
     }
 
-    fn binary_op(&mut self, op: &BinOp, lhs: &Node, rhs: &Node) -> Result<isize, ()> {
-        let lhs: isize = self.expr(lhs)?;
-        let rhs: isize = self.expr(rhs)?;
+    fn binary_op(&mut self, t: &Ctype, op: &BinOp, lhs: &Node, rhs: &Node) -> Result<isize, ()> {
+        let unsigned = lhs.ty().is_unsigned() || lhs.ty().is_pointer();
+        let l: isize = self.expr(lhs)?;
+        let r: isize = self.expr(rhs)?;
         let op = match op {
             BinOp::Add => Op::Add,
             BinOp::Sub => Op::Sub,
             BinOp::Mul => Op::Mul,
+            BinOp::Div if unsigned => Op::Udiv,
             BinOp::Div => Op::Div,
+            BinOp::Mod if unsigned => Op::Umod,
+            BinOp::Mod => Op::Mod,
+            BinOp::And => Op::And,
+            BinOp::Or => Op::Or,
+            BinOp::Xor => Op::Xor,
+            BinOp::Shl => Op::Shl,
+            // arithmetic shift for signed, logical for unsigned values
+            BinOp::Shr if unsigned => Op::Shr,
+            BinOp::Shr => Op::Sar,
+            BinOp::Lt if unsigned => Op::Cmp(Cond::Ult),
+            BinOp::Le if unsigned => Op::Cmp(Cond::Ule),
+            BinOp::Gt if unsigned => Op::Cmp(Cond::Ugt),
+            BinOp::Ge if unsigned => Op::Cmp(Cond::Uge),
+            BinOp::Lt => Op::Cmp(Cond::Lt),
+            BinOp::Le => Op::Cmp(Cond::Le),
+            BinOp::Gt => Op::Cmp(Cond::Gt),
+            BinOp::Ge => Op::Cmp(Cond::Ge),
+            BinOp::Eq => Op::Cmp(Cond::Eq),
+            BinOp::Ne => Op::Cmp(Cond::Ne),
         };
+        let is_cmp = matches!(op, Op::Cmp(_));
+        let wraps = t.is_unsigned() && matches!(op, Op::Add | Op::Sub | Op::Mul | Op::Shl);
 
-        self.ins.push(Ir::new(op, lhs, rhs));
-        self.ins.push(Ir::new(Op::Kill, rhs, -1));
-        Ok(lhs)
+        // a comparison works at the width of its operands
+        let w = if is_cmp { width(lhs.ty()) } else { width(t) };
+        self.emit(op, Some(l), vec![Operand::Reg(l), Operand::Reg(r)], w)
+            .wraps = wraps;
+        self.kill(r);
+        if !is_cmp {
+            self.normalize(t, l);
+        }
+        Ok(l)
     }
 
     fn regc_step(&mut self) -> isize {
@@ -164,3 +718,13 @@ impl GenIr {
         c
     }
 }
+
+// Width of arithmetic on a value of type `t`. Narrower operands have
+// been promoted to int, whose 4-byte result is extended afterwards.
+fn width(t: &Ctype) -> usize {
+    if t.size() <= 4 {
+        4
+    } else {
+        8
+    }
+}
@@ -1,6 +1,8 @@
 // generate x86 assembly from IR
 
-use gen_ir::{Ir, Op};
+use cfg::{Function, Term};
+use gen_ir::{Cond, Global, Ir, Op, Operand, Program};
+use regalloc::CALLER_SAVED;
 use std::fmt;
 
 struct Reg {
@@ -24,11 +26,23 @@ macro_rules! new_reg {
 pub struct X86 {
     regs: Vec<Reg>,
     regs8: Vec<Reg>,
+    regs16: Vec<Reg>,
     regs32: Vec<Reg>,
     argregs: Vec<Reg>,
     argregs8: Vec<Reg>,
+    argregs16: Vec<Reg>,
     argregs32: Vec<Reg>,
     nlabel: usize,
+    // callee-saved registers of the current function and their slots
+    saved: Vec<(isize, isize)>,
+    // slots for caller-saved registers live across calls
+    spill: Vec<isize>,
+}
+
+impl Default for X86 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl X86 {
@@ -42,6 +56,10 @@ impl X86 {
                 new_reg!("r13"),
                 new_reg!("r14"),
                 new_reg!("r15"),
+                new_reg!("rdi"),
+                new_reg!("rsi"),
+                new_reg!("r8"),
+                new_reg!("r9"),
             ],
             regs8: vec![
                 new_reg!("r10b"),
@@ -51,6 +69,23 @@ impl X86 {
                 new_reg!("r13b"),
                 new_reg!("r14b"),
                 new_reg!("r15b"),
+                new_reg!("dil"),
+                new_reg!("sil"),
+                new_reg!("r8b"),
+                new_reg!("r9b"),
+            ],
+            regs16: vec![
+                new_reg!("r10w"),
+                new_reg!("r11w"),
+                new_reg!("bx"),
+                new_reg!("r12w"),
+                new_reg!("r13w"),
+                new_reg!("r14w"),
+                new_reg!("r15w"),
+                new_reg!("di"),
+                new_reg!("si"),
+                new_reg!("r8w"),
+                new_reg!("r9w"),
             ],
             regs32: vec![
                 new_reg!("r10d"),
@@ -60,6 +95,10 @@ impl X86 {
                 new_reg!("r13d"),
                 new_reg!("r14d"),
                 new_reg!("r15d"),
+                new_reg!("edi"),
+                new_reg!("esi"),
+                new_reg!("r8d"),
+                new_reg!("r9d"),
             ],
             argregs: vec![
                 new_reg!("rdi"),
@@ -73,9 +112,18 @@ impl X86 {
                 new_reg!("dil"),
                 new_reg!("sil"),
                 new_reg!("dl"),
+                new_reg!("cl"),
                 new_reg!("r8b"),
                 new_reg!("r9b"),
             ],
+            argregs16: vec![
+                new_reg!("di"),
+                new_reg!("si"),
+                new_reg!("dx"),
+                new_reg!("cx"),
+                new_reg!("r8w"),
+                new_reg!("r9w"),
+            ],
             argregs32: vec![
                 new_reg!("edi"),
                 new_reg!("esi"),
@@ -85,86 +133,509 @@ impl X86 {
                 new_reg!("r9d"),
             ],
             nlabel: 1,
+            saved: vec![],
+            spill: vec![],
         }
     }
 }
 
 impl X86 {
-    pub fn emit(&mut self, irvv: &Vec<Vec<Ir>>) {
+    pub fn emit(&mut self, prog: &Program) {
         self.nlabel += 1;
 
         println!(".intel_syntax noprefix");
-        println!(".global main");
 
-        for irv in irvv.iter() {
-            self.emit_ir(&irv);
+        for g in prog.globals.iter() {
+            self.emit_global(g);
+        }
+
+        println!(".text");
+        for func in prog.funcs.iter() {
+            self.emit_func(func);
         }
     }
 
-    fn emit_ir(&mut self, irv: &Vec<Ir>) {
-        for ir in irv {
-            match &ir.op {
-                Op::DefFun(s) => {
-                    println!("{}:", s);
-                    println!("  push rbp");
-                    println!("  mov rbp, rsp");
-                }
-                Op::Call(s, args) => {
-                    for arg in args {
-                        println!("  mov {}, {}", self.argreg(*arg, 4), self.reg(*arg, 4));
-                    }
+    fn emit_global(&mut self, g: &Global) {
+        match &g.init {
+            Some(_) => println!(".data"),
+            None => println!(".bss"),
+        }
+        if !g.storage.is_static {
+            println!(".globl {}", g.name);
+        }
+        println!(".align {}", g.align);
+        println!(".type {}, @object", g.name);
+        println!(".size {}, {}", g.name, g.size);
+        println!("{}:", g.name);
+        match &g.init {
+            Some(bytes) => {
+                let bytes: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+                println!("  .byte {}", bytes.join(", "));
+            }
+            None => println!("  .zero {}", g.size),
+        }
+    }
 
-                    println!("  mov rax, 0");
-                    println!("  call {}", s);
-                    println!("  mov {}, eax", self.reg(ir.lhs, 4));
-                }
-                Op::Imm => {
-                    println!("  mov {}, {}", self.reg(ir.lhs, 4), ir.rhs);
+    // Blocks are labeled `.L<function>.<block>` and laid out in order, so
+    // a jump to the next block falls through.
+    fn emit_func(&mut self, func: &Function) {
+        let name = &func.name;
+        if !func.storage.is_static {
+            println!(".globl {}", name);
+        }
+        println!(".type {}, @function", name);
+        println!("{}:", name);
+        println!("  push rbp");
+        println!("  mov rbp, rsp");
+        let size = self.frame(func);
+        if size > 0 {
+            println!("  sub rsp, {}", size);
+        }
+        for (r, off) in self.saved.iter() {
+            println!("  mov qword ptr [rbp-{}], {}", off, self.reg(*r, 8));
+        }
+        for (i, p) in func.params.iter().enumerate() {
+            if i >= self.argregs.len() {
+                // passed on the stack above the return address
+                let off = 16 + (i - self.argregs.len()) * 8;
+                println!("  mov rax, [rbp+{}]", off);
+                println!(
+                    "  mov {} ptr [rbp-{}], {}",
+                    X86::ptr(p.size),
+                    p.offset,
+                    X86::scratch('a', p.size)
+                );
+            } else {
+                println!(
+                    "  mov {} ptr [rbp-{}], {}",
+                    X86::ptr(p.size),
+                    p.offset,
+                    self.argreg(i as isize, p.size)
+                );
+            }
+        }
+
+        let last = func.blocks.len();
+        for (i, b) in func.blocks.iter().enumerate() {
+            println!(".L{}.{}:", name, i);
+            for ir in b.ins.iter() {
+                self.emit_ir(ir);
+            }
+            match b.term {
+                Term::Jmp(to) if to == i + 1 => {}
+                Term::Jmp(to) => println!("  jmp .L{}.{}", name, to),
+                Term::Br(Operand::Imm(n), then, els) => {
+                    let to = if n != 0 { then } else { els };
+                    if to != i + 1 {
+                        println!("  jmp .L{}.{}", name, to);
+                    }
                 }
-                Op::StoreArg => {
-                    println!(
-                        "  mov dword ptr [rbp-{}], {}",
-                        (ir.lhs + 1) * 4,
-                        self.argreg(ir.lhs, 4)
-                    );
+                Term::Br(ref a, then, els) => {
+                    println!("  cmp {}, 0", self.value(a, 8));
+                    println!("  je .L{}.{}", name, els);
+                    if then != i + 1 {
+                        println!("  jmp .L{}.{}", name, then);
+                    }
                 }
-                Op::Load => {
-                    println!(
-                        "  mov {}, dword ptr [rbp-{}]",
-                        self.reg(ir.lhs, 4),
-                        (ir.lhs + 1) * 4
-                    );
+                Term::Ret(ref v, size) => {
+                    if let Some(v) = v {
+                        self.mov("rax", &X86::scratch('a', size), v, size);
+                    }
+                    if i + 1 != last {
+                        println!("  jmp .Lreturn.{}", name);
+                    }
                 }
-                Op::Mov => {
-                    println!("  mov {}, {}", self.reg(ir.lhs, 4), self.reg(ir.rhs, 4));
+                Term::Unreachable => println!("  ud2"),
+            }
+        }
+        println!(".Lreturn.{}:", name);
+        for (r, off) in self.saved.iter() {
+            println!("  mov {}, qword ptr [rbp-{}]", self.reg(*r, 8), off);
+        }
+        println!("  mov rsp, rbp");
+        println!("  pop rbp");
+        println!("  ret");
+        println!(".size {}, .-{}", name, name);
+    }
+
+    // Two-operand x86 instructions take the destination as their first
+    // operand, so `dst = op a, b` becomes `mov dst, a; op dst, b`. rax,
+    // rcx and rdx are never allocated and serve as scratch registers.
+    fn emit_ir(&mut self, ir: &Ir) {
+        let size = ir.size;
+        let d = ir.dst.unwrap_or(-1);
+        match &ir.op {
+            Op::Save => {
+                let r = X86::reg_num(&ir.args[0]);
+                let off = self.spill[r as usize];
+                println!("  mov qword ptr [rbp-{}], {}", off, self.reg(r, 8));
+            }
+            Op::Restore => {
+                let off = self.spill[d as usize];
+                println!("  mov {}, qword ptr [rbp-{}]", self.reg(d, 8), off);
+            }
+            Op::Call => {
+                self.call_args(ir);
+                println!("  mov rax, 0");
+                match &ir.args[0] {
+                    Operand::Global(name) => println!("  call {}", name),
+                    Operand::Reg(r) if self.call_args_clobber(ir, *r) => println!("  call r11"),
+                    f => println!("  call {}", self.value(f, 8)),
                 }
-                Op::Return => {
-                    println!("  mov eax, {}", self.reg(ir.lhs, 4));
-                    //println!("  jmp {}");
+                println!("  mov {}, rax", self.reg(d, 8));
+            }
+            // a 4-byte move clears the upper half even within a register
+            Op::Mov if size == 4 && ir.args[0] == Operand::Reg(d) => {
+                println!("  mov {}, {}", self.reg(d, 4), self.reg(d, 4));
+            }
+            Op::Mov => {
+                let (to64, to) = (self.reg(d, 8), self.reg(d, size));
+                self.mov(&to64, &to, &ir.args[0], size);
+            }
+            Op::Push => {
+                println!("  push qword ptr {}", self.mem(&ir.args[0]));
+            }
+            Op::SpAdd => match ir.args[0] {
+                Operand::Imm(n) if n < 0 => println!("  sub rsp, {}", -n),
+                Operand::Imm(n) => println!("  add rsp, {}", n),
+                _ => panic!("spadd needs an immediate"),
+            },
+            Op::Load => {
+                let ins = match size {
+                    1 | 2 => "movsx",
+                    4 => "movsxd",
+                    _ => "mov",
+                };
+                println!(
+                    "  {} {}, {} ptr {}",
+                    ins,
+                    self.reg(d, 8),
+                    X86::ptr(size),
+                    self.mem(&ir.args[0])
+                );
+            }
+            Op::LoadU => {
+                let (ins, dst) = match size {
+                    1 | 2 => ("movzx", 4),
+                    4 => ("mov", 4),
+                    _ => ("mov", 8),
+                };
+                println!(
+                    "  {} {}, {} ptr {}",
+                    ins,
+                    self.reg(d, dst),
+                    X86::ptr(size),
+                    self.mem(&ir.args[0])
+                );
+            }
+            Op::Store => {
+                let v = self.src(&ir.args[1], size, 'a');
+                println!(
+                    "  mov {} ptr {}, {}",
+                    X86::ptr(size),
+                    self.mem(&ir.args[0]),
+                    v
+                );
+            }
+            Op::Sext => {
+                let ins = if size == 4 { "movsxd" } else { "movsx" };
+                let a = self.in_reg(&ir.args[0], size);
+                println!("  {} {}, {}", ins, self.reg(d, 8), a);
+            }
+            Op::Zext => {
+                let ins = if size == 4 { "mov" } else { "movzx" };
+                let a = self.in_reg(&ir.args[0], size);
+                println!("  {} {}, {}", ins, self.reg(d, 4), a);
+            }
+            Op::Add => self.two_address("add", d, &ir.args, size, true),
+            Op::Sub => self.two_address("sub", d, &ir.args, size, false),
+            Op::Mul => match (&ir.args[0], &ir.args[1]) {
+                (a, Operand::Imm(n)) | (Operand::Imm(n), a) if size >= 4 => {
+                    self.mul_imm(d, a, *n, size)
                 }
-                Op::Add => {
-                    println!("  add {}, {}", self.reg(ir.lhs, 4), self.reg(ir.rhs, 4));
+                _ => self.two_address("imul", d, &ir.args, size, true),
+            },
+            Op::And => self.two_address("and", d, &ir.args, size, true),
+            Op::Or => self.two_address("or", d, &ir.args, size, true),
+            Op::Xor => self.two_address("xor", d, &ir.args, size, true),
+            Op::Div | Op::Udiv | Op::Mod | Op::Umod => {
+                let signed = ir.op == Op::Div || ir.op == Op::Mod;
+                let rax = X86::scratch('a', size);
+                self.mov("rax", &rax, &ir.args[0], size);
+                let divisor = match &ir.args[1] {
+                    Operand::Reg(r) => self.reg(*r, size),
+                    b => {
+                        let rcx = X86::scratch('c', size);
+                        self.mov("rcx", &rcx, b, size);
+                        rcx
+                    }
+                };
+                if signed {
+                    println!("  {}", if size == 4 { "cdq" } else { "cqo" });
+                    println!("  idiv {}", divisor);
+                } else {
+                    println!("  xor edx, edx");
+                    println!("  div {}", divisor);
                 }
-                Op::Sub => {
-                    println!("  sub {}, {}", self.reg(ir.lhs, 4), self.reg(ir.rhs, 4));
+                let res = if ir.op == Op::Div || ir.op == Op::Udiv {
+                    rax
+                } else {
+                    X86::scratch('d', size)
+                };
+                println!("  mov {}, {}", self.reg(d, size), res);
+            }
+            Op::Mulh | Op::Umulh => {
+                let rax = X86::scratch('a', size);
+                self.mov("rax", &rax, &ir.args[0], size);
+                let factor = match &ir.args[1] {
+                    Operand::Reg(r) => self.reg(*r, size),
+                    b => {
+                        let rcx = X86::scratch('c', size);
+                        self.mov("rcx", &rcx, b, size);
+                        rcx
+                    }
+                };
+                let ins = if ir.op == Op::Mulh { "imul" } else { "mul" };
+                println!("  {} {}", ins, factor);
+                println!("  mov {}, {}", self.reg(d, size), X86::scratch('d', size));
+            }
+            Op::Shl | Op::Shr | Op::Sar => {
+                let ins = match ir.op {
+                    Op::Shl => "shl",
+                    Op::Shr => "shr",
+                    _ => "sar",
+                };
+                let count = match &ir.args[1] {
+                    Operand::Imm(n) => (n & (size as i64 * 8 - 1)).to_string(),
+                    // rcx only carries arguments right before a call
+                    b => {
+                        self.mov("rcx", "rcx", b, 8);
+                        "cl".to_string()
+                    }
+                };
+                let (to64, to) = (self.reg(d, 8), self.reg(d, size));
+                self.mov(&to64, &to, &ir.args[0], size);
+                println!("  {} {}, {}", ins, to, count);
+            }
+            Op::Neg => {
+                let (to64, to) = (self.reg(d, 8), self.reg(d, size));
+                self.mov(&to64, &to, &ir.args[0], size);
+                println!("  neg {}", to);
+            }
+            Op::Cmp(cond) => {
+                let a = self.in_reg(&ir.args[0], size);
+                let b = self.src(&ir.args[1], size, 'd');
+                println!("  cmp {}, {}", a, b);
+                println!("  set{} {}", X86::cc(*cond), self.reg(d, 1));
+                println!("  movzx {}, {}", self.reg(d, 4), self.reg(d, 1));
+            }
+            Op::Nop => {}
+            Op::Kill => panic!("kill after register allocation"),
+            Op::Phi(_) => panic!("phi after register allocation"),
+        }
+    }
+
+    // Multiplies by a constant that is 1, 3, 5 or 9 times a power of two
+    // with a `lea` and a shift, and by any other with `imul`.
+    fn mul_imm(&self, d: isize, a: &Operand, n: i64, size: usize) {
+        let shift = n.trailing_zeros();
+        let odd = if n > 0 { n >> shift } else { 0 };
+        if !matches!(odd, 1 | 3 | 5 | 9) {
+            let args = [a.clone(), Operand::Imm(n)];
+            return self.two_address("imul", d, &args, size, true);
+        }
+        let (to64, to) = (self.reg(d, 8), self.reg(d, size));
+        if odd == 1 {
+            self.mov(&to64, &to, a, size);
+            // a 4-byte result clears the upper half
+            if shift == 0 && size == 4 && *a == Operand::Reg(d) {
+                println!("  mov {}, {}", to, to);
+            }
+        } else {
+            let a = self.in_reg(a, 8);
+            println!("  lea {}, [{}+{}*{}]", to, a, a, odd - 1);
+        }
+        if shift > 0 {
+            println!("  shl {}, {}", to, shift);
+        }
+    }
+
+    // Moves the arguments of a call to their registers. Some may already
+    // be in the register of another argument, so the moves between
+    // registers are done as a parallel copy, breaking cycles through rax,
+    // before the others. A callee in one of the registers written is
+    // moved to r11, which the call clobbers anyway.
+    fn call_args(&self, ir: &Ir) {
+        let mut moves = vec![];
+        for (i, arg) in ir.args.iter().enumerate().skip(1) {
+            let to = self.argreg(i as isize - 1, 8);
+            if let Operand::Reg(r) = arg {
+                moves.push((to, self.reg(*r, 8)));
+            }
+        }
+        if let Operand::Reg(r) = ir.args[0] {
+            if self.call_args_clobber(ir, r) {
+                moves.push(("r11".to_string(), self.reg(r, 8)));
+            }
+        }
+        moves.retain(|(to, from)| to != from);
+        while !moves.is_empty() {
+            let free = moves
+                .iter()
+                .position(|(to, _)| moves.iter().all(|(_, from)| from != to));
+            match free {
+                Some(i) => {
+                    let (to, from) = moves.remove(i);
+                    println!("  mov {}, {}", to, from);
                 }
-                Op::Mul => {
-                    println!("  mov eax, {}", self.reg(ir.rhs, 4));
-                    println!("  imul {}", self.reg(ir.lhs, 4));
-                    println!("  mov {}, eax", self.reg(ir.lhs, 4));
+                None => {
+                    let to = moves[0].0.clone();
+                    println!("  mov rax, {}", to);
+                    for m in moves.iter_mut().filter(|m| m.1 == to) {
+                        m.1 = "rax".to_string();
+                    }
                 }
-                Op::Div => {
-                    println!("  mov eax, {}", self.reg(ir.lhs, 4));
-                    println!("  cqo");
-                    println!("  div {}", self.reg(ir.rhs, 4));
-                    println!("  mov {}, eax", self.reg(ir.lhs, 4));
+            }
+        }
+        for (i, arg) in ir.args.iter().enumerate().skip(1) {
+            if let Operand::Reg(_) = arg {
+                continue;
+            }
+            let to = self.argreg(i as isize - 1, 8);
+            self.mov(&to, &to, arg, 8);
+        }
+    }
+
+    // whether passing the arguments of a call overwrites register `r`
+    fn call_args_clobber(&self, ir: &Ir, r: isize) -> bool {
+        let name = self.reg(r, 8);
+        (1..ir.args.len()).any(|i| self.argreg(i as isize - 1, 8) == name)
+    }
+
+    fn two_address(&self, ins: &str, d: isize, args: &[Operand], size: usize, commutes: bool) {
+        let (a, b) = (&args[0], &args[1]);
+        let (to64, to) = (self.reg(d, 8), self.reg(d, size));
+        if *b == Operand::Reg(d) && *a != Operand::Reg(d) {
+            if commutes {
+                let a = self.src(a, size, 'a');
+                println!("  {} {}, {}", ins, to, a);
+            } else {
+                let rax = X86::scratch('a', size);
+                self.mov("rax", &rax, a, size);
+                println!("  {} {}, {}", ins, rax, to);
+                println!("  mov {}, {}", to, rax);
+            }
+            return;
+        }
+        self.mov(&to64, &to, a, size);
+        let b = self.src(b, size, 'a');
+        println!("  {} {}, {}", ins, to, b);
+    }
+
+    // Moves an operand to the register named `to`, which is `to64` at
+    // its full width. Nothing is emitted if it is already there.
+    fn mov(&self, to64: &str, to: &str, o: &Operand, size: usize) {
+        match o {
+            Operand::Reg(r) => {
+                let from = self.reg(*r, size);
+                if from != to {
+                    println!("  mov {}, {}", to, from);
                 }
-                Op::Nop => continue,
-                _ => panic!("unknown operator"),
             }
+            Operand::Imm(n) => println!("  mov {}, {}", to, X86::imm(*n, size)),
+            Operand::Slot(_) | Operand::Global(_) => println!("  lea {}, {}", to64, self.mem(o)),
         }
-        println!("  pop rbp");
-        println!("  ret");
+    }
+
+    // An operand as the source of a two-operand instruction: a register
+    // or an immediate that fits in 32 bits. Anything else is moved to
+    // scratch register `r` first.
+    fn src(&self, o: &Operand, size: usize, r: char) -> String {
+        match o {
+            Operand::Reg(r) => self.reg(*r, size),
+            Operand::Imm(n) if size <= 4 || *n as i32 as i64 == *n => X86::imm(*n, size),
+            _ => {
+                let to = X86::scratch(r, size);
+                self.mov(&X86::scratch(r, 8), &to, o, size);
+                to
+            }
+        }
+    }
+
+    // an operand in a register, using rax for anything else
+    fn in_reg(&self, o: &Operand, size: usize) -> String {
+        match o {
+            Operand::Reg(r) => self.reg(*r, size),
+            _ => {
+                let rax = X86::scratch('a', size);
+                self.mov("rax", &rax, o, size);
+                rax
+            }
+        }
+    }
+
+    // a register operand used as a value, e.g. a function pointer
+    fn value(&self, o: &Operand, size: usize) -> String {
+        self.reg(X86::reg_num(o), size)
+    }
+
+    // the memory at the address an operand stands for
+    fn mem(&self, o: &Operand) -> String {
+        match o {
+            Operand::Reg(r) => format!("[{}]", self.reg(*r, 8)),
+            Operand::Slot(n) => format!("[rbp-{}]", n),
+            Operand::Global(name) => format!("[rip+{}]", name),
+            Operand::Imm(n) => format!("[{}]", n),
+        }
+    }
+
+    fn reg_num(o: &Operand) -> isize {
+        match o {
+            Operand::Reg(r) => *r,
+            _ => panic!("{:?} is not a register", o),
+        }
+    }
+
+    // an immediate as written at `size`, where 4-byte ones are truncated
+    fn imm(n: i64, size: usize) -> String {
+        if size <= 4 {
+            (n as i32).to_string()
+        } else {
+            n.to_string()
+        }
+    }
+
+    // rax, rcx or rdx at `size`
+    fn scratch(r: char, size: usize) -> String {
+        match size {
+            1 => format!("{}l", r),
+            2 => format!("{}x", r),
+            4 => format!("e{}x", r),
+            _ => format!("r{}x", r),
+        }
+    }
+
+    // Lays out the slots for saved registers below the locals and returns
+    // the frame size.
+    fn frame(&mut self, func: &Function) -> isize {
+        let mut size = func.frame;
+        let used = func.used.expect("registers not allocated");
+        self.saved = vec![];
+        for r in 0..self.regs.len() as isize {
+            if used & 1 << r != 0 && !CALLER_SAVED.contains(&r) {
+                size += 8;
+                self.saved.push((r, size));
+            }
+        }
+        self.spill = vec![0; self.regs.len()];
+        let calls = func.blocks.iter().flat_map(|b| b.ins.iter());
+        if calls.clone().any(|ir| ir.op == Op::Save) {
+            for r in CALLER_SAVED.iter() {
+                size += 8;
+                self.spill[*r as usize] = size;
+            }
+        }
+        (size + 15) / 16 * 16
     }
 }
 
@@ -172,6 +643,7 @@ impl X86 {
     fn reg(&self, ir_reg: isize, size: usize) -> String {
         let r = match size {
             1 => &self.regs8,
+            2 => &self.regs16,
             4 => &self.regs32,
             _ => &self.regs,
         };
@@ -181,10 +653,36 @@ impl X86 {
     fn argreg(&self, ir_reg: isize, size: usize) -> String {
         let r = match size {
             1 => &self.argregs8,
+            2 => &self.argregs16,
             4 => &self.argregs32,
             _ => &self.argregs,
         };
         let s = &r[ir_reg as usize].name;
         s.to_string()
     }
+
+    fn ptr(size: usize) -> &'static str {
+        match size {
+            1 => "byte",
+            2 => "word",
+            4 => "dword",
+            _ => "qword",
+        }
+    }
+
+    // condition code suffix for setcc/jcc
+    fn cc(cond: Cond) -> &'static str {
+        match cond {
+            Cond::Eq => "e",
+            Cond::Ne => "ne",
+            Cond::Lt => "l",
+            Cond::Le => "le",
+            Cond::Gt => "g",
+            Cond::Ge => "ge",
+            Cond::Ult => "b",
+            Cond::Ule => "be",
+            Cond::Ugt => "a",
+            Cond::Uge => "ae",
+        }
+    }
 }
@@ -0,0 +1,307 @@
+// Value numbering over the dominator tree.
+//
+// In SSA form two instructions applying the same pure operation to the
+// same operands compute the same value, so the second can reuse the
+// register of the first if the first dominates it. Blocks are visited
+// in dominator-tree preorder with a table of the computations available
+// from the dominating blocks, scoped like the renaming of `to_ssa`.
+// Copies are looked through, so `r2 = mov r1` gives r2 the value number
+// of r1, and the operands of commutative operations are put in order.
+//
+// A load reuses an earlier one of the same address and width only when
+// no store on the way may write to it. A frame slot whose address never
+// escapes can only be written through its own slot operand; other
+// addresses are assumed to alias anything but such slots and globals of
+// a different name. A call may write anything but those slots.
+
+use std::collections::{HashMap, HashSet};
+
+use cfg::{DomTree, Function};
+use gen_ir::{Cond, Ir, Op, Operand};
+
+type Key = (Op, Vec<Operand>, usize);
+
+// a load whose value is available in register `dst`
+#[derive(Clone)]
+struct Avail {
+    op: Op,
+    addr: Operand,
+    size: usize,
+    dst: isize,
+}
+
+struct Gvn {
+    dom: DomTree,
+    escaped: HashSet<isize>,
+    table: HashMap<Key, isize>,
+    // the value number of registers defined by copies
+    names: HashMap<isize, Operand>,
+}
+
+pub fn gvn(f: &mut Function) {
+    if f.blocks.is_empty() {
+        return;
+    }
+    let mut g = Gvn {
+        dom: DomTree::new(f),
+        escaped: escaped(f),
+        table: HashMap::new(),
+        names: HashMap::new(),
+    };
+    g.visit(f, 0, vec![]);
+}
+
+// slots used other than as the address of a load or store
+fn escaped(f: &Function) -> HashSet<isize> {
+    let mut escaped = HashSet::new();
+    for b in f.blocks.iter() {
+        for ir in b.ins.iter() {
+            for (i, a) in ir.args.iter().enumerate() {
+                if let Operand::Slot(n) = a {
+                    if i != 0 || !matches!(ir.op, Op::Load | Op::LoadU | Op::Store) {
+                        escaped.insert(*n);
+                    }
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn is_pure(op: &Op) -> bool {
+    matches!(
+        op,
+        Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Udiv
+            | Op::Mod
+            | Op::Umod
+            | Op::And
+            | Op::Or
+            | Op::Xor
+            | Op::Shl
+            | Op::Shr
+            | Op::Sar
+            | Op::Mulh
+            | Op::Umulh
+            | Op::Cmp(_)
+            | Op::Neg
+            | Op::Sext
+            | Op::Zext
+            | Op::Mov
+            | Op::Phi(_)
+    )
+}
+
+fn commutes(op: &Op) -> bool {
+    matches!(
+        op,
+        Op::Add | Op::Mul | Op::And | Op::Or | Op::Xor | Op::Cmp(Cond::Eq) | Op::Cmp(Cond::Ne)
+    )
+}
+
+impl Gvn {
+    fn canon(&self, a: &Operand) -> Operand {
+        match a {
+            Operand::Reg(r) => self.names.get(r).cloned().unwrap_or(a.clone()),
+            _ => a.clone(),
+        }
+    }
+
+    fn visit(&mut self, f: &mut Function, b: usize, mut loads: Vec<Avail>) {
+        let mut added = vec![];
+        for ir in f.blocks[b].ins.iter_mut() {
+            let mut args: Vec<Operand> = ir.args.iter().map(|a| self.canon(a)).collect();
+            let d = match ir.dst {
+                Some(d) if !ir.volatile => d,
+                _ => {
+                    self.clobber(ir, &args, &mut loads);
+                    continue;
+                }
+            };
+            // a full-width copy has the value of its operand
+            if ir.op == Op::Mov && ir.size == 8 {
+                self.names.insert(d, args.remove(0));
+                continue;
+            }
+            let earlier = match ir.op {
+                Op::Load | Op::LoadU => {
+                    let found = loads
+                        .iter()
+                        .find(|l| l.op == ir.op && l.addr == args[0] && l.size == ir.size)
+                        .map(|l| l.dst);
+                    if found.is_none() {
+                        loads.push(Avail {
+                            op: ir.op.clone(),
+                            addr: args[0].clone(),
+                            size: ir.size,
+                            dst: d,
+                        });
+                    }
+                    found
+                }
+                _ if is_pure(&ir.op) => {
+                    if commutes(&ir.op) && order(&args[1]) < order(&args[0]) {
+                        args.swap(0, 1);
+                    }
+                    let key = (ir.op.clone(), args, ir.size);
+                    let found = self.table.get(&key).cloned();
+                    if found.is_none() {
+                        self.table.insert(key.clone(), d);
+                        added.push(key);
+                    }
+                    found
+                }
+                _ => {
+                    self.clobber(ir, &args, &mut loads);
+                    None
+                }
+            };
+            if let Some(r) = earlier {
+                *ir = Ir::new(Op::Mov, Some(d), vec![Operand::Reg(r)], 8);
+                self.names.insert(d, Operand::Reg(r));
+            }
+        }
+
+        for c in self.dom.children[b].clone() {
+            let mut loads = loads.clone();
+            for p in between(f, b, c) {
+                for ir in f.blocks[p].ins.iter() {
+                    let args: Vec<Operand> = ir.args.iter().map(|a| self.canon(a)).collect();
+                    self.clobber(ir, &args, &mut loads);
+                }
+            }
+            self.visit(f, c, loads);
+        }
+        for key in added {
+            self.table.remove(&key);
+        }
+    }
+
+    // forgets the loads an instruction may write to
+    fn clobber(&self, ir: &Ir, args: &[Operand], loads: &mut Vec<Avail>) {
+        match ir.op {
+            Op::Store => loads.retain(|l| !self.alias(&args[0], ir.size, &l.addr, l.size)),
+            Op::Call => loads.retain(|l| match l.addr {
+                Operand::Slot(n) => !self.escaped.contains(&n),
+                _ => false,
+            }),
+            _ => {}
+        }
+    }
+
+    fn alias(&self, a: &Operand, asize: usize, b: &Operand, bsize: usize) -> bool {
+        match (a, b) {
+            // slot n covers rbp-n up to rbp-n+size
+            (Operand::Slot(x), Operand::Slot(y)) => {
+                -x < -y + bsize as isize && -y < -x + asize as isize
+            }
+            (Operand::Global(x), Operand::Global(y)) => x == y,
+            (Operand::Slot(_), Operand::Global(_)) | (Operand::Global(_), Operand::Slot(_)) => {
+                false
+            }
+            (Operand::Slot(n), _) | (_, Operand::Slot(n)) => self.escaped.contains(n),
+            _ => true,
+        }
+    }
+}
+
+// Puts registers before immediates and lower registers first, so both
+// orders of a commutative operation get the same key.
+fn order(a: &Operand) -> (u8, i64) {
+    match a {
+        Operand::Reg(r) => (0, *r as i64),
+        Operand::Imm(n) => (1, *n),
+        _ => (2, 0),
+    }
+}
+
+// The blocks a path from the end of block `b` may pass through before
+// reaching `c`, which `b` dominates, including `c` itself when it is in
+// a loop.
+fn between(f: &Function, b: usize, c: usize) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    let mut work: Vec<usize> = f.blocks[c].preds.clone();
+    let mut v = vec![];
+    while let Some(p) = work.pop() {
+        if p == b || !seen.insert(p) {
+            continue;
+        }
+        v.push(p);
+        work.extend(f.blocks[p].preds.iter());
+    }
+    v
+}
+
+#[test]
+fn gvn_test() {
+    use ir_text::{parse, print};
+    let mut funcs = parse(
+        "def f, 16
+        B0:
+          r0 = load.4 s4
+          r1 = load.4 s8
+          r2 = mul.4 r0, r1
+          r3 = mov r0
+          r4 = mul.4 r1, r3
+          r5 = load.4 s4
+          store.4 s8, r2
+          r6 = load.4 s4
+          r7 = load.4 s8
+          br r7, B1, B2
+        B1:
+          r8 = mul.4 r0, r1
+          r9 = load.4 s4
+          r10 = call @g, s12
+          r11 = load.4 s4
+          store.4 r10, r8
+          r12 = load.4 s4
+          jmp B3
+        B2:
+          store.4 s4, r2
+          jmp B3
+        B3:
+          r13 = mul.4 r1, r0
+          r14 = load.4 s4
+          r15 = load.4 s8
+          ret.4 r14",
+    )
+    .unwrap();
+    gvn(&mut funcs[0]);
+    // s4 and s8 never escape, so only stores to them kill their loads;
+    // B2 stores to s4 on the way to B3
+    assert_eq!(
+        print(&funcs),
+        "def f, 16
+B0:
+  r0 = load.4 s4
+  r1 = load.4 s8
+  r2 = mul.4 r0, r1
+  r3 = mov r0
+  r4 = mov r2
+  r5 = mov r0
+  store.4 s8, r2
+  r6 = mov r0
+  r7 = load.4 s8
+  br r7, B1, B2
+B1:
+  r8 = mov r2
+  r9 = mov r0
+  r10 = call @g, s12
+  r11 = mov r0
+  store.4 r10, r8
+  r12 = mov r0
+  jmp B3
+B2:
+  store.4 s4, r2
+  jmp B3
+B3:
+  r13 = mov r2
+  r14 = load.4 s4
+  r15 = mov r7
+  ret.4 r14
+"
+    );
+}
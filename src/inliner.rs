@@ -0,0 +1,319 @@
+// Function inlining.
+//
+// A call to a function defined in the same program is replaced by a copy
+// of its body when the function is not recursive and is either marked
+// `inline` or small: its size in instructions, counting one for each
+// block, is at most the limit set by `-finline-limit`. Functions are
+// visited callees first, so a body is copied with its own calls already
+// inlined. The copy gets registers above those of the caller, its blocks
+// are appended to the caller's, and its frame slots are placed below the
+// caller's locals. Each argument is stored to the slot of its parameter,
+// and each return moves the value to the call's destination and jumps
+// to the block holding the rest of the caller's block, so the usual
+// passes fold the parameters away afterwards. A limit of 0 turns
+// inlining off.
+//
+// A `static` function no longer referenced once its calls are inlined
+// is dropped.
+
+use std::collections::{HashMap, HashSet};
+
+use cfg::{Block, Function, Term};
+use gen_ir::{Ir, Op, Operand, Program, NUM_ARGREGS};
+
+pub const INLINE_LIMIT: usize = 40;
+
+pub fn inline(prog: &mut Program, limit: usize) {
+    if limit == 0 {
+        return;
+    }
+    let graph: HashMap<String, HashSet<String>> = prog
+        .funcs
+        .iter()
+        .map(|f| (f.name.clone(), callees(f)))
+        .collect();
+    let recursive: HashSet<String> = graph
+        .keys()
+        .filter(|f| reaches(&graph, f, f))
+        .cloned()
+        .collect();
+
+    let mut order = vec![];
+    let mut visited = HashSet::new();
+    for f in prog.funcs.iter() {
+        postorder(&graph, &f.name, &mut visited, &mut order);
+    }
+    for name in order {
+        let i = match prog.funcs.iter().position(|f| f.name == name) {
+            Some(i) => i,
+            None => continue,
+        };
+        loop {
+            let site = {
+                let f = &prog.funcs[i];
+                let funcs = &prog.funcs;
+                call_sites(f).into_iter().find_map(|(b, k, callee)| {
+                    let g = funcs.iter().find(|g| g.name == callee)?;
+                    let ok = !recursive.contains(&g.name)
+                        && (g.storage.is_inline || size(g) <= limit)
+                        && inlinable(g, &f.blocks[b].ins[k]);
+                    if ok {
+                        Some((b, k, g.clone()))
+                    } else {
+                        None
+                    }
+                })
+            };
+            match site {
+                Some((b, k, g)) => splice(&mut prog.funcs[i], b, k, &g),
+                None => break,
+            }
+        }
+    }
+    remove_unused(prog);
+}
+
+// the functions a function calls by name
+fn callees(f: &Function) -> HashSet<String> {
+    call_sites(f).into_iter().map(|(_, _, g)| g).collect()
+}
+
+// the calls by name in a function, as block, index and callee
+fn call_sites(f: &Function) -> Vec<(usize, usize, String)> {
+    let mut v = vec![];
+    for (b, block) in f.blocks.iter().enumerate() {
+        for (k, ir) in block.ins.iter().enumerate() {
+            if let (Op::Call, Some(Operand::Global(g))) = (&ir.op, ir.args.first()) {
+                v.push((b, k, g.clone()));
+            }
+        }
+    }
+    v
+}
+
+fn reaches(graph: &HashMap<String, HashSet<String>>, from: &str, to: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut work: Vec<&str> = vec![from];
+    while let Some(f) = work.pop() {
+        for g in graph.get(f).into_iter().flatten() {
+            if g == to {
+                return true;
+            }
+            if seen.insert(g.as_str()) {
+                work.push(g);
+            }
+        }
+    }
+    false
+}
+
+fn postorder(
+    graph: &HashMap<String, HashSet<String>>,
+    f: &str,
+    visited: &mut HashSet<String>,
+    order: &mut Vec<String>,
+) {
+    if !visited.insert(f.to_string()) {
+        return;
+    }
+    let mut callees: Vec<&String> = graph.get(f).into_iter().flatten().collect();
+    callees.sort();
+    for g in callees {
+        postorder(graph, g, visited, order);
+    }
+    order.push(f.to_string());
+}
+
+// the size of a function for the heuristic
+fn size(f: &Function) -> usize {
+    f.blocks
+        .iter()
+        .map(|b| {
+            let n = b
+                .ins
+                .iter()
+                .filter(|ir| !matches!(ir.op, Op::Kill | Op::Nop));
+            n.count() + 1
+        })
+        .sum()
+}
+
+// Whether a call to `g` can be replaced by its body: the call passes
+// exactly its parameters, all in registers and of a width a store can
+// write.
+fn inlinable(g: &Function, call: &Ir) -> bool {
+    !g.blocks.is_empty()
+        && !call.volatile
+        && call.args.len() == g.params.len() + 1
+        && g.params.len() <= NUM_ARGREGS
+        && g.params.iter().all(|p| matches!(p.size, 1 | 2 | 4 | 8))
+}
+
+// Replaces the call at index `k` of block `b` of `f` by the body of `g`.
+fn splice(f: &mut Function, b: usize, k: usize, g: &Function) {
+    let base = f.frame;
+    f.frame += g.frame;
+    let regs = f.next_reg();
+    let first = f.blocks.len();
+    let cont = first + g.blocks.len();
+
+    let rest = f.blocks[b].ins.split_off(k + 1);
+    let call = f.blocks[b].ins.pop().unwrap();
+    let term = std::mem::replace(&mut f.blocks[b].term, Term::Jmp(first));
+    for (p, a) in g.params.iter().zip(call.args.iter().skip(1)) {
+        let store = vec![Operand::Slot(p.offset + base), a.clone()];
+        f.blocks[b]
+            .ins
+            .push(Ir::new(Op::Store, None, store, p.size));
+    }
+
+    let rename = |a: &mut Operand| match a {
+        Operand::Reg(r) => *r += regs,
+        Operand::Slot(n) => *n += base,
+        _ => {}
+    };
+    for block in g.blocks.iter() {
+        let mut block = block.clone();
+        for ir in block.ins.iter_mut() {
+            ir.args.iter_mut().for_each(rename);
+            if let Some(d) = ir.dst.as_mut() {
+                *d += regs;
+            }
+            if let Op::Phi(preds) = &mut ir.op {
+                preds.iter_mut().for_each(|p| *p += first);
+            }
+        }
+        if let Some(a) = block.term.arg_mut() {
+            rename(a);
+        }
+        block.term = match block.term {
+            Term::Jmp(t) => Term::Jmp(t + first),
+            Term::Br(a, then, els) => Term::Br(a, then + first, els + first),
+            Term::Ret(v, size) => {
+                if let (Some(v), Some(d)) = (v, call.dst) {
+                    let size = if size == 4 { 4 } else { 8 };
+                    block.ins.push(Ir::new(Op::Mov, Some(d), vec![v], size));
+                }
+                Term::Jmp(cont)
+            }
+            Term::Unreachable => Term::Unreachable,
+        };
+        f.blocks.push(block);
+    }
+    let mut block = Block::new(term);
+    block.ins = rest;
+    f.blocks.push(block);
+    f.compute_edges();
+}
+
+// drops the static functions nothing refers to by name
+fn remove_unused(prog: &mut Program) {
+    loop {
+        let mut used = HashSet::new();
+        for f in prog.funcs.iter() {
+            for ir in f.blocks.iter().flat_map(|b| b.ins.iter()) {
+                for a in ir.args.iter() {
+                    match a {
+                        Operand::Global(g) if *g != f.name => {
+                            used.insert(g.clone());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let n = prog.funcs.len();
+        prog.funcs
+            .retain(|f| !f.storage.is_static || used.contains(&f.name));
+        if prog.funcs.len() == n {
+            return;
+        }
+    }
+}
+
+#[test]
+fn inline_test() {
+    use ir_text::{parse_program, print};
+    let mut prog = parse_program(
+        "def static sq, 16
+          param.4 4
+        B0:
+          r0 = load.4 s4
+          r1 = cmp.lt.4 r0, 0
+          br r1, B1, B2
+        B1:
+          r0 = neg.4 r0
+          jmp B2
+        B2:
+          r0 = mul.4 r0, r0
+          ret.4 r0
+        def main, 16
+        B0:
+          r0 = mov 3
+          store.4 s4, r0
+          r1 = call @sq, r0
+          r2 = load.4 s4
+          r1 = add.4 r1, r2
+          ret.4 r1",
+    )
+    .unwrap();
+    inline(&mut prog, INLINE_LIMIT);
+    // sq is gone, its slot is below main's and its registers above
+    assert_eq!(
+        print(&prog.funcs),
+        "def main, 32
+B0:
+  r0 = mov 3
+  store.4 s4, r0
+  store.4 s20, r0
+  jmp B1
+B1:
+  r3 = load.4 s20
+  r4 = cmp.lt.4 r3, 0
+  br r4, B2, B3
+B2:
+  r3 = neg.4 r3
+  jmp B3
+B3:
+  r3 = mul.4 r3, r3
+  r1 = mov.4 r3
+  jmp B4
+B4:
+  r2 = load.4 s4
+  r1 = add.4 r1, r2
+  ret.4 r1
+"
+    );
+}
+
+#[test]
+fn inline_limit_test() {
+    use ir_text::{parse, parse_program};
+    // f calls itself through g; h is too big for a limit of 3
+    let text = "def f, 0
+        B0:
+          r0 = call @g
+          ret r0
+        def g, 0
+        B0:
+          r0 = call @f
+          ret r0
+        def h, 0
+        B0:
+          r0 = mov 1
+          r0 = add r0, 1
+          r0 = add r0, 1
+          ret r0
+        def main, 0
+        B0:
+          r0 = call @f
+          r1 = call @h
+          ret r1";
+    let mut prog = parse_program(text).unwrap();
+    inline(&mut prog, 3);
+    assert_eq!(prog.funcs, parse(text).unwrap());
+    inline(&mut prog, 4);
+    let main = &prog.funcs[3];
+    assert_eq!(call_sites(main).len(), 1);
+    assert_eq!(main.blocks.len(), 3);
+}
@@ -0,0 +1,852 @@
+// IR interpreter
+//
+// Runs the output of `GenIr` before or after register allocation, so the
+// frontend and the allocator can be tested without an assembler. Memory
+// is a flat byte array holding the globals and a stack laid out like the
+// x86 backend's frames: `Bprel` slots sit below rbp, and arguments past
+// the sixth above the saved rbp and the return address.
+//
+// A function with a `used` register mask has been allocated.
+// Those share one physical register file: callee-saved registers survive
+// calls and caller-saved ones are clobbered unless saved around them.
+// Before allocation every call gets its own virtual registers, and reading
+// one that was never set or already killed is an error.
+//
+// Calls to functions that are not defined go to a few shims for the C
+// library; what they print is collected in `output`.
+
+use std::collections::HashMap;
+
+use cfg::Term;
+use gen_ir::{Cond, Op, Operand, Program, NUM_ARGREGS};
+use regalloc::{CALLER_SAVED, NUM_REGS as REGS};
+
+const NUM_REGS: usize = REGS as usize;
+
+// the lowest valid address, so that null is never one
+const MEM_BASE: u64 = 0x1000;
+const STACK_SIZE: u64 = 1 << 20;
+// functions get addresses outside of memory
+const FUNC_BASE: u64 = 1 << 40;
+const MAX_DEPTH: usize = 10000;
+const MAX_STEPS: usize = 100_000_000;
+// what caller-saved registers hold after a call
+const POISON: u64 = 0x5a5a_5a5a_5a5a_5a5a;
+
+const SHIMS: [&str; 3] = ["printf", "putchar", "puts"];
+
+pub struct Interp<'a> {
+    prog: &'a Program,
+    mem: Vec<u8>,
+    globals: HashMap<String, u64>,
+    funcs: HashMap<String, usize>,
+    regs: [u64; NUM_REGS],
+    rax: u64,
+    rsp: u64,
+    frames: Vec<Frame>,
+    steps: usize,
+    // width of the value `main` returned
+    ret_size: usize,
+    pub output: String,
+}
+
+// state of one activation
+struct Frame {
+    f: usize,
+    block: usize,
+    // index of the next instruction; the terminator follows the last
+    pc: usize,
+    rbp: u64,
+    // `None` after allocation, when the physical registers are used
+    vregs: Option<HashMap<isize, u64>>,
+    // caller-saved registers saved around a call
+    spills: HashMap<isize, u64>,
+    // callee-saved registers to hand back to the caller
+    saved: Vec<(usize, u64)>,
+    // register of the caller that receives the result
+    dst: isize,
+}
+
+enum Callee {
+    Func(usize),
+    Shim(&'static str),
+}
+
+impl<'a> Interp<'a> {
+    pub fn new(prog: &'a Program) -> Self {
+        let mut globals = HashMap::new();
+        let mut size: usize = 0;
+        for g in prog.globals.iter() {
+            let align = g.align.max(1);
+            size = size.div_ceil(align) * align;
+            globals.insert(g.name.clone(), MEM_BASE + size as u64);
+            size += g.size;
+        }
+        // the stack starts 16-byte aligned
+        let mut mem = vec![0; size.div_ceil(16) * 16 + STACK_SIZE as usize];
+        for g in prog.globals.iter() {
+            if let Some(bytes) = &g.init {
+                let a = (globals[&g.name] - MEM_BASE) as usize;
+                mem[a..a + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+        let mut funcs = HashMap::new();
+        for (i, func) in prog.funcs.iter().enumerate() {
+            funcs.insert(func.name.clone(), i);
+        }
+        let top = MEM_BASE + mem.len() as u64;
+        Interp {
+            prog,
+            mem,
+            globals,
+            funcs,
+            regs: [POISON; NUM_REGS],
+            rax: POISON,
+            rsp: top,
+            frames: vec![],
+            steps: 0,
+            ret_size: 8,
+            output: String::new(),
+        }
+    }
+
+    // calls `main` and returns its value, sign-extended from the width
+    // it was returned at
+    pub fn run(&mut self) -> Result<i64, String> {
+        let main = match self.funcs.get("main") {
+            Some(f) => *f,
+            None => return Err("undefined function 'main'".to_string()),
+        };
+        self.enter(main, vec![], -1)?;
+        while !self.frames.is_empty() {
+            self.step()?;
+        }
+        Ok(Interp::sext(self.rax, self.ret_size) as i64)
+    }
+}
+
+impl<'a> Interp<'a> {
+    // Pushes an activation of function `f` whose result goes to register
+    // `dst` of the caller, and stores the parameters to their slots.
+    fn enter(&mut self, f: usize, args: Vec<u64>, dst: isize) -> Result<(), String> {
+        if self.frames.len() >= MAX_DEPTH {
+            return Err("call depth exceeded".to_string());
+        }
+        let func = &self.prog.funcs[f];
+        if func.blocks.is_empty() {
+            return Err(format!("function '{}' has no blocks", func.name));
+        }
+        // the return address and the saved rbp
+        self.push(0)?;
+        self.push(0)?;
+        let rbp = self.rsp;
+        self.set_rsp(rbp - func.frame as u64)?;
+        for (i, p) in func.params.iter().enumerate() {
+            let v = if i < NUM_ARGREGS {
+                match args.get(i) {
+                    Some(v) => *v,
+                    None => return Err(format!("missing argument {}", i)),
+                }
+            } else {
+                self.load(rbp + 16 + 8 * (i - NUM_ARGREGS) as u64, 8)?
+            };
+            self.store(rbp - p.offset as u64, p.size, v)?;
+        }
+        let used = func.used;
+        let saved = (0..NUM_REGS)
+            .filter(|r| used.is_some_and(|used| used & 1 << r != 0))
+            .filter(|r| !CALLER_SAVED.contains(&(*r as isize)))
+            .map(|r| (r, self.regs[r]))
+            .collect();
+        self.frames.push(Frame {
+            f,
+            block: 0,
+            pc: 0,
+            rbp,
+            vregs: if used.is_none() {
+                Some(HashMap::new())
+            } else {
+                None
+            },
+            spills: HashMap::new(),
+            saved,
+            dst,
+        });
+        Ok(())
+    }
+
+    // returns from the current activation with the value in rax
+    fn leave(&mut self) {
+        let fr = self.frames.pop().expect("no activation");
+        for (r, v) in fr.saved {
+            self.regs[r] = v;
+        }
+        self.rsp = fr.rbp + 16;
+        if !self.frames.is_empty() {
+            self.returned(fr.dst);
+        }
+    }
+
+    // clobbers the caller-saved registers and sets the result
+    fn returned(&mut self, dst: isize) {
+        if self.frame().vregs.is_none() {
+            for r in CALLER_SAVED.iter() {
+                self.regs[*r as usize] = POISON;
+            }
+        }
+        let v = self.rax;
+        self.set(dst, v);
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("no activation")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no activation")
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err("step limit exceeded".to_string());
+        }
+        let prog = self.prog;
+        let (f, block, pc) = {
+            let fr = self.frame_mut();
+            fr.pc += 1;
+            (fr.f, fr.block, fr.pc - 1)
+        };
+        let b = &prog.funcs[f].blocks[block];
+        let ir = match b.ins.get(pc) {
+            Some(ir) => ir,
+            None => return self.terminate(&b.term),
+        };
+        let dst = ir.dst.unwrap_or(-1);
+        match &ir.op {
+            Op::Mov
+            | Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Udiv
+            | Op::Mod
+            | Op::Umod
+            | Op::And
+            | Op::Or
+            | Op::Xor
+            | Op::Shl
+            | Op::Shr
+            | Op::Sar
+            | Op::Mulh
+            | Op::Umulh
+            | Op::Cmp(_)
+            | Op::Neg
+            | Op::Sext
+            | Op::Zext => {
+                let mut args = vec![];
+                for a in ir.args.iter() {
+                    args.push(self.value(a)?);
+                }
+                let v = evaluate(&ir.op, &args, ir.size)?;
+                self.set(dst, v);
+            }
+            Op::Load => {
+                let addr = self.value(&ir.args[0])?;
+                let v = Interp::sext(self.load(addr, ir.size)?, ir.size);
+                self.set(dst, v);
+            }
+            Op::LoadU => {
+                let addr = self.value(&ir.args[0])?;
+                let v = self.load(addr, ir.size)?;
+                self.set(dst, v);
+            }
+            Op::Store => {
+                let addr = self.value(&ir.args[0])?;
+                let v = self.value(&ir.args[1])?;
+                self.store(addr, ir.size, v)?;
+            }
+            Op::Push => {
+                let addr = self.value(&ir.args[0])?;
+                let v = self.load(addr, 8)?;
+                self.push(v)?;
+            }
+            Op::SpAdd => {
+                let n = self.value(&ir.args[0])?;
+                let rsp = self.rsp.wrapping_add(n);
+                self.set_rsp(rsp)?;
+            }
+            Op::Save => {
+                let r = match ir.args[0] {
+                    Operand::Reg(r) if (r as usize) < NUM_REGS => r,
+                    ref a => return Err(format!("save of {}", a)),
+                };
+                let v = self.regs[r as usize];
+                self.frame_mut().spills.insert(r, v);
+            }
+            Op::Restore => match self.frame().spills.get(&dst) {
+                Some(v) => self.regs[dst as usize] = *v,
+                None => return Err(format!("restore of unsaved register r{}", dst)),
+            },
+            Op::Call => {
+                let callee = match &ir.args[0] {
+                    Operand::Global(name) => self.callee(name)?,
+                    f => {
+                        let addr = self.value(f)?;
+                        self.callee_at(addr)?
+                    }
+                };
+                self.call(callee, &ir.args[1..], dst)?;
+            }
+            Op::Nop => {}
+            Op::Phi(_) => return Err("phi after the start of a block".to_string()),
+            Op::Kill => {
+                for r in ir.uses() {
+                    self.kill(r);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn terminate(&mut self, term: &Term) -> Result<(), String> {
+        match term {
+            Term::Jmp(b) => self.jump(*b)?,
+            Term::Br(a, then, els) => {
+                let v = self.value(a)?;
+                self.jump(if v != 0 { *then } else { *els })?;
+            }
+            Term::Ret(v, size) => {
+                if let Some(v) = v {
+                    self.rax = Interp::truncate(self.value(v)?, *size);
+                    if self.frames.len() == 1 {
+                        self.ret_size = *size;
+                    }
+                }
+                self.leave();
+            }
+            Term::Unreachable => {
+                return Err(format!("reached unreachable B{}", self.frame().block));
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, callee: Callee, args: &[Operand], dst: isize) -> Result<(), String> {
+        if !self.rsp.is_multiple_of(16) {
+            return Err("stack is not 16-byte aligned at a call".to_string());
+        }
+        let mut v = vec![];
+        for a in args {
+            v.push(self.value(a)?);
+        }
+        match callee {
+            Callee::Func(f) => self.enter(f, v, dst),
+            Callee::Shim(name) => {
+                self.rax = self.shim(name, &v)?;
+                self.returned(dst);
+                Ok(())
+            }
+        }
+    }
+
+    // an operation at width `size`, where 4-byte results are zero-extended
+    fn binary(op: &Op, l: u64, r: u64, size: usize) -> Result<u64, String> {
+        if size == 4 {
+            let (l, r) = (Interp::sext(l, 4), Interp::sext(r, 4));
+            let (ul, ur) = (Interp::zext(l, 4), Interp::zext(r, 4));
+            let v = match op {
+                Op::Udiv | Op::Umod => Interp::binary(op, ul, ur, 8)?,
+                Op::Shr => Interp::binary(op, ul, ur & 31, 8)?,
+                Op::Div | Op::Mod if l as i64 == i32::MIN as i64 && r as i64 == -1 => {
+                    return Err("division overflow".to_string())
+                }
+                Op::Shl | Op::Sar => Interp::binary(op, l, r & 31, 8)?,
+                Op::Mulh => ((l as i64 * r as i64) >> 32) as u64,
+                Op::Umulh => (ul * ur) >> 32,
+                Op::Cmp(Cond::Ult)
+                | Op::Cmp(Cond::Ule)
+                | Op::Cmp(Cond::Ugt)
+                | Op::Cmp(Cond::Uge) => Interp::binary(op, ul, ur, 8)?,
+                _ => Interp::binary(op, l, r, 8)?,
+            };
+            return Ok(Interp::zext(v, 4));
+        }
+        let (sl, sr) = (l as i64, r as i64);
+        let v = match op {
+            Op::Add => l.wrapping_add(r),
+            Op::Sub => l.wrapping_sub(r),
+            Op::Mul => l.wrapping_mul(r),
+            Op::Mulh => ((sl as i128 * sr as i128) >> 64) as u64,
+            Op::Umulh => ((l as u128 * r as u128) >> 64) as u64,
+            Op::Div | Op::Udiv | Op::Mod | Op::Umod if r == 0 => {
+                return Err("division by zero".to_string())
+            }
+            Op::Div | Op::Mod if sl == i64::MIN && sr == -1 => {
+                return Err("division overflow".to_string())
+            }
+            Op::Div => (sl / sr) as u64,
+            Op::Udiv => l / r,
+            Op::Mod => (sl % sr) as u64,
+            Op::Umod => l % r,
+            Op::And => l & r,
+            Op::Or => l | r,
+            Op::Xor => l ^ r,
+            // the count is taken modulo 64 as by x86
+            Op::Shl => l << (r & 63),
+            Op::Shr => l >> (r & 63),
+            Op::Sar => (sl >> (r & 63)) as u64,
+            Op::Cmp(cond) => {
+                let b = match cond {
+                    Cond::Eq => l == r,
+                    Cond::Ne => l != r,
+                    Cond::Lt => sl < sr,
+                    Cond::Le => sl <= sr,
+                    Cond::Gt => sl > sr,
+                    Cond::Ge => sl >= sr,
+                    Cond::Ult => l < r,
+                    Cond::Ule => l <= r,
+                    Cond::Ugt => l > r,
+                    Cond::Uge => l >= r,
+                };
+                b as u64
+            }
+            _ => return Err(format!("{:?} is not a binary operation", op)),
+        };
+        Ok(v)
+    }
+
+    fn sext(v: u64, size: usize) -> u64 {
+        if size >= 8 {
+            return v;
+        }
+        let shift = 64 - size * 8;
+        (((v << shift) as i64) >> shift) as u64
+    }
+
+    // the result of an operation at width `size`, as x86 leaves it
+    fn truncate(v: u64, size: usize) -> u64 {
+        if size == 4 {
+            Interp::zext(v, 4)
+        } else {
+            v
+        }
+    }
+
+    fn zext(v: u64, size: usize) -> u64 {
+        if size >= 8 {
+            return v;
+        }
+        v & ((1 << (size * 8)) - 1)
+    }
+
+    fn value(&self, o: &Operand) -> Result<u64, String> {
+        match o {
+            Operand::Reg(r) => self.get(*r),
+            Operand::Imm(n) => Ok(*n as u64),
+            Operand::Slot(n) => Ok(self.frame().rbp - *n as u64),
+            Operand::Global(name) => self.address(name),
+        }
+    }
+
+    fn get(&self, r: isize) -> Result<u64, String> {
+        match &self.frame().vregs {
+            Some(vregs) => match vregs.get(&r) {
+                Some(v) => Ok(*v),
+                None => Err(format!("use of undefined register r{}", r)),
+            },
+            None => match self.regs.get(r as usize) {
+                Some(v) => Ok(*v),
+                None => Err(format!("no register r{}", r)),
+            },
+        }
+    }
+
+    fn set(&mut self, r: isize, v: u64) {
+        if let Some(vregs) = &mut self.frame_mut().vregs {
+            vregs.insert(r, v);
+            return;
+        }
+        self.regs[r as usize] = v;
+    }
+
+    fn kill(&mut self, r: isize) {
+        if let Some(vregs) = &mut self.frame_mut().vregs {
+            vregs.remove(&r);
+        }
+    }
+
+    // Enters a block, running its phis as one parallel copy: they all
+    // read their operands before any of them is written.
+    fn jump(&mut self, block: usize) -> Result<(), String> {
+        let prog = self.prog;
+        let (f, from) = (self.frame().f, self.frame().block);
+        let mut copies = vec![];
+        for ir in prog.funcs[f].blocks[block].ins.iter() {
+            let preds = match &ir.op {
+                Op::Phi(preds) => preds,
+                _ => break,
+            };
+            match preds.iter().position(|p| *p == from) {
+                Some(i) => copies.push((ir.dst.unwrap_or(-1), self.value(&ir.args[i])?)),
+                None => return Err(format!("phi in B{} has no value for B{}", block, from)),
+            }
+        }
+        let fr = self.frame_mut();
+        fr.block = block;
+        fr.pc = copies.len();
+        for (r, v) in copies {
+            self.set(r, v);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Interp<'a> {
+    fn address(&self, name: &str) -> Result<u64, String> {
+        if let Some(addr) = self.globals.get(name) {
+            return Ok(*addr);
+        }
+        let nfuncs = self.prog.funcs.len();
+        match self.callee(name)? {
+            Callee::Func(f) => Ok(FUNC_BASE + f as u64),
+            Callee::Shim(name) => {
+                let i = SHIMS.iter().position(|s| *s == name).unwrap_or(0);
+                Ok(FUNC_BASE + (nfuncs + i) as u64)
+            }
+        }
+    }
+
+    fn callee(&self, name: &str) -> Result<Callee, String> {
+        if let Some(f) = self.funcs.get(name) {
+            return Ok(Callee::Func(*f));
+        }
+        match SHIMS.iter().find(|s| **s == name) {
+            Some(s) => Ok(Callee::Shim(s)),
+            None => Err(format!("undefined function '{}'", name)),
+        }
+    }
+
+    fn callee_at(&self, addr: u64) -> Result<Callee, String> {
+        let nfuncs = self.prog.funcs.len();
+        let i = addr.wrapping_sub(FUNC_BASE) as usize;
+        if addr >= FUNC_BASE && i < nfuncs {
+            return Ok(Callee::Func(i));
+        }
+        match SHIMS.get(i.wrapping_sub(nfuncs)) {
+            Some(s) if addr >= FUNC_BASE => Ok(Callee::Shim(s)),
+            _ => Err(format!("call through invalid pointer {:#x}", addr)),
+        }
+    }
+
+    fn check(&self, addr: u64, size: usize) -> Result<usize, String> {
+        let end = MEM_BASE + self.mem.len() as u64;
+        if addr < MEM_BASE || addr.saturating_add(size as u64) > end {
+            return Err(format!("invalid memory access at {:#x}", addr));
+        }
+        Ok((addr - MEM_BASE) as usize)
+    }
+
+    fn load(&self, addr: u64, size: usize) -> Result<u64, String> {
+        let a = self.check(addr, size)?;
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(&self.mem[a..a + size]);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn store(&mut self, addr: u64, size: usize, v: u64) -> Result<(), String> {
+        let a = self.check(addr, size)?;
+        self.mem[a..a + size].copy_from_slice(&v.to_le_bytes()[..size]);
+        Ok(())
+    }
+
+    fn push(&mut self, v: u64) -> Result<(), String> {
+        self.set_rsp(self.rsp - 8)?;
+        self.store(self.rsp, 8, v)
+    }
+
+    fn set_rsp(&mut self, rsp: u64) -> Result<(), String> {
+        let bottom = MEM_BASE + self.mem.len() as u64 - STACK_SIZE;
+        if rsp < bottom {
+            return Err("stack overflow".to_string());
+        }
+        self.rsp = rsp;
+        Ok(())
+    }
+
+    fn string(&self, addr: u64) -> Result<String, String> {
+        let mut s = vec![];
+        let mut a = addr;
+        loop {
+            let c = self.load(a, 1)? as u8;
+            if c == 0 {
+                return Ok(String::from_utf8_lossy(&s).into_owned());
+            }
+            s.push(c);
+            a += 1;
+        }
+    }
+}
+
+// the C library shims
+impl<'a> Interp<'a> {
+    fn shim(&mut self, name: &str, args: &[u64]) -> Result<u64, String> {
+        match name {
+            "putchar" => {
+                let c = *args.first().unwrap_or(&0) as u8;
+                self.output.push(c as char);
+                Ok(c as u64)
+            }
+            "puts" => {
+                let s = self.string(*args.first().unwrap_or(&0))?;
+                self.output.push_str(&s);
+                self.output.push('\n');
+                Ok(s.len() as u64 + 1)
+            }
+            "printf" => {
+                let s = self.printf(args)?;
+                self.output.push_str(&s);
+                Ok(s.len() as u64)
+            }
+            _ => Err(format!("undefined function '{}'", name)),
+        }
+    }
+
+    // Supports the conversions d, i, u, x, c, s, p and % with an
+    // optional l or ll length modifier.
+    fn printf(&self, args: &[u64]) -> Result<String, String> {
+        let fmt = self.string(*args.first().unwrap_or(&0))?;
+        let mut out = String::new();
+        let mut next = 1;
+        let mut chars = fmt.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let mut long = false;
+            let mut conv = chars.next();
+            while conv == Some('l') {
+                long = true;
+                conv = chars.next();
+            }
+            let conv = match conv {
+                Some('%') => {
+                    out.push('%');
+                    continue;
+                }
+                Some(c) => c,
+                None => return Err("incomplete conversion in printf format".to_string()),
+            };
+            let v = self.vararg(args, next)?;
+            next += 1;
+            match conv {
+                'd' | 'i' if long => out.push_str(&(v as i64).to_string()),
+                'd' | 'i' => out.push_str(&(v as i32).to_string()),
+                'u' if long => out.push_str(&v.to_string()),
+                'u' => out.push_str(&(v as u32).to_string()),
+                'x' if long => out.push_str(&format!("{:x}", v)),
+                'x' => out.push_str(&format!("{:x}", v as u32)),
+                'p' => out.push_str(&format!("{:#x}", v)),
+                'c' => out.push(v as u8 as char),
+                's' => out.push_str(&self.string(v)?),
+                c => return Err(format!("unsupported conversion '%{}' in printf format", c)),
+            }
+        }
+        Ok(out)
+    }
+
+    // argument `i` of a variadic call; those past the sixth are on the stack
+    fn vararg(&self, args: &[u64], i: usize) -> Result<u64, String> {
+        if i < NUM_ARGREGS {
+            return match args.get(i) {
+                Some(v) => Ok(*v),
+                None => Err("too few arguments to printf".to_string()),
+            };
+        }
+        self.load(self.rsp + 8 * (i - NUM_ARGREGS) as u64, 8)
+    }
+}
+
+// The result of an operation without side effects on the given operand
+// values, or the error it traps with. Constant folding uses it too, so
+// folded code computes exactly what the interpreter would.
+pub fn evaluate(op: &Op, args: &[u64], size: usize) -> Result<u64, String> {
+    match op {
+        Op::Mov => Ok(Interp::truncate(args[0], size)),
+        Op::Neg => Ok(Interp::truncate(args[0].wrapping_neg(), size)),
+        Op::Sext => Ok(Interp::sext(args[0], size)),
+        Op::Zext => Ok(Interp::zext(args[0], size)),
+        _ => Interp::binary(op, args[0], args[1], size),
+    }
+}
+
+// Compiles `code` and runs it as generated, optimized in SSA form and
+// optimized after register allocation, returning the three results and
+// what the first run printed.
+#[cfg(test)]
+fn run(code: &str) -> ([Result<i64, String>; 3], String) {
+    use gen_ir::GenIr;
+    use inliner::INLINE_LIMIT;
+    use lexer::Lexer;
+    use opt::optimize;
+    use parser::Parser;
+    use preprocess::Preprocessor;
+    use regalloc::RegAlloc;
+    use sema::Sema;
+    let compile = || {
+        let code = Preprocessor::new().run(code).unwrap();
+        let tokens = Lexer::new(&code).run().unwrap();
+        let nodes = Parser::new().run(tokens).unwrap();
+        let nodes = Sema::new().run(nodes).unwrap();
+        GenIr::new().run(&nodes).unwrap()
+    };
+    let prog = compile();
+    let mut interp = Interp::new(&prog);
+    let before = interp.run();
+    let output = interp.output;
+    let mut prog = compile();
+    optimize(&mut prog, INLINE_LIMIT);
+    let ssa = Interp::new(&prog).run();
+    let prog = RegAlloc::new().run(prog).unwrap();
+    let after = Interp::new(&prog).run();
+    ([before, ssa, after], output)
+}
+
+#[test]
+fn programs_test() {
+    // the programs test.bash runs, except the one that links a C helper
+    let tests = [
+        ("main.c", 0),
+        ("addsubmuldiv.c", 27),
+        ("func.c", 12),
+        ("var.c", 17),
+        ("inttypes.c", 14),
+        ("qualifier.c", 21),
+        ("funcptr.c", 32),
+        ("declarator.c", 86),
+        ("static.c", 66),
+        ("bitfield.c", 15),
+        ("bool.c", 17),
+        ("manyargs.c", 133),
+        ("bitops.c", 22),
+        ("return.c", 0),
+        ("void.c", 132),
+        ("loop.c", 92),
+        ("sccp.c", 15),
+        ("cse.c", 87),
+        ("args.c", 104),
+        ("strength.c", 29),
+        ("inline.c", 73),
+    ];
+    for (file, expected) in tests.iter() {
+        let path = format!("{}/test/{}", env!("CARGO_MANIFEST_DIR"), file);
+        let code = ::std::fs::read_to_string(&path).unwrap();
+        for result in run(&code).0.iter() {
+            assert_eq!(result, &Ok(*expected), "{}", file);
+        }
+    }
+    for result in run("int main() { return -1; }").0.iter() {
+        assert_eq!(result, &Ok(-1));
+    }
+}
+
+#[test]
+fn printf_test() {
+    let (results, output) = run("int printf(char *f, int a, long b, int c);
+        int putchar(int c);
+        int main() {
+        char f[9];
+        f[0] = 37; f[1] = 100; f[2] = 32; f[3] = 37; f[4] = 108;
+        f[5] = 117; f[6] = 37; f[7] = 99; f[8] = 0;
+        int n = printf(f, -5, 4294967296, 33);
+        putchar(10);
+        return n;
+    }");
+    assert_eq!(output, "-5 4294967296!\n");
+    assert_eq!(results, [Ok(14), Ok(14), Ok(14)]);
+}
+
+#[test]
+fn error_test() {
+    let first = |code| run(code).0[0].clone();
+    let (results, _) = run("int f(int a) { return 7 / a; } int main() { return f(0); }");
+    for result in results.iter() {
+        assert_eq!(*result, Err("division by zero".to_string()));
+    }
+    assert_eq!(
+        first("int g(int); int main() { return g(1); }"),
+        Err("undefined function 'g'".to_string())
+    );
+    assert_eq!(
+        first("int main() { int *p = 0; return *p; }"),
+        Err("invalid memory access at 0x0".to_string())
+    );
+    assert_eq!(
+        first("int f(int n) { return f(n + 1); } int main() { return f(0); }"),
+        Err("call depth exceeded".to_string())
+    );
+}
+
+#[test]
+fn hand_written_ir_test() {
+    use ir_text::parse_program;
+    // an allocated callee that clobbers r10 but also uses rbx, which it
+    // must hand back intact
+    let prog = parse_program(
+        "def f, 0, 5
+        B0:
+          r0 = mov 1
+          r2 = mov 2
+          r0 = add r0, r2
+          ret r0
+        def main, 0, 6
+        B0:
+          r2 = mov 40
+          r1 = call @f
+          r1 = add r1, r2
+          br r1, B2, B1
+        B1:
+          ret r1
+        B2:
+          ret r1",
+    )
+    .unwrap();
+    assert_eq!(Interp::new(&prog).run(), Ok(43));
+}
+
+#[test]
+fn width_test() {
+    use ir_text::parse_program;
+    // 4-byte operations see only the low half and zero-extend the result;
+    // what main returns is sign-extended from the width of its ret
+    let run = |body: &str| {
+        let text = format!("def main, 16\nB0:\n{}", body);
+        let prog = parse_program(&text).unwrap();
+        Interp::new(&prog).run()
+    };
+    assert_eq!(run("r0 = mov -1\nr0 = add.4 r0, 0\nret r0"), Ok(4294967295));
+    assert_eq!(run("r0 = mov -1\nr0 = add.4 r0, 0\nret.4 r0"), Ok(-1));
+    assert_eq!(
+        run("r0 = mov 4294967296\nr0 = cmp.eq.4 r0, 0\nret r0"),
+        Ok(1)
+    );
+    assert_eq!(
+        run("r0 = mov -8\nr0 = sar.4 r0, 33\nret r0"),
+        Ok(4294967292)
+    );
+    assert_eq!(
+        run("r0 = mov -7\nr0 = udiv.4 r0, 2\nret r0"),
+        Ok(2147483644)
+    );
+    assert_eq!(
+        run("r0 = mov s8\nstore.4 r0, -3\nr1 = load.4 s8\nret r1"),
+        Ok(-3)
+    );
+    assert_eq!(run("ret.4 -1"), Ok(-1));
+    assert_eq!(run("ret.4 4294967294"), Ok(-2));
+    assert_eq!(
+        run("r0 = mov -2147483648\nr0 = div.4 r0, -1\nret r0"),
+        Err("division overflow".to_string())
+    );
+}
@@ -0,0 +1,791 @@
+// Textual form of the IR, printed by `--emit=ir` and read back by
+// `parse`, so register allocator and backend tests can be written as
+// plain text.
+//
+// There is one instruction per line and `;` starts a comment. A program
+// starts with a line for each object with static storage duration,
+//
+//     global [static] NAME, SIZE, ALIGN[, BYTES]
+//
+// where BYTES is the initial value as hex digits, two for each byte in
+// memory order, and an object without one goes to .bss. A function
+// starts with a `def` line and runs until the next one:
+//
+//     def [static] [inline] NAME, FRAME[, USED]
+//
+// where FRAME is the frame size and USED the register bitmask the
+// allocator fills in. It is followed by a `param.SIZE OFFSET` line for
+// each parameter and then by its blocks, in order. Block n starts with
+// `Bn:` on a line of its own and ends with one of the terminators
+//
+//     jmp Bn
+//     br A, Bn, Bm       ; to Bn if A is nonzero, else to Bm
+//     ret[.SIZE] [A]
+//     unreachable        ; control never gets here
+//
+// Every other line is
+//
+//     [volatile] [wrap] [rD =] OP[.COND][.SIZE] [A, ...]
+//
+// where `wrap` marks arithmetic on unsigned values, whose overflow is
+// defined.
+// Operands are registers `rN`, virtual before allocation and physical
+// after it, decimal immediates, frame slots `sN` standing for the address
+// rbp-N, and globals `@NAME` standing for their address. The size is the
+// width of the operation and may be left out when it is 8, except on
+// load, loadu, store, sext and zext. COND is the condition of cmp: eq,
+// ne, lt, le, gt, ge, ult, ule, ugt or uge. A phi pairs each operand
+// with the predecessor it comes from.
+//
+//     rD = add sub mul div udiv mod umod and or xor shl shr sar  A, B
+//     rD = mulh umulh cmp                                        A, B
+//     rD = neg mov load loadu sext zext                          A
+//     rD = call                                                  F, A...
+//     rD = restore
+//     rD = phi                                                   Bn: A, ...
+//     store                                                      ADDR, A
+//     push spadd save kill                                       A
+//     nop
+
+use std::fmt;
+
+use cfg::{Block, Function, Param, Term};
+use gen_ir::{Cond, Global, Ir, Op, Operand, Program};
+use node::Storage;
+
+const CONDS: [(Cond, &str); 10] = [
+    (Cond::Eq, "eq"),
+    (Cond::Ne, "ne"),
+    (Cond::Lt, "lt"),
+    (Cond::Le, "le"),
+    (Cond::Gt, "gt"),
+    (Cond::Ge, "ge"),
+    (Cond::Ult, "ult"),
+    (Cond::Ule, "ule"),
+    (Cond::Ugt, "ugt"),
+    (Cond::Uge, "uge"),
+];
+
+// operations whose size is always written out
+const SIZED: [&str; 5] = ["load", "loadu", "store", "sext", "zext"];
+
+pub fn print_program(prog: &Program) -> String {
+    let mut s = String::new();
+    for g in prog.globals.iter() {
+        s.push_str("global ");
+        if g.storage.is_static {
+            s.push_str("static ");
+        }
+        s.push_str(&format!("{}, {}, {}", g.name, g.size, g.align));
+        if let Some(init) = &g.init {
+            let bytes: String = init.iter().map(|b| format!("{:02x}", b)).collect();
+            s.push_str(&format!(", {}", bytes));
+        }
+        s.push('\n');
+    }
+    if !prog.globals.is_empty() && !prog.funcs.is_empty() {
+        s.push('\n');
+    }
+    s.push_str(&print(&prog.funcs));
+    s
+}
+
+pub fn print(funcs: &[Function]) -> String {
+    let mut s = String::new();
+    for (i, func) in funcs.iter().enumerate() {
+        if i > 0 {
+            s.push('\n');
+        }
+        s.push_str("def ");
+        if func.storage.is_static {
+            s.push_str("static ");
+        }
+        if func.storage.is_inline {
+            s.push_str("inline ");
+        }
+        s.push_str(&format!("{}, {}", func.name, func.frame));
+        if let Some(used) = func.used {
+            s.push_str(&format!(", {}", used));
+        }
+        s.push('\n');
+        for p in func.params.iter() {
+            s.push_str(&format!("  param.{} {}\n", p.size, p.offset));
+        }
+        for (n, b) in func.blocks.iter().enumerate() {
+            s.push_str(&format!("B{}:\n", n));
+            for ir in b.ins.iter() {
+                s.push_str(&format!("  {}\n", ir));
+            }
+            s.push_str(&format!("  {}\n", b.term));
+        }
+    }
+    s
+}
+
+// one line of the text
+enum Line {
+    Global(Global),
+    Def(Function),
+    Param(Param),
+    Block(usize),
+    Term(Term),
+    Ins(Ir),
+}
+
+// the functions of a program without globals
+pub fn parse(text: &str) -> Result<Vec<Function>, String> {
+    parse_program(text).map(|prog| prog.funcs)
+}
+
+pub fn parse_program(text: &str) -> Result<Program, String> {
+    let mut globals = vec![];
+    let mut funcs: Vec<Function> = vec![];
+    // whether the last block still takes instructions
+    let mut open = false;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |e: &str| format!("line {}: {}", i + 1, e);
+        let line = parse_line(line).map_err(|e| err(&e))?;
+        if let Line::Global(g) = line {
+            if !funcs.is_empty() {
+                return Err(err("global after a function"));
+            }
+            globals.push(g);
+            continue;
+        }
+        if let Line::Def(f) = line {
+            if open {
+                return Err(err("block without a terminator"));
+            }
+            funcs.push(f);
+            continue;
+        }
+        let func = match funcs.last_mut() {
+            Some(func) => func,
+            None => return Err(err("instruction outside a function")),
+        };
+        match line {
+            Line::Param(p) if func.blocks.is_empty() => func.params.push(p),
+            Line::Param(_) => return Err(err("parameter after the first block")),
+            Line::Block(n) if open => {
+                return Err(err(&format!("block B{} starts without a terminator", n)))
+            }
+            Line::Block(n) if n != func.blocks.len() => {
+                return Err(err(&format!("expected B{}", func.blocks.len())))
+            }
+            Line::Block(_) => {
+                func.blocks.push(Block::new(Term::Ret(None, 0)));
+                open = true;
+            }
+            Line::Term(_) | Line::Ins(_) if !open => {
+                return Err(err("instruction outside a block"))
+            }
+            Line::Term(t) => {
+                func.blocks.last_mut().unwrap().term = t;
+                open = false;
+            }
+            Line::Ins(ir) => func.blocks.last_mut().unwrap().ins.push(ir),
+            Line::Global(_) | Line::Def(_) => unreachable!(),
+        }
+    }
+    if open {
+        return Err("block without a terminator at the end".to_string());
+    }
+    for func in funcs.iter_mut() {
+        let n = func.blocks.len();
+        let bad = func
+            .blocks
+            .iter()
+            .flat_map(|b| b.term.succs())
+            .find(|s| *s >= n);
+        if let Some(b) = bad {
+            return Err(format!("'{}' jumps to missing block B{}", func.name, b));
+        }
+        func.compute_edges();
+    }
+    Ok(Program { funcs, globals })
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Term::Jmp(b) => write!(f, "jmp B{}", b),
+            Term::Br(a, then, els) => write!(f, "br {}, B{}, B{}", a, then, els),
+            Term::Ret(Some(a), 8) => write!(f, "ret {}", a),
+            Term::Ret(Some(a), size) => write!(f, "ret.{} {}", size, a),
+            Term::Ret(None, _) => write!(f, "ret"),
+            Term::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Reg(r) => write!(f, "r{}", r),
+            Operand::Imm(n) => write!(f, "{}", n),
+            Operand::Slot(n) => write!(f, "s{}", n),
+            Operand::Global(name) => write!(f, "@{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Ir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.volatile {
+            write!(f, "volatile ")?;
+        }
+        if self.wraps {
+            write!(f, "wrap ")?;
+        }
+        if let Some(r) = self.dst {
+            write!(f, "r{} = ", r)?;
+        }
+        let name = mnemonic(&self.op);
+        write!(f, "{}", name)?;
+        if let Op::Cmp(c) = self.op {
+            let (_, s) = CONDS.iter().find(|(cond, _)| *cond == c).unwrap();
+            write!(f, ".{}", s)?;
+        }
+        if (self.size != 8 || SIZED.contains(&name)) && self.op != Op::Nop {
+            write!(f, ".{}", self.size)?;
+        }
+        let args: Vec<String> = match &self.op {
+            Op::Phi(preds) => preds
+                .iter()
+                .zip(self.args.iter())
+                .map(|(b, a)| format!("B{}: {}", b, a))
+                .collect(),
+            _ => self.args.iter().map(|a| a.to_string()).collect(),
+        };
+        if !args.is_empty() {
+            write!(f, " {}", args.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn mnemonic(op: &Op) -> &'static str {
+    match op {
+        Op::Add => "add",
+        Op::Sub => "sub",
+        Op::Mul => "mul",
+        Op::Div => "div",
+        Op::Udiv => "udiv",
+        Op::Mod => "mod",
+        Op::Umod => "umod",
+        Op::And => "and",
+        Op::Or => "or",
+        Op::Xor => "xor",
+        Op::Shl => "shl",
+        Op::Shr => "shr",
+        Op::Sar => "sar",
+        Op::Mulh => "mulh",
+        Op::Umulh => "umulh",
+        Op::Cmp(_) => "cmp",
+        Op::Neg => "neg",
+        Op::Mov => "mov",
+        Op::Load => "load",
+        Op::LoadU => "loadu",
+        Op::Store => "store",
+        Op::Sext => "sext",
+        Op::Zext => "zext",
+        Op::Push => "push",
+        Op::SpAdd => "spadd",
+        Op::Save => "save",
+        Op::Restore => "restore",
+        Op::Call => "call",
+        Op::Kill => "kill",
+        Op::Nop => "nop",
+        Op::Phi(_) => "phi",
+    }
+}
+
+// The operation a mnemonic names, its size, whether it has a destination
+// and how many operands it takes; calls take at least one.
+fn op(mnemonic: &str) -> Result<(Op, usize, bool, usize), String> {
+    let mut parts = mnemonic.split('.');
+    let name = parts.next().unwrap_or("");
+    let mut suffixes: Vec<&str> = parts.collect();
+    let op = match name {
+        "add" => Op::Add,
+        "sub" => Op::Sub,
+        "mul" => Op::Mul,
+        "div" => Op::Div,
+        "udiv" => Op::Udiv,
+        "mod" => Op::Mod,
+        "umod" => Op::Umod,
+        "and" => Op::And,
+        "or" => Op::Or,
+        "xor" => Op::Xor,
+        "shl" => Op::Shl,
+        "shr" => Op::Shr,
+        "sar" => Op::Sar,
+        "mulh" => Op::Mulh,
+        "umulh" => Op::Umulh,
+        "cmp" => {
+            let cond = match suffixes.first() {
+                Some(s) => CONDS.iter().find(|(_, c)| c == s),
+                None => None,
+            };
+            match cond {
+                Some((c, _)) => {
+                    suffixes.remove(0);
+                    Op::Cmp(*c)
+                }
+                None => return Err("'cmp' needs a condition".to_string()),
+            }
+        }
+        "neg" => Op::Neg,
+        "mov" => Op::Mov,
+        "load" => Op::Load,
+        "loadu" => Op::LoadU,
+        "store" => Op::Store,
+        "sext" => Op::Sext,
+        "zext" => Op::Zext,
+        "push" => Op::Push,
+        "spadd" => Op::SpAdd,
+        "save" => Op::Save,
+        "restore" => Op::Restore,
+        "call" => Op::Call,
+        "kill" => Op::Kill,
+        "nop" => Op::Nop,
+        "phi" => Op::Phi(vec![]),
+        _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+    };
+    let size = match suffixes.as_slice() {
+        [] if !SIZED.contains(&name) => 8,
+        [s] => width(s, name)?,
+        [] => return Err(format!("'{}' needs a size of 1, 2, 4 or 8", name)),
+        _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+    };
+    let (dst, n) = match op {
+        Op::Store => (false, 2),
+        Op::Push | Op::SpAdd | Op::Save | Op::Kill => (false, 1),
+        Op::Nop => (false, 0),
+        Op::Restore => (true, 0),
+        Op::Neg | Op::Mov | Op::Load | Op::LoadU | Op::Sext | Op::Zext | Op::Call => (true, 1),
+        Op::Phi(_) => (true, 0),
+        _ => (true, 2),
+    };
+    Ok((op, size, dst, n))
+}
+
+fn parse_line(line: &str) -> Result<Line, String> {
+    if let Some(label) = line.strip_suffix(':') {
+        return Ok(Line::Block(block(label)?));
+    }
+    let (volatile, line) = match line.strip_prefix("volatile ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, line),
+    };
+    let (wraps, line) = match line.strip_prefix("wrap ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, line),
+    };
+    let (dst, line) = match line.find('=') {
+        Some(i) => (Some(prefixed(line[..i].trim(), 'r')?), line[i + 1..].trim()),
+        None => (None, line),
+    };
+    let (mnemonic, rest) = match line.find(' ') {
+        Some(i) => (&line[..i], line[i + 1..].trim()),
+        None => (line, ""),
+    };
+    let args = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',').map(|s| s.trim()).collect()
+    };
+    if dst.is_none() {
+        match mnemonic {
+            "def" => return def(rest),
+            "global" => return global(rest),
+            "jmp" if args.len() == 1 => return Ok(Line::Term(Term::Jmp(block(args[0])?))),
+            "br" if args.len() == 3 => {
+                let t = Term::Br(operand(args[0])?, block(args[1])?, block(args[2])?);
+                return Ok(Line::Term(t));
+            }
+            "ret" if args.is_empty() => return Ok(Line::Term(Term::Ret(None, 0))),
+            "unreachable" if args.is_empty() => return Ok(Line::Term(Term::Unreachable)),
+            "ret" if args.len() == 1 => {
+                return Ok(Line::Term(Term::Ret(Some(operand(args[0])?), 8)))
+            }
+            "jmp" | "br" | "ret" | "unreachable" => {
+                return Err(format!("wrong number of operands to '{}'", mnemonic))
+            }
+            _ => {}
+        }
+        if let Some(size) = mnemonic.strip_prefix("ret.") {
+            let size = width(size, "ret")?;
+            match args.as_slice() {
+                [a] => return Ok(Line::Term(Term::Ret(Some(operand(a)?), size))),
+                _ => return Err("wrong number of operands to 'ret'".to_string()),
+            }
+        }
+        if let Some(size) = mnemonic.strip_prefix("param.") {
+            return Ok(Line::Param(Param {
+                offset: int(rest)? as isize,
+                size: int(size)? as usize,
+            }));
+        }
+    }
+    let (op, size, has_dst, n) = op(mnemonic)?;
+    if has_dst != dst.is_some() {
+        let what = if has_dst { "needs" } else { "takes no" };
+        return Err(format!("'{}' {} destination", mnemonic, what));
+    }
+    let variadic = matches!(op, Op::Call | Op::Phi(_));
+    if args.len() != n && !(variadic && args.len() > n) {
+        return Err(format!("wrong number of operands to '{}'", mnemonic));
+    }
+    let mut preds = vec![];
+    let mut v = vec![];
+    for a in args {
+        if let Op::Phi(_) = op {
+            match a.find(':') {
+                Some(i) => {
+                    preds.push(block(a[..i].trim())?);
+                    v.push(operand(a[i + 1..].trim())?);
+                }
+                None => return Err(format!("expected 'Bn: A', got '{}'", a)),
+            }
+        } else {
+            v.push(operand(a)?);
+        }
+    }
+    let op = match op {
+        Op::Phi(_) => Op::Phi(preds),
+        op => op,
+    };
+    let mut ir = Ir::new(op, dst, v, size);
+    ir.volatile = volatile;
+    ir.wraps = wraps;
+    Ok(Line::Ins(ir))
+}
+
+// `[static] [inline] NAME, FRAME[, USED]`
+fn def(rest: &str) -> Result<Line, String> {
+    let (is_static, rest) = match rest.strip_prefix("static ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, rest),
+    };
+    let (is_inline, rest) = match rest.strip_prefix("inline ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, rest),
+    };
+    let parts: Vec<&str> = rest.split(',').map(|s| s.trim()).collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err("expected 'def NAME, FRAME'".to_string());
+    }
+    let used = match parts.get(2) {
+        Some(s) => Some(int(s)? as isize),
+        None => None,
+    };
+    Ok(Line::Def(Function {
+        name: ident(parts[0])?,
+        storage: Storage {
+            is_static,
+            is_inline,
+        },
+        params: vec![],
+        frame: int(parts[1])? as isize,
+        used,
+        blocks: vec![],
+    }))
+}
+
+// `[static] NAME, SIZE, ALIGN[, BYTES]`
+fn global(rest: &str) -> Result<Line, String> {
+    let (is_static, rest) = match rest.strip_prefix("static ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, rest),
+    };
+    let parts: Vec<&str> = rest.split(',').map(|s| s.trim()).collect();
+    if parts.len() < 3 || parts.len() > 4 {
+        return Err("expected 'global NAME, SIZE, ALIGN'".to_string());
+    }
+    let init = match parts.get(3) {
+        Some(s) => {
+            let hex = |i: usize| u8::from_str_radix(s.get(i..i + 2).unwrap_or("?"), 16);
+            let bytes: Result<Vec<u8>, _> = (0..s.len()).step_by(2).map(hex).collect();
+            match bytes {
+                Ok(bytes) => Some(bytes),
+                Err(_) => return Err(format!("expected bytes in hex, got '{}'", s)),
+            }
+        }
+        None => None,
+    };
+    Ok(Line::Global(Global {
+        name: ident(parts[0])?,
+        size: int(parts[1])? as usize,
+        align: int(parts[2])? as usize,
+        init,
+        storage: Storage {
+            is_static,
+            is_inline: false,
+        },
+    }))
+}
+
+fn operand(s: &str) -> Result<Operand, String> {
+    if let Some(name) = s.strip_prefix('@') {
+        return Ok(Operand::Global(ident(name)?));
+    }
+    match s.chars().next() {
+        Some('r') => Ok(Operand::Reg(prefixed(s, 'r')?)),
+        Some('s') => Ok(Operand::Slot(prefixed(s, 's')?)),
+        _ => Ok(Operand::Imm(int(s)?)),
+    }
+}
+
+fn ident(s: &str) -> Result<String, String> {
+    let valid = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    if s.is_empty() || !s.chars().all(valid) {
+        return Err(format!("invalid name '{}'", s));
+    }
+    Ok(s.to_string())
+}
+
+fn block(s: &str) -> Result<usize, String> {
+    Ok(prefixed(s, 'B')? as usize)
+}
+
+// a register `rN`, a slot `sN` or a block `BN`
+fn prefixed(s: &str, prefix: char) -> Result<isize, String> {
+    match s.strip_prefix(prefix).map(|n| n.parse()) {
+        Some(Ok(n)) if n >= 0 => Ok(n),
+        _ => Err(format!("expected '{}N', got '{}'", prefix, s)),
+    }
+}
+
+// the size suffix of an operation on a value in a register
+fn width(s: &str, name: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(n @ 1) | Ok(n @ 2) | Ok(n @ 4) | Ok(n @ 8) => Ok(n),
+        _ => Err(format!("'{}' needs a size of 1, 2, 4 or 8", name)),
+    }
+}
+
+fn int(s: &str) -> Result<i64, String> {
+    s.parse()
+        .map_err(|_| format!("expected an integer, got '{}'", s))
+}
+
+#[cfg(test)]
+fn compile(code: &str) -> Program {
+    use gen_ir::GenIr;
+    use lexer::Lexer;
+    use parser::Parser;
+    use sema::Sema;
+    let tokens = Lexer::new(code).run().unwrap();
+    let nodes = Parser::new().run(tokens).unwrap();
+    let nodes = Sema::new().run(nodes).unwrap();
+    GenIr::new().run(&nodes).unwrap()
+}
+
+#[test]
+fn print_test() {
+    let funcs = compile("int f(int *p) { if (*p) return 1; return -*p; }").funcs;
+    assert_eq!(
+        print(&funcs),
+        "def f, 16
+  param.8 8
+B0:
+  r0 = load.8 s8
+  r0 = load.4 r0
+  br r0, B1, B2
+B1:
+  r1 = mov 1
+  ret.4 r1
+B2:
+  r2 = load.8 s8
+  r2 = load.4 r2
+  r2 = neg.4 r2
+  r2 = sext.4 r2
+  ret.4 r2
+B3:
+  jmp B2
+B4:
+  ret
+"
+    );
+}
+
+#[test]
+fn round_trip_test() {
+    use regalloc::RegAlloc;
+    use ssa::to_ssa;
+    let code = "struct s { unsigned a : 3; int b; };
+        static int g;
+        long k = -2;
+        int h(int a, int b, int c, int d, int e, int f, int x) { return a + x; }
+        void v(void) { return; }
+        int f(volatile struct s *p, int (*fp)(int)) {
+            static short n = 3;
+            for (int i = 0; i < 4; i += 1) { p->a = i; n %= fp(i) >> 1; }
+            v();
+            while (p->b <= n) g = (char)n;
+            return h(1, 2, 3, 4, 5, 6, g) == (unsigned)n - 1;
+        }";
+    let prog = compile(code);
+    let text = print_program(&prog);
+    assert_eq!(parse_program(&text), Ok(prog));
+    assert!(text.starts_with(
+        "global static g, 4, 4
+global k, 8, 8, feffffffffffffff
+global static n.0, 2, 2, 0300
+"
+    ));
+    assert!(text.contains("volatile r"));
+    assert!(text.contains("wrap r"));
+    assert!(text.contains("= loadu.4 r"));
+    assert!(text.contains("call @h,"));
+    assert!(text.contains("push s"));
+
+    let mut prog = compile(code);
+    for f in prog.funcs.iter_mut() {
+        to_ssa(f);
+    }
+    let text = print_program(&prog);
+    assert_eq!(parse_program(&text), Ok(prog));
+    assert!(text.contains(" = phi B"));
+
+    let prog = RegAlloc::new().run(parse_program(&text).unwrap()).unwrap();
+    let text = print_program(&prog);
+    assert_eq!(parse_program(&text), Ok(prog));
+    assert!(text.contains("save r0"));
+}
+
+#[test]
+fn parse_test() {
+    let text = "
+        ; hand-written
+        def static inline f, 0, 5
+          param.4 8
+        B0:
+          r3 = mov -2   ; a comment
+          r3 = call @g, r3, r4
+          volatile store.2 s16, @h
+          r1 = cmp.ult.4 r3, 7
+          jmp B1
+        B1:
+          ret.4 r1
+        def main, 16
+        B0:
+          nop
+          ret 0
+    ";
+    let funcs = parse(text).unwrap();
+    assert_eq!(funcs.len(), 2);
+    let f = &funcs[0];
+    assert_eq!(
+        f.storage,
+        Storage {
+            is_static: true,
+            is_inline: true
+        }
+    );
+    assert_eq!((f.frame, f.used), (0, Some(5)));
+    assert_eq!(f.params, vec![Param { offset: 8, size: 4 }]);
+    let mut store = Ir::new(
+        Op::Store,
+        None,
+        vec![Operand::Slot(16), Operand::Global("h".to_string())],
+        2,
+    );
+    store.volatile = true;
+    assert_eq!(
+        f.blocks[0].ins,
+        vec![
+            Ir::new(Op::Mov, Some(3), vec![Operand::Imm(-2)], 8),
+            Ir::new(
+                Op::Call,
+                Some(3),
+                vec![
+                    Operand::Global("g".to_string()),
+                    Operand::Reg(3),
+                    Operand::Reg(4)
+                ],
+                8
+            ),
+            store,
+            Ir::new(
+                Op::Cmp(Cond::Ult),
+                Some(1),
+                vec![Operand::Reg(3), Operand::Imm(7)],
+                4
+            ),
+        ]
+    );
+    assert_eq!(f.blocks[0].term, Term::Jmp(1));
+    assert_eq!(f.blocks[1].preds, vec![0]);
+    assert_eq!(f.blocks[1].term, Term::Ret(Some(Operand::Reg(1)), 4));
+    assert_eq!(funcs[1].blocks[0].term, Term::Ret(Some(Operand::Imm(0)), 8));
+    assert_eq!(
+        parse("r0 = mov 1"),
+        Err("line 1: instruction outside a function".to_string())
+    );
+    assert_eq!(
+        parse("global g, 2, 2, 0g00"),
+        Err("line 1: expected bytes in hex, got '0g00'".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  ret\nglobal g, 4, 4"),
+        Err("line 4: global after a function".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  r0 = add r0"),
+        Err("line 3: wrong number of operands to 'add'".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  r0 = load r1"),
+        Err("line 3: 'load' needs a size of 1, 2, 4 or 8".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  ret.16 r0"),
+        Err("line 3: 'ret' needs a size of 1, 2, 4 or 8".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  ret.3 r0"),
+        Err("line 3: 'ret' needs a size of 1, 2, 4 or 8".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  add r0, r1"),
+        Err("line 3: 'add' needs destination".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  r0 = store.4 r0, r1"),
+        Err("line 3: 'store.4' takes no destination".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  jmp r1"),
+        Err("line 3: expected 'BN', got 'r1'".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  r0 = mov x"),
+        Err("line 3: expected an integer, got 'x'".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  frob r1"),
+        Err("line 3: unknown instruction 'frob'".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  ret\n  nop"),
+        Err("line 4: instruction outside a block".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB1:\n  ret"),
+        Err("line 2: expected B0".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  jmp B1"),
+        Err("'f' jumps to missing block B1".to_string())
+    );
+    assert_eq!(
+        parse("def f, 0\nB0:\n  nop"),
+        Err("block without a terminator at the end".to_string())
+    );
+}
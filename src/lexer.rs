@@ -1,5 +1,3 @@
-use node;
-
 #[derive(Debug, PartialEq)]
 pub enum Token {
     EOF,
@@ -7,17 +5,52 @@ pub enum Token {
     Ident(String),
     Ctype(String),
     Equal,
+    EqualEqual,
+    NotEqual,
+    LessThan,
+    LessEqual,
+    GreaterThan,
+    GreaterEqual,
     Plus,
     Minus,
     Asterisk,
     Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    LeftShift,
+    RightShift,
+    PlusEqual,
+    MinusEqual,
+    AsteriskEqual,
+    SlashEqual,
+    PercentEqual,
+    AmpersandEqual,
+    PipeEqual,
+    CaretEqual,
+    LeftShiftEqual,
+    RightShiftEqual,
     Comma,
     SemiColon,
     LeftParen,
     RightParen,
     LeftCurlyBrace,
     RightCurlyBrace,
+    LeftSquareBracket,
+    RightSquareBracket,
     Return,
+    If,
+    Else,
+    While,
+    For,
+    Sizeof,
+    Static,
+    Inline,
+    Struct,
+    Dot,
+    Arrow,
+    Colon,
 }
 
 #[derive(Debug, PartialEq)]
@@ -36,6 +69,7 @@ impl Lexer {
         }
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn run(mut self) -> Result<Vec<Token>, ()> {
         while !self.is_eof() {
             self = self.token()?;
@@ -49,8 +83,8 @@ impl Lexer {
     fn token(self) -> Result<Self, ()> {
         if !self.is_eof() {
             match self.peek()? {
-                'a'...'z' | 'A'...'Z' => self.keyword_identifier(),
-                '0'...'9' => self.num(),
+                'a'..='z' | 'A'..='Z' | '_' => self.keyword_identifier(),
+                '0'..='9' => self.num(),
                 '\n' | '\t' | ' ' => self.step().token(),
                 _ => self.symbol(),
             }
@@ -85,7 +119,16 @@ impl Lexer {
     fn keyword(s: &str) -> Option<Token> {
         match s {
             "return" => Some(Token::Return),
-            "int" => Some(Token::Ctype(s.to_string())),
+            "if" => Some(Token::If),
+            "else" => Some(Token::Else),
+            "while" => Some(Token::While),
+            "for" => Some(Token::For),
+            "sizeof" => Some(Token::Sizeof),
+            "static" => Some(Token::Static),
+            "inline" => Some(Token::Inline),
+            "struct" => Some(Token::Struct),
+            "void" | "_Bool" | "char" | "short" | "int" | "long" | "signed" | "unsigned"
+            | "const" | "volatile" | "restrict" => Some(Token::Ctype(s.to_string())),
             _ => None,
         }
     }
@@ -95,18 +138,65 @@ impl Lexer {
     }
 
     fn symbol(mut self) -> Result<Self, ()> {
+        let three: String = self.code[self.pos..].chars().take(3).collect();
+        let token = match three.as_str() {
+            "<<=" => Some(Token::LeftShiftEqual),
+            ">>=" => Some(Token::RightShiftEqual),
+            _ => None,
+        };
+        if let Some(token) = token {
+            self = self.step().step().step();
+            self.tokens.push(token);
+            return Ok(self);
+        }
+
+        let two: String = self.code[self.pos..].chars().take(2).collect();
+        let token = match two.as_str() {
+            "==" => Some(Token::EqualEqual),
+            "!=" => Some(Token::NotEqual),
+            "<=" => Some(Token::LessEqual),
+            ">=" => Some(Token::GreaterEqual),
+            "->" => Some(Token::Arrow),
+            "<<" => Some(Token::LeftShift),
+            ">>" => Some(Token::RightShift),
+            "+=" => Some(Token::PlusEqual),
+            "-=" => Some(Token::MinusEqual),
+            "*=" => Some(Token::AsteriskEqual),
+            "/=" => Some(Token::SlashEqual),
+            "%=" => Some(Token::PercentEqual),
+            "&=" => Some(Token::AmpersandEqual),
+            "|=" => Some(Token::PipeEqual),
+            "^=" => Some(Token::CaretEqual),
+            _ => None,
+        };
+        if let Some(token) = token {
+            self = self.step().step();
+            self.tokens.push(token);
+            return Ok(self);
+        }
+
         let token = match self.peek()? {
             '+' => Token::Plus,
             '-' => Token::Minus,
             '*' => Token::Asterisk,
             '/' => Token::Slash,
+            '%' => Token::Percent,
+            '&' => Token::Ampersand,
+            '|' => Token::Pipe,
+            '^' => Token::Caret,
             ';' => Token::SemiColon,
             ',' => Token::Comma,
+            '.' => Token::Dot,
+            ':' => Token::Colon,
             '=' => Token::Equal,
+            '<' => Token::LessThan,
+            '>' => Token::GreaterThan,
             '(' => Token::LeftParen,
             ')' => Token::RightParen,
             '{' => Token::LeftCurlyBrace,
             '}' => Token::RightCurlyBrace,
+            '[' => Token::LeftSquareBracket,
+            ']' => Token::RightSquareBracket,
             _ => return Err(()),
         };
         self = self.step();
@@ -237,6 +327,62 @@ fn read_symbol_test() {
     );
 }
 
+#[test]
+fn read_comparison_test() {
+    let a = Lexer::new("a<=b!=c<d");
+    assert_eq!(
+        a.run().unwrap(),
+        vec![
+            Token::Ident("a".to_string()),
+            Token::LessEqual,
+            Token::Ident("b".to_string()),
+            Token::NotEqual,
+            Token::Ident("c".to_string()),
+            Token::LessThan,
+            Token::Ident("d".to_string()),
+            Token::EOF,
+        ]
+    );
+}
+
+#[test]
+fn read_operator_test() {
+    let a = Lexer::new("a<<=b>>c%=d^e|f");
+    assert_eq!(
+        a.run().unwrap(),
+        vec![
+            Token::Ident("a".to_string()),
+            Token::LeftShiftEqual,
+            Token::Ident("b".to_string()),
+            Token::RightShift,
+            Token::Ident("c".to_string()),
+            Token::PercentEqual,
+            Token::Ident("d".to_string()),
+            Token::Caret,
+            Token::Ident("e".to_string()),
+            Token::Pipe,
+            Token::Ident("f".to_string()),
+            Token::EOF,
+        ]
+    );
+}
+
+#[test]
+fn read_keyword_test() {
+    let a = Lexer::new("if else while for iffy");
+    assert_eq!(
+        a.run().unwrap(),
+        vec![
+            Token::If,
+            Token::Else,
+            Token::While,
+            Token::For,
+            Token::Ident("iffy".to_string()),
+            Token::EOF,
+        ]
+    );
+}
+
 #[test]
 fn read_num_test() {
     let a = Lexer::new("12345a");
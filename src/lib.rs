@@ -1,6 +1,21 @@
+pub mod cfg;
+pub mod copyprop;
+pub mod dce;
+pub mod fold;
 pub mod gen_ir;
 pub mod gen_x86;
+pub mod gvn;
+pub mod inliner;
+pub mod interp;
+pub mod ir_text;
 pub mod lexer;
+pub mod liveness;
 pub mod node;
+pub mod opt;
 pub mod parser;
+pub mod preprocess;
 pub mod regalloc;
+pub mod sccp;
+pub mod sema;
+pub mod ssa;
+pub mod strength;
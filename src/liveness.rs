@@ -0,0 +1,98 @@
+// Liveness of virtual registers at block boundaries, found by the usual
+// backward dataflow iteration. A phi reads its argument at the end of
+// the predecessor it comes from, not in its own block.
+
+use std::collections::BTreeSet;
+
+use cfg::Function;
+use gen_ir::{Op, Operand};
+
+#[derive(Debug, PartialEq)]
+pub struct Liveness {
+    pub live_in: Vec<BTreeSet<isize>>,
+    pub live_out: Vec<BTreeSet<isize>>,
+}
+
+impl Liveness {
+    pub fn new(f: &Function) -> Self {
+        let n = f.blocks.len();
+        // registers read before any write in each block, and written
+        let mut gen = vec![BTreeSet::new(); n];
+        let mut defs = vec![BTreeSet::new(); n];
+        // registers read by the phis of the successors, per predecessor
+        let mut phi_uses = vec![BTreeSet::new(); n];
+        for (i, b) in f.blocks.iter().enumerate() {
+            for ir in b.ins.iter() {
+                if let Op::Phi(preds) = &ir.op {
+                    for (p, a) in preds.iter().zip(ir.args.iter()) {
+                        if let Operand::Reg(r) = a {
+                            phi_uses[*p].insert(*r);
+                        }
+                    }
+                } else {
+                    for r in ir.uses() {
+                        if !defs[i].contains(&r) {
+                            gen[i].insert(r);
+                        }
+                    }
+                }
+                defs[i].extend(ir.dst);
+            }
+            if let Some(Operand::Reg(r)) = b.term.arg() {
+                if !defs[i].contains(r) {
+                    gen[i].insert(*r);
+                }
+            }
+        }
+
+        let mut live_in = gen.clone();
+        let mut live_out = phi_uses;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, b) in f.blocks.iter().enumerate().rev() {
+                for s in b.succs.iter() {
+                    for r in live_in[*s].clone() {
+                        changed |= live_out[i].insert(r);
+                    }
+                }
+                for r in live_out[i].difference(&defs[i]) {
+                    changed |= live_in[i].insert(*r);
+                }
+            }
+        }
+        Liveness { live_in, live_out }
+    }
+}
+
+#[test]
+fn liveness_test() {
+    use ir_text::parse;
+    let f = parse(
+        "def f, 0
+        B0:
+          r0 = mov 1
+          r1 = mov 2
+          jmp B1
+        B1:
+          r2 = phi B0: r0, B2: r3
+          r4 = cmp.lt r2, 10
+          br r4, B2, B3
+        B2:
+          r3 = add r2, r1
+          jmp B1
+        B3:
+          ret r2",
+    )
+    .unwrap()
+    .remove(0);
+    let live = Liveness::new(&f);
+    let set = |v: &[isize]| v.iter().cloned().collect::<BTreeSet<_>>();
+    assert_eq!(live.live_out[0], set(&[0, 1]));
+    // the phi defines r2, and r0 and r3 are read on the edges
+    assert_eq!(live.live_in[1], set(&[1]));
+    assert_eq!(live.live_out[1], set(&[1, 2]));
+    assert_eq!(live.live_in[2], set(&[1, 2]));
+    assert_eq!(live.live_out[2], set(&[1, 3]));
+    assert_eq!(live.live_in[3], set(&[2]));
+}
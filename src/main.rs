@@ -3,10 +3,14 @@ use std::env;
 extern crate c;
 use c::gen_ir;
 use c::gen_x86;
+use c::inliner;
+use c::ir_text;
 use c::lexer;
-use c::node;
+use c::opt;
 use c::parser;
+use c::preprocess;
 use c::regalloc;
+use c::sema;
 
 extern crate clap;
 use clap::{App, Arg};
@@ -21,14 +25,58 @@ fn main() {
         .version(VERSION_STR)
         .author("shinkwhek")
         .about("A toy C compiler")
-        .arg(Arg::with_name("file").help("input file name").index(1));
+        .arg(Arg::with_name("file").help("input file name").index(1))
+        .arg(
+            Arg::with_name("emit")
+                .long("emit")
+                .help("output to emit")
+                .takes_value(true)
+                .possible_values(&["asm", "ir"])
+                .default_value("asm"),
+        )
+        .arg(
+            Arg::with_name("opt")
+                .short("O")
+                .help("optimization level")
+                .takes_value(true)
+                .possible_values(&["0", "1"])
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("stop-after")
+                .long("stop-after")
+                .help("last optimization pass to run")
+                .takes_value(true)
+                .possible_values(&opt::PASS_NAMES)
+                .default_value("dce"),
+        )
+        .arg(
+            Arg::with_name("flag")
+                .short("f")
+                .help("code generation flag, such as inline-limit=N")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        );
     let app_matches = app.clone().get_matches();
 
+    let mut inline_limit = inliner::INLINE_LIMIT;
+    for flag in app_matches.values_of("flag").into_iter().flatten() {
+        let limit = flag.strip_prefix("inline-limit=").map(|n| n.parse());
+        match limit {
+            Some(Ok(n)) => inline_limit = n,
+            _ => {
+                eprintln!("error: unknown flag -f{}", flag);
+                ::std::process::exit(1);
+            }
+        }
+    }
+
     if let Some(filename) = app_matches.value_of("file") {
         let mut code = String::new();
         match OpenOptions::new().read(true).open(filename) {
             Ok(mut ok) => {
-                ok.read_to_string(&mut code).ok().expect("cannot read file");
+                ok.read_to_string(&mut code).expect("cannot read file");
             }
             Err(e) => {
                 println!("error: {}", e);
@@ -36,28 +84,50 @@ fn main() {
             }
         }
 
+        let code = match preprocess::Preprocessor::new().run(&code) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("{}: error: {}", filename, e);
+                ::std::process::exit(1);
+            }
+        };
+
         if let Ok(lex) = lexer::Lexer::new(&code).run() {
             //println!("lexer:\n{:?}", lex);
 
-            if let Ok(parse) = parser::Parser::new().run(lex) {
+            let mut parser = parser::Parser::new();
+            let parse = parser.run(lex);
+            if let Some(e) = parser.error {
+                eprintln!("{}: error: {}", filename, e);
+                ::std::process::exit(1);
+            }
+            if let Ok(parse) = parse {
                 //println!("parser:\n{:?}", parse);
 
-                if let Ok(irv) = gen_ir::GenIr::new().run(&parse) {
-                    //println!{"ir:"}
-                    //for ir in &irv {
-                    //    for i in ir {
-                    //        println!("{:?}", i);
-                    //    }
-                    //}
+                let mut sema = sema::Sema::new();
+                let parse = sema.run(parse);
+                for w in sema.warnings.iter() {
+                    eprintln!("{}: warning: {}", filename, w);
+                }
+                let parse = match parse {
+                    Ok(nodes) => nodes,
+                    Err(e) => {
+                        eprintln!("{}: error: {}", filename, e);
+                        ::std::process::exit(1);
+                    }
+                };
 
-                    if let Ok(irv) = regalloc::RegAlloc::new().run(irv) {
-                        //println!("regAlloc:");
-                        //for ir in &irv {
-                        //    for i in ir {
-                        //        println!("{:?}", i);
-                        //    }
-                        //}
+                if let Ok(mut irv) = gen_ir::GenIr::new().run(&parse) {
+                    if app_matches.value_of("opt") != Some("0") {
+                        let last = app_matches.value_of("stop-after").unwrap();
+                        opt::optimize_until(&mut irv, inline_limit, last);
+                    }
+                    if app_matches.value_of("emit") == Some("ir") {
+                        print!("{}", ir_text::print_program(&irv));
+                        return;
+                    }
 
+                    if let Ok(irv) = regalloc::RegAlloc::new().run(irv) {
                         gen_x86::X86::new().emit(&irv);
                     }
                 }
@@ -1,45 +1,602 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
 #[derive(Debug, PartialEq)]
 pub enum NodeBase {
     // value
     Number(usize),
     Ident(String),
+    Lvar(usize),
+    Gvar(String),
+    Func(String),
     Call(String, Vec<Node>),
+    CallPtr(Box<Node>, Vec<Node>),
     // expr
+    UnaryOp(UnOp, Box<Node>),
     BinaryOp(BinOp, Box<Node>, Box<Node>),
+    Assign(Box<Node>, Box<Node>),
+    // `lhs op= rhs`. After sema the rhs is the whole new value, computed
+    // from a `Loaded` leaf that stands for the current value of lhs.
+    OpAssign(BinOp, Box<Node>, Box<Node>),
+    Loaded,
+    Cast(Box<Node>),
+    Deref(Box<Node>),
+    Addr(Box<Node>),
+    Sizeof(Box<Node>),
+    Member(Box<Node>, String),
     // stmt
-    Return(Box<Node>),
+    // `return;` has no value
+    Return(Option<Box<Node>>),
+    If(Box<Node>, Box<Node>, Option<Box<Node>>),
+    While(Box<Node>, Box<Node>),
+    // init, condition, increment and body; a missing condition is true
+    For(
+        Option<Box<Node>>,
+        Option<Box<Node>>,
+        Option<Box<Node>>,
+        Box<Node>,
+    ),
     Statements(Vec<Box<Node>>),
+    VarDef(Ctype, String, Option<Box<Node>>, Storage),
     // def
-    DefFun(Ctype, Box<Node>, Vec<(Ctype, Node)>, Box<Node>),
+    DefFun(
+        Ctype,
+        Box<Node>,
+        Vec<(Ctype, Node)>,
+        Box<Node>,
+        Vec<Var>,
+        Storage,
+    ),
+    DecFun(Ctype, Box<Node>, Vec<(Ctype, Node)>, Storage),
 }
 
-#[derive(Debug, PartialEq)]
+// storage-class specifiers of a declaration, and the function specifier
+// `inline`
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Storage {
+    pub is_static: bool,
+    pub is_inline: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BinOp {
     Add,
     Sub,
     Mul,
     Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
 }
 
 #[derive(Debug, PartialEq)]
+pub enum UnOp {
+    Neg,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Ctype {
+    Void,
+    Bool,
+    Char,
+    UChar,
+    Short,
+    UShort,
     Int,
+    UInt,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
+    Ptr(Box<Ctype>),
+    Array(Box<Ctype>, usize),
+    Func(Box<Ctype>, Vec<Ctype>),
+    Struct(StructRef),
+    // A qualified type. Never nested and never has empty qualifiers;
+    // build it with `Ctype::qualified`.
+    Qual(Box<Ctype>, Quals),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Quals {
+    pub is_const: bool,
+    pub is_volatile: bool,
+    pub is_restrict: bool,
+}
+
+impl Quals {
+    pub fn is_empty(&self) -> bool {
+        !self.is_const && !self.is_volatile && !self.is_restrict
+    }
+
+    // true if every qualifier in `other` is also in `self`
+    pub fn contains(&self, other: Quals) -> bool {
+        (self.is_const || !other.is_const)
+            && (self.is_volatile || !other.is_volatile)
+            && (self.is_restrict || !other.is_restrict)
+    }
+
+    pub fn union(&self, other: Quals) -> Quals {
+        Quals {
+            is_const: self.is_const || other.is_const,
+            is_volatile: self.is_volatile || other.is_volatile,
+            is_restrict: self.is_restrict || other.is_restrict,
+        }
+    }
+}
+
+impl Ctype {
+    pub fn qualified(t: Ctype, quals: Quals) -> Ctype {
+        let quals = quals.union(t.quals());
+        let t = t.unqual().clone();
+        if quals.is_empty() {
+            t
+        } else {
+            Ctype::Qual(Box::new(t), quals)
+        }
+    }
+
+    pub fn unqual(&self) -> &Ctype {
+        match self {
+            Ctype::Qual(t, _) => t,
+            t => t,
+        }
+    }
+
+    pub fn quals(&self) -> Quals {
+        match self {
+            Ctype::Qual(_, q) => *q,
+            _ => Quals::default(),
+        }
+    }
+
+    pub fn is_const(&self) -> bool {
+        self.quals().is_const
+    }
+
+    pub fn is_volatile(&self) -> bool {
+        self.quals().is_volatile
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.rank() > 0
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer()
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self.unqual(), Ctype::Ptr(_))
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.is_arithmetic() || self.is_pointer()
+    }
+
+    // pointed-to type of a pointer
+    pub fn pointee(&self) -> Option<&Ctype> {
+        match self.unqual() {
+            Ctype::Ptr(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self.unqual(),
+            Ctype::Bool
+                | Ctype::UChar
+                | Ctype::UShort
+                | Ctype::UInt
+                | Ctype::ULong
+                | Ctype::ULongLong
+        )
+    }
+
+    // integer conversion rank; 0 for non-integer types
+    pub fn rank(&self) -> usize {
+        match self.unqual() {
+            Ctype::Bool => 1,
+            Ctype::Char | Ctype::UChar => 2,
+            Ctype::Short | Ctype::UShort => 3,
+            Ctype::Int | Ctype::UInt => 4,
+            Ctype::Long | Ctype::ULong => 5,
+            Ctype::LongLong | Ctype::ULongLong => 6,
+            _ => 0,
+        }
+    }
+
+    pub fn to_unsigned(&self) -> Ctype {
+        match self.unqual() {
+            Ctype::Char => Ctype::UChar,
+            Ctype::Short => Ctype::UShort,
+            Ctype::Int => Ctype::UInt,
+            Ctype::Long => Ctype::ULong,
+            Ctype::LongLong => Ctype::ULongLong,
+            t => t.clone(),
+        }
+    }
+
+    // sizes follow the LP64 data model
+    pub fn size(&self) -> usize {
+        match self {
+            // void is an incomplete type
+            Ctype::Void => 0,
+            Ctype::Bool | Ctype::Char | Ctype::UChar => 1,
+            Ctype::Short | Ctype::UShort => 2,
+            Ctype::Int | Ctype::UInt => 4,
+            Ctype::Long | Ctype::ULong | Ctype::LongLong | Ctype::ULongLong => 8,
+            Ctype::Ptr(_) => 8,
+            Ctype::Array(t, len) => t.size() * len,
+            Ctype::Func(..) => 1,
+            Ctype::Struct(s) => s.body().map_or(0, |b| b.size),
+            Ctype::Qual(t, _) => t.size(),
+        }
+    }
+
+    pub fn align(&self) -> usize {
+        match self.unqual() {
+            Ctype::Array(t, _) => t.align(),
+            Ctype::Struct(s) => s.body().map_or(1, |b| b.align),
+            t => t.size(),
+        }
+    }
+
+    // member of a complete struct type
+    pub fn member(&self, name: &str) -> Option<Member> {
+        match self.unqual() {
+            Ctype::Struct(s) => s.body()?.member(name).cloned(),
+            _ => None,
+        }
+    }
+}
+
+// A struct type. Struct types are the same type iff they have the same
+// `id`. The body is filled in once the definition is complete, so members
+// can point to the struct itself.
+#[derive(Clone)]
+pub struct StructRef {
+    pub id: usize,
+    pub tag: String,
+    body: Rc<RefCell<Option<Rc<StructBody>>>>,
+}
+
+impl StructRef {
+    pub fn new(id: usize, tag: &str) -> StructRef {
+        StructRef {
+            id,
+            tag: tag.to_string(),
+            body: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    // `None` while the struct is incomplete
+    pub fn body(&self) -> Option<Rc<StructBody>> {
+        self.body.borrow().clone()
+    }
+
+    pub fn define(&self, body: StructBody) {
+        *self.body.borrow_mut() = Some(Rc::new(body));
+    }
+}
+
+impl PartialEq for StructRef {
+    fn eq(&self, other: &StructRef) -> bool {
+        self.id == other.id
+    }
+}
+
+impl fmt::Debug for StructRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "struct {}#{}", self.tag, self.id)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Member {
+    // empty for unnamed bit-fields
+    pub name: String,
+    pub ctype: Ctype,
+    // byte offset; for a bit-field, the offset of its storage unit, which
+    // has the size of the declared type
+    pub offset: usize,
+    pub bits: Option<BitField>,
+}
+
+// position of a bit-field inside its storage unit
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BitField {
+    pub offset: usize,
+    pub width: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructBody {
+    pub members: Vec<Member>,
+    pub size: usize,
+    pub align: usize,
+}
+
+impl StructBody {
+    // Lays out members the way GCC does on x86-64: a bit-field is packed
+    // right after the previous member unless it would cross a boundary of
+    // its declared type, a zero-width bit-field pads to the next such
+    // boundary, and unnamed bit-fields do not affect the struct alignment.
+    // Invalid widths are laid out anyway; sema reports them.
+    pub fn new(decls: Vec<(Ctype, String, Option<usize>)>) -> StructBody {
+        let round_up = |n: usize, align: usize| n.div_ceil(align) * align;
+        let mut members = vec![];
+        let mut bitpos: usize = 0;
+        let mut align = 1;
+        for (t, name, width) in decls {
+            let talign = t.align().max(1);
+            match width {
+                None => {
+                    let offset = round_up(bitpos.div_ceil(8), talign);
+                    bitpos = (offset + t.size()) * 8;
+                    align = align.max(talign);
+                    members.push(Member {
+                        name,
+                        ctype: t,
+                        offset,
+                        bits: None,
+                    });
+                }
+                Some(0) => bitpos = round_up(bitpos, talign * 8),
+                Some(width) => {
+                    let unit = t.size().max(1) * 8;
+                    if bitpos / unit != (bitpos + width - 1) / unit {
+                        bitpos = round_up(bitpos, unit);
+                    }
+                    let start = bitpos / unit * unit;
+                    let bits = BitField {
+                        offset: bitpos - start,
+                        width,
+                    };
+                    bitpos += width;
+                    if !name.is_empty() {
+                        align = align.max(talign);
+                    }
+                    members.push(Member {
+                        name,
+                        ctype: t,
+                        offset: start / 8,
+                        bits: Some(bits),
+                    });
+                }
+            }
+        }
+        StructBody {
+            members,
+            size: round_up(bitpos.div_ceil(8), align),
+            align,
+        }
+    }
+
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        self.members
+            .iter()
+            .find(|m| !m.name.is_empty() && m.name == name)
+    }
+}
+
+// local variable resolved by sema. `Lvar(i)` refers to the i-th entry of
+// the enclosing function's `Var` list.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Var {
+    pub name: String,
+    pub ctype: Ctype,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Node {
     pub base: NodeBase,
+    pub ctype: Option<Ctype>,
 }
 
 impl Node {
     pub fn new(base: NodeBase) -> Node {
-        Node { base: base }
+        Node { base, ctype: None }
     }
 
-    pub fn ctype(s: &str) -> Result<Ctype, ()> {
-        match s {
-            "int" => Ok(Ctype::Int),
-            _ => Err(()),
+    pub fn typed(base: NodeBase, ctype: Ctype) -> Node {
+        Node {
+            base,
+            ctype: Some(ctype),
         }
     }
+
+    pub fn ty(&self) -> &Ctype {
+        self.ctype.as_ref().expect("node is not typed")
+    }
+
+    // position of the bit-field a typed member access refers to
+    pub fn bit_field(&self) -> Option<BitField> {
+        match &self.base {
+            NodeBase::Member(e, name) => e.ty().member(name)?.bits,
+            _ => None,
+        }
+    }
+
+    // build a type from a list of type specifiers and qualifiers such as
+    // ["const", "unsigned", "long", "int"]. Order does not matter.
+    #[allow(clippy::result_unit_err)]
+    pub fn ctype(specs: &[String]) -> Result<Ctype, ()> {
+        let quals = Node::quals(specs);
+        if quals.is_restrict {
+            // restrict only applies to pointer types
+            return Err(());
+        }
+        let specs: Vec<String> = specs
+            .iter()
+            .filter(|s| !Node::is_qualifier(s))
+            .cloned()
+            .collect();
+        Ok(Ctype::qualified(Node::base_ctype(&specs)?, quals))
+    }
+
+    pub fn is_qualifier(s: &str) -> bool {
+        matches!(s, "const" | "volatile" | "restrict")
+    }
+
+    pub fn quals(specs: &[String]) -> Quals {
+        Quals {
+            is_const: specs.iter().any(|s| s == "const"),
+            is_volatile: specs.iter().any(|s| s == "volatile"),
+            is_restrict: specs.iter().any(|s| s == "restrict"),
+        }
+    }
+
+    fn base_ctype(specs: &[String]) -> Result<Ctype, ()> {
+        let count = |k: &str| specs.iter().filter(|s| *s == k).count();
+        let signed = count("signed");
+        let unsigned = count("unsigned");
+        let c = count("char");
+        let s = count("short");
+        let i = count("int");
+        let l = count("long");
+        if specs.len() == 1 && specs[0] == "_Bool" {
+            return Ok(Ctype::Bool);
+        }
+        if specs.len() == 1 && specs[0] == "void" {
+            return Ok(Ctype::Void);
+        }
+        if specs.is_empty() || signed + unsigned > 1 || i > 1 {
+            return Err(());
+        }
+        if c + s + i + l + signed + unsigned != specs.len() {
+            return Err(());
+        }
+        let t = match (c, s, i, l) {
+            (1, 0, 0, 0) => Ctype::Char,
+            (0, 1, _, 0) => Ctype::Short,
+            (0, 0, _, 0) => Ctype::Int,
+            (0, 0, _, 1) => Ctype::Long,
+            (0, 0, _, 2) => Ctype::LongLong,
+            _ => return Err(()),
+        };
+        if unsigned == 1 {
+            Ok(t.to_unsigned())
+        } else {
+            Ok(t)
+        }
+    }
+}
+
+#[cfg(test)]
+fn specs(s: &str) -> Vec<String> {
+    s.split(' ').map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn ctype_specifiers_test() {
+    assert_eq!(Node::ctype(&specs("int")), Ok(Ctype::Int));
+    assert_eq!(Node::ctype(&specs("unsigned")), Ok(Ctype::UInt));
+    assert_eq!(Node::ctype(&specs("signed char")), Ok(Ctype::Char));
+    assert_eq!(Node::ctype(&specs("unsigned char")), Ok(Ctype::UChar));
+    assert_eq!(Node::ctype(&specs("short int")), Ok(Ctype::Short));
+    assert_eq!(Node::ctype(&specs("long unsigned int")), Ok(Ctype::ULong));
+    assert_eq!(Node::ctype(&specs("long long")), Ok(Ctype::LongLong));
+    assert_eq!(
+        Node::ctype(&specs("unsigned long long")),
+        Ok(Ctype::ULongLong)
+    );
+    assert_eq!(Node::ctype(&specs("_Bool")), Ok(Ctype::Bool));
+    assert_eq!(Node::ctype(&specs("unsigned _Bool")), Err(()));
+    assert_eq!(Node::ctype(&specs("signed unsigned")), Err(()));
+    assert_eq!(Node::ctype(&specs("short long")), Err(()));
+    assert_eq!(Node::ctype(&specs("char int")), Err(()));
+}
+
+#[test]
+fn ctype_qualifier_test() {
+    let c = Node::ctype(&specs("const int")).unwrap();
+    assert!(c.is_const());
+    assert_eq!(c.unqual(), &Ctype::Int);
+    let cv = Node::ctype(&specs("volatile unsigned const")).unwrap();
+    assert!(cv.is_const() && cv.is_volatile());
+    assert_eq!(cv.unqual(), &Ctype::UInt);
+    assert_eq!(Ctype::qualified(c.clone(), Node::quals(&specs("const"))), c);
+    assert_eq!(Node::ctype(&specs("restrict int")), Err(()));
+}
+
+#[cfg(test)]
+fn layout(decls: &[(Ctype, Option<usize>)]) -> (Vec<(usize, Option<BitField>)>, usize, usize) {
+    let decls = decls
+        .iter()
+        .enumerate()
+        .map(|(i, (t, w))| (t.clone(), format!("m{}", i), *w))
+        .collect();
+    let body = StructBody::new(decls);
+    let members = body.members.iter().map(|m| (m.offset, m.bits)).collect();
+    (members, body.size, body.align)
+}
+
+#[test]
+fn struct_layout_test() {
+    let bits = |offset, width| Some(BitField { offset, width });
+    assert_eq!(
+        layout(&[
+            (Ctype::Char, None),
+            (Ctype::Int, None),
+            (Ctype::Short, None)
+        ]),
+        (vec![(0, None), (4, None), (8, None)], 12, 4)
+    );
+    // struct { char a; int b : 4; } packs b right after a
+    assert_eq!(
+        layout(&[(Ctype::Char, None), (Ctype::Int, Some(4))]),
+        (vec![(0, None), (0, bits(8, 4))], 4, 4)
+    );
+    // a field that would cross an int boundary starts a new unit
+    assert_eq!(
+        layout(&[(Ctype::UInt, Some(30)), (Ctype::UInt, Some(3))]),
+        (vec![(0, bits(0, 30)), (4, bits(0, 3))], 8, 4)
+    );
+    // a zero-width field pads to the next boundary of its type
+    assert_eq!(
+        layout(&[
+            (Ctype::Int, Some(3)),
+            (Ctype::Int, Some(0)),
+            (Ctype::Int, Some(3))
+        ]),
+        (vec![(0, bits(0, 3)), (4, bits(0, 3))], 8, 4)
+    );
+    assert_eq!(
+        layout(&[
+            (Ctype::UChar, Some(4)),
+            (Ctype::UChar, Some(4)),
+            (Ctype::UShort, Some(9))
+        ]),
+        (
+            vec![(0, bits(0, 4)), (0, bits(4, 4)), (2, bits(0, 9))],
+            4,
+            2
+        )
+    );
+    assert_eq!(
+        layout(&[(Ctype::Long, Some(40)), (Ctype::Int, Some(20))]),
+        (vec![(0, bits(0, 40)), (4, bits(8, 20))], 8, 8)
+    );
+}
+
+#[test]
+fn ctype_size_test() {
+    assert_eq!(Ctype::Char.size(), 1);
+    assert_eq!(Ctype::UShort.size(), 2);
+    assert_eq!(Ctype::Int.size(), 4);
+    assert_eq!(Ctype::Long.size(), 8);
+    assert_eq!(Ctype::ULongLong.size(), 8);
+    assert_eq!(Ctype::Ptr(Box::new(Ctype::Char)).size(), 8);
 }
@@ -0,0 +1,96 @@
+// The optimization pipeline, run on each function between `GenIr` and
+// `RegAlloc` after calls are inlined with a size limit of
+// `inline_limit`. Functions are left in SSA form; the allocator takes
+// them out of it. `--stop-after` ends the pipeline early, so that
+// `--emit=ir` shows what a pass did, such as the constants and
+// unreachable blocks `sccp` found before `dce` removes them.
+
+use cfg::Function;
+use copyprop::copy_prop;
+use dce::dce;
+use fold::fold;
+use gen_ir::Program;
+use gvn::gvn;
+use inliner::inline;
+use sccp::sccp;
+use ssa::to_ssa;
+use strength::reduce;
+
+type Pass = fn(&mut Function);
+
+// the passes run on each function, in order
+const PASSES: [(&str, Pass); 7] = [
+    ("ssa", to_ssa),
+    ("sccp", sccp),
+    ("fold", fold),
+    ("reduce", reduce),
+    ("gvn", gvn),
+    ("copyprop", copy_prop),
+    ("dce", dce),
+];
+
+// the names `optimize_until` takes
+pub const PASS_NAMES: [&str; 8] = [
+    "inline", "ssa", "sccp", "fold", "reduce", "gvn", "copyprop", "dce",
+];
+
+pub fn optimize(prog: &mut Program, inline_limit: usize) {
+    optimize_until(prog, inline_limit, "dce");
+}
+
+// runs the pipeline up to and including pass `last`
+pub fn optimize_until(prog: &mut Program, inline_limit: usize, last: &str) {
+    inline(prog, inline_limit);
+    if last == "inline" {
+        return;
+    }
+    for f in prog.funcs.iter_mut() {
+        for (name, pass) in PASSES.iter() {
+            pass(f);
+            if *name == last {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn stop_after_test() {
+    use inliner::INLINE_LIMIT;
+    use ir_text::{parse_program, print};
+    let text = "def main, 16
+        B0:
+          store.4 s4, 1
+          r0 = load.4 s4
+          br r0, B1, B2
+        B1:
+          r1 = add.4 r0, 2
+          ret.4 r1
+        B2:
+          ret.4 r0";
+    let mut prog = parse_program(text).unwrap();
+    optimize_until(&mut prog, INLINE_LIMIT, "sccp");
+    assert_eq!(
+        print(&prog.funcs),
+        "def main, 16
+B0:
+  r3 = mov 1
+  r4 = mov 1
+  jmp B1
+B1:
+  r5 = mov 3
+  ret.4 3
+B2:
+  unreachable
+"
+    );
+    let mut prog = parse_program(text).unwrap();
+    optimize(&mut prog, INLINE_LIMIT);
+    assert_eq!(
+        print(&prog.funcs),
+        "def main, 0
+B0:
+  ret.4 3
+"
+    );
+}
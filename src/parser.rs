@@ -1,100 +1,403 @@
-#![feature(slice_patterns)]
+use std::collections::HashMap;
 
-use lexer::{Lexer, Token};
-use node::{BinOp, Ctype, Node, NodeBase};
+use lexer::Token;
+use node::{BinOp, Ctype, Node, NodeBase, Quals, Storage, StructBody, StructRef, UnOp};
+use sema::Sema;
 
 pub struct Parser {
     pos: usize,
+    // parameters of the function declarator parsed last
+    params: Vec<(Ctype, Node)>,
+    // struct tags, innermost block scope last
+    tags: Vec<HashMap<String, StructRef>>,
+    nstruct: usize,
+    // what made `run` fail, when there is more to say than a panic
+    pub error: Option<String>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Parser {
     pub fn new() -> Self {
-        Parser { pos: 0 }
+        Parser {
+            pos: 0,
+            params: vec![],
+            tags: vec![HashMap::new()],
+            nstruct: 0,
+            error: None,
+        }
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn run(&mut self, tokens: Vec<Token>) -> Result<Vec<Node>, ()> {
         let mut v = vec![];
         while !self.is_eof(&tokens) {
-            let gd = self.global_def(&tokens)?;
-            v.push(gd);
+            let mut gd = self.global_def(&tokens)?;
+            v.append(&mut gd);
         }
         Ok(v)
     }
 }
 
 impl Parser {
-    fn global_def(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
+    // A declaration with a list of declarators gives a node for each.
+    fn global_def(&mut self, tokens: &[Token]) -> Result<Vec<Node>, ()> {
         match &tokens[self.pos] {
-            Token::Ctype(s) => {
-                self.step();
-                let typ = Node::ctype(&s)?;
-                let id = self.ident(&tokens)?;
-                self.expect(&tokens, Token::LeftParen);
-                let local_args = self.args_def(&tokens)?;
-                self.expect(&tokens, Token::RightParen);
-                self.expect(&tokens, Token::LeftCurlyBrace);
-                let stmts = self.statements(&tokens, Token::RightCurlyBrace)?;
-                self.expect(&tokens, Token::RightCurlyBrace);
-                Ok(Node::new(NodeBase::DefFun(
-                    typ,
-                    Box::new(id),
-                    local_args,
-                    Box::new(stmts),
-                )))
+            Token::Ctype(_) | Token::Static | Token::Inline | Token::Struct => {
+                let (typ, storage) = self.decl_specs(tokens)?;
+                // a declaration of a struct type only
+                if self.consume(tokens, Token::SemiColon, 0) {
+                    self.step();
+                    return Ok(vec![Node::new(NodeBase::Statements(vec![]))]);
+                }
+                let mut v = vec![];
+                loop {
+                    let (typ, id) = self.declarator(tokens, typ.clone())?;
+                    let id = id.ok_or(())?;
+                    let ret = match typ {
+                        Ctype::Func(ret, _) => *ret,
+                        typ => {
+                            v.push(self.var_def_rest(tokens, typ, id, storage)?);
+                            if !self.consume(tokens, Token::Comma, 0) {
+                                break;
+                            }
+                            self.step();
+                            continue;
+                        }
+                    };
+                    let local_args = ::std::mem::take(&mut self.params);
+                    if v.is_empty() && self.consume(tokens, Token::LeftCurlyBrace, 0) {
+                        self.step();
+                        let stmts = self.statements(tokens, Token::RightCurlyBrace)?;
+                        self.expect(tokens, Token::RightCurlyBrace);
+                        return Ok(vec![Node::new(NodeBase::DefFun(
+                            ret,
+                            Box::new(id),
+                            local_args,
+                            Box::new(stmts),
+                            vec![],
+                            storage,
+                        ))]);
+                    }
+                    v.push(Node::new(NodeBase::DecFun(
+                        ret,
+                        Box::new(id),
+                        local_args,
+                        storage,
+                    )));
+                    if !self.consume(tokens, Token::Comma, 0) {
+                        break;
+                    }
+                    self.step();
+                }
+                self.expect(tokens, Token::SemiColon);
+                Ok(v)
             }
             _ => Err(()),
         }
     }
 
-    fn args_def(&mut self, tokens: &Vec<Token>) -> Result<Vec<(Ctype, Node)>, ()> {
+    // Parameters get the usual adjustments: arrays become pointers to
+    // their element type, functions become function pointers. Unnamed
+    // parameters get an empty identifier.
+    fn args_def(&mut self, tokens: &[Token]) -> Result<Vec<(Ctype, Node)>, ()> {
         let mut v = vec![];
-        while !self.consume(&tokens, Token::RightParen, 0) {
-            let argtyp = self.ctype(&tokens)?;
-            let argid = self.ident(&tokens)?;
+        // `(void)` declares no parameters
+        if tokens[self.pos] == Token::Ctype("void".to_string())
+            && self.consume(tokens, Token::RightParen, 1)
+        {
+            self.step();
+            return Ok(v);
+        }
+        while !self.consume(tokens, Token::RightParen, 0) {
+            let argtyp = self.ctype(tokens)?;
+            let (argtyp, argid) = self.declarator(tokens, argtyp)?;
+            let argtyp = match argtyp {
+                Ctype::Array(t, _) => Ctype::Ptr(t),
+                t @ Ctype::Func(..) => Ctype::Ptr(Box::new(t)),
+                t => t,
+            };
+            let argid = argid.unwrap_or_else(|| Node::new(NodeBase::Ident(String::new())));
             v.push((argtyp, argid));
-            if self.consume(&tokens, Token::Comma, 0) {
-                self.expect(&tokens, Token::Comma);
+            if self.consume(tokens, Token::Comma, 0) {
+                self.expect(tokens, Token::Comma);
             }
         }
         Ok(v)
     }
 
-    fn statements(&mut self, tokens: &Vec<Token>, end: Token) -> Result<Node, ()> {
+    fn statements(&mut self, tokens: &[Token], end: Token) -> Result<Node, ()> {
         let mut stmts: Vec<Box<Node>> = vec![];
+        self.tags.push(HashMap::new());
         while end != tokens[self.pos] {
-            let stmt = self.statement(&tokens)?;
+            // each declarator of a declaration is a statement of the block
+            if self.is_decl(tokens) {
+                let defs = self.var_defs(tokens)?;
+                self.expect(tokens, Token::SemiColon);
+                stmts.extend(defs.into_iter().map(Box::new));
+                continue;
+            }
+            let stmt = self.statement(tokens)?;
             stmts.push(Box::new(stmt));
         }
+        self.tags.pop();
 
         Ok(Node::new(NodeBase::Statements(stmts)))
     }
 
-    fn statement(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
+    fn statement(&mut self, tokens: &[Token]) -> Result<Node, ()> {
         let stmt = match &tokens[self.pos] {
             Token::Return => {
                 self.step();
-                Node::new(NodeBase::Return(Box::new(self.expr(&tokens)?)))
+                Node::new(NodeBase::Return(self.opt_expr(tokens, Token::SemiColon)?))
+            }
+            Token::LeftCurlyBrace => {
+                self.step();
+                let stmts = self.statements(tokens, Token::RightCurlyBrace)?;
+                self.expect(tokens, Token::RightCurlyBrace);
+                return Ok(stmts);
+            }
+            Token::If => {
+                self.step();
+                self.expect(tokens, Token::LeftParen);
+                let cond = self.expr(tokens)?;
+                self.expect(tokens, Token::RightParen);
+                let then = self.statement(tokens)?;
+                let els = if self.consume(tokens, Token::Else, 0) {
+                    self.step();
+                    Some(Box::new(self.statement(tokens)?))
+                } else {
+                    None
+                };
+                return Ok(Node::new(NodeBase::If(Box::new(cond), Box::new(then), els)));
+            }
+            Token::While => {
+                self.step();
+                self.expect(tokens, Token::LeftParen);
+                let cond = self.expr(tokens)?;
+                self.expect(tokens, Token::RightParen);
+                let body = self.statement(tokens)?;
+                return Ok(Node::new(NodeBase::While(Box::new(cond), Box::new(body))));
+            }
+            Token::For => {
+                self.step();
+                self.expect(tokens, Token::LeftParen);
+                // A declaration in the first clause is scoped to the loop;
+                // one with several declarators goes in a block around it.
+                let mut defs = vec![];
+                let init = if self.consume(tokens, Token::SemiColon, 0) {
+                    None
+                } else if self.is_ctype(tokens, 0) {
+                    defs = self.var_defs(tokens)?;
+                    if defs.len() == 1 {
+                        defs.pop().map(Box::new)
+                    } else {
+                        None
+                    }
+                } else {
+                    Some(Box::new(self.expr(tokens)?))
+                };
+                self.expect(tokens, Token::SemiColon);
+                let cond = self.opt_expr(tokens, Token::SemiColon)?;
+                self.expect(tokens, Token::SemiColon);
+                let inc = self.opt_expr(tokens, Token::RightParen)?;
+                self.expect(tokens, Token::RightParen);
+                let body = self.statement(tokens)?;
+                let node = Node::new(NodeBase::For(init, cond, inc, Box::new(body)));
+                if defs.is_empty() {
+                    return Ok(node);
+                }
+                let mut stmts: Vec<Box<Node>> = defs.into_iter().map(Box::new).collect();
+                stmts.push(Box::new(node));
+                return Ok(Node::new(NodeBase::Statements(stmts)));
             }
-            _ => self.expr(&tokens)?,
+            // the null statement
+            Token::SemiColon => Node::new(NodeBase::Statements(vec![])),
+            Token::Ctype(_) | Token::Static | Token::Struct => {
+                let mut defs = self.var_defs(tokens)?;
+                if defs.len() == 1 {
+                    defs.pop().unwrap()
+                } else {
+                    Node::new(NodeBase::Statements(
+                        defs.into_iter().map(Box::new).collect(),
+                    ))
+                }
+            }
+            _ => self.expr(tokens)?,
         };
-        self.expect(&tokens, Token::SemiColon);
+        self.expect(tokens, Token::SemiColon);
         Ok(stmt)
     }
 
-    fn expr(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
-        self.expr_op1(&tokens)
+    // a declaration in block scope, with a `VarDef` for each declarator
+    fn var_defs(&mut self, tokens: &[Token]) -> Result<Vec<Node>, ()> {
+        let (typ, storage) = self.decl_specs(tokens)?;
+        let mut v = vec![];
+        if self.consume(tokens, Token::SemiColon, 0) {
+            return Ok(v);
+        }
+        loop {
+            let (typ, id) = self.declarator(tokens, typ.clone())?;
+            v.push(self.var_def_rest(tokens, typ, id.ok_or(())?, storage)?);
+            if !self.consume(tokens, Token::Comma, 0) {
+                return Ok(v);
+            }
+            self.step();
+        }
     }
 
-    fn expr_op1(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
-        let mut lhs = self.expr_op2(&tokens)?;
-        while !self.is_eof(&tokens) {
+    fn var_def_rest(
+        &mut self,
+        tokens: &[Token],
+        typ: Ctype,
+        id: Node,
+        storage: Storage,
+    ) -> Result<Node, ()> {
+        let name = match id.base {
+            NodeBase::Ident(s) => s,
+            _ => return Err(()),
+        };
+        let init = if self.consume(tokens, Token::Equal, 0) {
+            self.step();
+            Some(Box::new(self.expr(tokens)?))
+        } else {
+            None
+        };
+        Ok(Node::new(NodeBase::VarDef(typ, name, init, storage)))
+    }
+
+    fn expr(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        self.assign(tokens)
+    }
+
+    // an expression that may be left out before `end`
+    fn opt_expr(&mut self, tokens: &[Token], end: Token) -> Result<Option<Box<Node>>, ()> {
+        if self.consume(tokens, end, 0) {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(self.expr(tokens)?)))
+    }
+
+    fn assign(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let lhs = self.bit_or(tokens)?;
+        if self.consume(tokens, Token::Equal, 0) {
+            self.step();
+            let rhs = self.assign(tokens)?;
+            return Ok(Node::new(NodeBase::Assign(Box::new(lhs), Box::new(rhs))));
+        }
+        let op = match &tokens[self.pos] {
+            Token::PlusEqual => BinOp::Add,
+            Token::MinusEqual => BinOp::Sub,
+            Token::AsteriskEqual => BinOp::Mul,
+            Token::SlashEqual => BinOp::Div,
+            Token::PercentEqual => BinOp::Mod,
+            Token::AmpersandEqual => BinOp::And,
+            Token::PipeEqual => BinOp::Or,
+            Token::CaretEqual => BinOp::Xor,
+            Token::LeftShiftEqual => BinOp::Shl,
+            Token::RightShiftEqual => BinOp::Shr,
+            _ => return Ok(lhs),
+        };
+        self.step();
+        let rhs = self.assign(tokens)?;
+        Ok(Node::new(NodeBase::OpAssign(
+            op,
+            Box::new(lhs),
+            Box::new(rhs),
+        )))
+    }
+
+    fn bit_or(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.bit_xor(tokens)?;
+        while self.consume(tokens, Token::Pipe, 0) {
+            self.step();
+            let rhs = self.bit_xor(tokens)?;
+            lhs = Node::new(NodeBase::BinaryOp(BinOp::Or, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn bit_xor(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.bit_and(tokens)?;
+        while self.consume(tokens, Token::Caret, 0) {
+            self.step();
+            let rhs = self.bit_and(tokens)?;
+            lhs = Node::new(NodeBase::BinaryOp(BinOp::Xor, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn bit_and(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.equality(tokens)?;
+        while self.consume(tokens, Token::Ampersand, 0) {
+            self.step();
+            let rhs = self.equality(tokens)?;
+            lhs = Node::new(NodeBase::BinaryOp(BinOp::And, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn equality(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.relational(tokens)?;
+        loop {
+            let op = match &tokens[self.pos] {
+                Token::EqualEqual => BinOp::Eq,
+                Token::NotEqual => BinOp::Ne,
+                _ => break,
+            };
+            self.step();
+            let rhs = self.relational(tokens)?;
+            lhs = Node::new(NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn relational(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.shift(tokens)?;
+        loop {
+            let op = match &tokens[self.pos] {
+                Token::LessThan => BinOp::Lt,
+                Token::LessEqual => BinOp::Le,
+                Token::GreaterThan => BinOp::Gt,
+                Token::GreaterEqual => BinOp::Ge,
+                _ => break,
+            };
+            self.step();
+            let rhs = self.shift(tokens)?;
+            lhs = Node::new(NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn shift(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.expr_op1(tokens)?;
+        loop {
+            let op = match &tokens[self.pos] {
+                Token::LeftShift => BinOp::Shl,
+                Token::RightShift => BinOp::Shr,
+                _ => break,
+            };
+            self.step();
+            let rhs = self.expr_op1(tokens)?;
+            lhs = Node::new(NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn expr_op1(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.expr_op2(tokens)?;
+        while !self.is_eof(tokens) {
             match &tokens[self.pos] {
                 Token::Plus => {
                     self.step();
                     lhs = Node::new(NodeBase::BinaryOp(
                         BinOp::Add,
                         Box::new(lhs),
-                        Box::new(self.expr_op2(&tokens)?),
+                        Box::new(self.expr_op2(tokens)?),
                     ));
                 }
                 Token::Minus => {
@@ -102,7 +405,7 @@ impl Parser {
                     lhs = Node::new(NodeBase::BinaryOp(
                         BinOp::Sub,
                         Box::new(lhs),
-                        Box::new(self.expr_op2(&tokens)?),
+                        Box::new(self.expr_op2(tokens)?),
                     ));
                 }
                 _ => {
@@ -113,16 +416,16 @@ impl Parser {
         Ok(lhs)
     }
 
-    fn expr_op2(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
-        let mut lhs = self.term(&tokens)?;
-        while !self.is_eof(&tokens) {
+    fn expr_op2(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut lhs = self.unary(tokens)?;
+        while !self.is_eof(tokens) {
             match &tokens[self.pos] {
                 Token::Asterisk => {
                     self.step();
                     lhs = Node::new(NodeBase::BinaryOp(
                         BinOp::Mul,
                         Box::new(lhs),
-                        Box::new(self.term(&tokens)?),
+                        Box::new(self.unary(tokens)?),
                     ));
                 }
                 Token::Slash => {
@@ -130,7 +433,15 @@ impl Parser {
                     lhs = Node::new(NodeBase::BinaryOp(
                         BinOp::Div,
                         Box::new(lhs),
-                        Box::new(self.term(&tokens)?),
+                        Box::new(self.unary(tokens)?),
+                    ));
+                }
+                Token::Percent => {
+                    self.step();
+                    lhs = Node::new(NodeBase::BinaryOp(
+                        BinOp::Mod,
+                        Box::new(lhs),
+                        Box::new(self.unary(tokens)?),
                     ));
                 }
                 _ => break,
@@ -141,20 +452,110 @@ impl Parser {
 }
 
 impl Parser {
-    fn term(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
+    fn unary(&mut self, tokens: &[Token]) -> Result<Node, ()> {
         match &tokens[self.pos] {
-            Token::Num(_) => self.number(&tokens),
+            Token::Minus => {
+                self.step();
+                let e = self.unary(tokens)?;
+                Ok(Node::new(NodeBase::UnaryOp(UnOp::Neg, Box::new(e))))
+            }
+            Token::Plus => {
+                self.step();
+                self.unary(tokens)
+            }
+            Token::Asterisk => {
+                self.step();
+                let e = self.unary(tokens)?;
+                Ok(Node::new(NodeBase::Deref(Box::new(e))))
+            }
+            Token::Ampersand => {
+                self.step();
+                let e = self.unary(tokens)?;
+                Ok(Node::new(NodeBase::Addr(Box::new(e))))
+            }
+            Token::Sizeof => {
+                self.step();
+                if self.consume(tokens, Token::LeftParen, 0) && self.is_ctype(tokens, 1) {
+                    self.step();
+                    let typ = self.type_name(tokens)?;
+                    self.expect(tokens, Token::RightParen);
+                    let size = Node::new(NodeBase::Number(typ.size()));
+                    let mut cast = Node::new(NodeBase::Cast(Box::new(size)));
+                    cast.ctype = Some(Ctype::ULong);
+                    return Ok(cast);
+                }
+                let e = self.unary(tokens)?;
+                Ok(Node::new(NodeBase::Sizeof(Box::new(e))))
+            }
+            Token::LeftParen if self.is_ctype(tokens, 1) => {
+                self.step();
+                let typ = self.type_name(tokens)?;
+                self.expect(tokens, Token::RightParen);
+                let e = self.unary(tokens)?;
+                let mut cast = Node::new(NodeBase::Cast(Box::new(e)));
+                cast.ctype = Some(typ);
+                Ok(cast)
+            }
+            _ => self.postfix(tokens),
+        }
+    }
+
+    fn postfix(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        let mut e = self.term(tokens)?;
+        loop {
+            match &tokens[self.pos] {
+                Token::LeftParen => {
+                    self.step();
+                    let args = self.call_arg(tokens)?;
+                    self.expect(tokens, Token::RightParen);
+                    e = Node::new(NodeBase::CallPtr(Box::new(e), args));
+                }
+                // a[i] is *(a + i)
+                Token::LeftSquareBracket => {
+                    self.step();
+                    let idx = self.expr(tokens)?;
+                    self.expect(tokens, Token::RightSquareBracket);
+                    let add = Node::new(NodeBase::BinaryOp(BinOp::Add, Box::new(e), Box::new(idx)));
+                    e = Node::new(NodeBase::Deref(Box::new(add)));
+                }
+                Token::Dot => {
+                    self.step();
+                    let name = self.member_name(tokens)?;
+                    e = Node::new(NodeBase::Member(Box::new(e), name));
+                }
+                // p->m is (*p).m
+                Token::Arrow => {
+                    self.step();
+                    let name = self.member_name(tokens)?;
+                    let deref = Node::new(NodeBase::Deref(Box::new(e)));
+                    e = Node::new(NodeBase::Member(Box::new(deref), name));
+                }
+                _ => break,
+            }
+        }
+        Ok(e)
+    }
+
+    fn term(&mut self, tokens: &[Token]) -> Result<Node, ()> {
+        match &tokens[self.pos] {
+            Token::Num(_) => self.number(tokens),
             Token::Ident(_) => {
-                if self.consume(&tokens, Token::LeftParen, 1) {
-                    return self.funccall(&tokens);
+                if self.consume(tokens, Token::LeftParen, 1) {
+                    return self.funccall(tokens);
                 }
-                self.ident(&tokens)
+                self.ident(tokens)
+            }
+            Token::LeftParen => {
+                self.step();
+                let e = self.expr(tokens)?;
+                self.expect(tokens, Token::RightParen);
+                Ok(e)
             }
             _ => Err(()),
         }
     }
 
-    fn number(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
+    fn number(&mut self, tokens: &[Token]) -> Result<Node, ()> {
         match &tokens[self.pos] {
             Token::Num(n) => {
                 self.step();
@@ -164,7 +565,7 @@ impl Parser {
         }
     }
 
-    fn ident(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
+    fn ident(&mut self, tokens: &[Token]) -> Result<Node, ()> {
         match &tokens[self.pos] {
             Token::Ident(s) => {
                 self.step();
@@ -174,26 +575,36 @@ impl Parser {
         }
     }
 
-    fn funccall(&mut self, tokens: &Vec<Token>) -> Result<Node, ()> {
+    fn member_name(&mut self, tokens: &[Token]) -> Result<String, ()> {
+        match &tokens[self.pos] {
+            Token::Ident(s) => {
+                self.step();
+                Ok(s.to_string())
+            }
+            _ => Err(()),
+        }
+    }
+
+    fn funccall(&mut self, tokens: &[Token]) -> Result<Node, ()> {
         match &tokens[self.pos] {
             Token::Ident(s) => {
                 self.step();
-                self.expect(&tokens, Token::LeftParen);
-                let call_arg = self.call_arg(&tokens)?;
-                self.expect(&tokens, Token::RightParen);
+                self.expect(tokens, Token::LeftParen);
+                let call_arg = self.call_arg(tokens)?;
+                self.expect(tokens, Token::RightParen);
                 Ok(Node::new(NodeBase::Call(s.to_string(), call_arg)))
             }
             _ => Err(()),
         }
     }
 
-    fn call_arg(&mut self, tokens: &Vec<Token>) -> Result<Vec<Node>, ()> {
+    fn call_arg(&mut self, tokens: &[Token]) -> Result<Vec<Node>, ()> {
         let mut v = vec![];
-        while !self.consume(&tokens, Token::RightParen, 0) {
-            let exp = self.expr(&tokens)?;
+        while !self.consume(tokens, Token::RightParen, 0) {
+            let exp = self.expr(tokens)?;
             v.push(exp);
-            if self.consume(&tokens, Token::Comma, 0) {
-                self.expect(&tokens, Token::Comma);
+            if self.consume(tokens, Token::Comma, 0) {
+                self.expect(tokens, Token::Comma);
             } else {
                 break;
             }
@@ -201,15 +612,250 @@ impl Parser {
         Ok(v)
     }
 
-    fn ctype(&mut self, tokens: &Vec<Token>) -> Result<Ctype, ()> {
+    // type specifiers with storage-class and function specifiers mixed
+    // in
+    fn decl_specs(&mut self, tokens: &[Token]) -> Result<(Ctype, Storage), ()> {
+        let mut storage = Storage::default();
+        let mut specs = vec![];
+        let mut st = None;
+        loop {
+            match &tokens[self.pos] {
+                Token::Ctype(s) => specs.push(s.to_string()),
+                Token::Static => storage.is_static = true,
+                Token::Inline => storage.is_inline = true,
+                Token::Struct if st.is_none() => {
+                    st = Some(self.struct_spec(tokens)?);
+                    continue;
+                }
+                _ => break,
+            }
+            self.step();
+        }
+        let typ = match st {
+            Some(_) if !specs.iter().all(|s| Node::is_qualifier(s)) => return Err(()),
+            Some(st) => Ctype::qualified(st, Node::quals(&specs)),
+            None => Node::ctype(&specs)?,
+        };
+        Ok((typ, storage))
+    }
+
+    fn ctype(&mut self, tokens: &[Token]) -> Result<Ctype, ()> {
+        match self.decl_specs(tokens)? {
+            (typ, storage) if storage == Storage::default() => Ok(typ),
+            _ => Err(()),
+        }
+    }
+
+    // struct-specifier := 'struct' ident? ('{' member-decl* '}')?
+    // member-decl := specs member (',' member)* ';'
+    // member := declarator (':' num)? | ':' num
+    fn struct_spec(&mut self, tokens: &[Token]) -> Result<Ctype, ()> {
+        self.expect(tokens, Token::Struct);
+        let tag = match &tokens[self.pos] {
+            Token::Ident(s) => {
+                self.step();
+                Some(s.to_string())
+            }
+            _ => None,
+        };
+        if !self.consume(tokens, Token::LeftCurlyBrace, 0) {
+            return Ok(Ctype::Struct(self.struct_tag(&tag.ok_or(())?)));
+        }
+        self.step();
+        let st = match tag {
+            Some(tag) => self.define_tag(&tag)?,
+            None => self.new_struct(""),
+        };
+        let mut decls = vec![];
+        while !self.consume(tokens, Token::RightCurlyBrace, 0) {
+            let typ = self.ctype(tokens)?;
+            loop {
+                let (t, id) = if self.consume(tokens, Token::Colon, 0) {
+                    (typ.clone(), None)
+                } else {
+                    self.declarator(tokens, typ.clone())?
+                };
+                let width = if self.consume(tokens, Token::Colon, 0) {
+                    self.step();
+                    match &tokens[self.pos] {
+                        Token::Num(n) => {
+                            self.step();
+                            Some(*n)
+                        }
+                        _ => return Err(()),
+                    }
+                } else {
+                    None
+                };
+                let name = match id {
+                    Some(id) => match id.base {
+                        NodeBase::Ident(s) => s,
+                        _ => return Err(()),
+                    },
+                    None if width.is_some() => String::new(),
+                    None => return Err(()),
+                };
+                // only unnamed bit-fields may have zero width
+                if width == Some(0) && !name.is_empty() {
+                    return Err(());
+                }
+                decls.push((t, name, width));
+                if !self.consume(tokens, Token::Comma, 0) {
+                    break;
+                }
+                self.step();
+            }
+            self.expect(tokens, Token::SemiColon);
+        }
+        self.step();
+        st.define(StructBody::new(decls));
+        Ok(Ctype::Struct(st))
+    }
+
+    // a reference to a struct tag; an unknown tag declares an incomplete
+    // struct in the current scope
+    fn struct_tag(&mut self, tag: &str) -> StructRef {
+        for scope in self.tags.iter().rev() {
+            if let Some(st) = scope.get(tag) {
+                return st.clone();
+            }
+        }
+        self.new_struct(tag)
+    }
+
+    // the struct a definition with this tag completes
+    fn define_tag(&mut self, tag: &str) -> Result<StructRef, ()> {
+        let scope = self.tags.last().expect("no scope");
+        match scope.get(tag) {
+            Some(st) if st.body().is_some() => Err(()),
+            Some(st) => Ok(st.clone()),
+            None => Ok(self.new_struct(tag)),
+        }
+    }
+
+    fn new_struct(&mut self, tag: &str) -> StructRef {
+        let st = StructRef::new(self.nstruct, tag);
+        self.nstruct += 1;
+        if !tag.is_empty() {
+            let scope = self.tags.last_mut().expect("no scope");
+            scope.insert(tag.to_string(), st.clone());
+        }
+        st
+    }
+
+    // declarator := pointer direct-declarator type-suffix
+    // direct-declarator := ident | '(' declarator ')' | (nothing)
+    //
+    // The identifier is optional so the same code parses abstract
+    // declarators. For a nested declarator such as `(*fp)(int)` the suffix
+    // after the parentheses applies first, so the nested part is skipped,
+    // the suffix parsed, and then the nested part re-parsed on top of it.
+    fn declarator(&mut self, tokens: &[Token], typ: Ctype) -> Result<(Ctype, Option<Node>), ()> {
+        let typ = self.pointer(tokens, typ)?;
+        if self.consume(tokens, Token::LeftParen, 0) && self.is_nested_declarator(tokens) {
+            self.step();
+            let start = self.pos;
+            self.declarator(tokens, Ctype::Int)?;
+            self.expect(tokens, Token::RightParen);
+            let typ = self.type_suffix(tokens, typ)?;
+            let end = self.pos;
+            self.pos = start;
+            let res = self.declarator(tokens, typ)?;
+            self.pos = end;
+            return Ok(res);
+        }
+        let id = match &tokens[self.pos] {
+            Token::Ident(_) => Some(self.ident(tokens)?),
+            _ => None,
+        };
+        Ok((self.type_suffix(tokens, typ)?, id))
+    }
+
+    fn type_suffix(&mut self, tokens: &[Token], typ: Ctype) -> Result<Ctype, ()> {
         match &tokens[self.pos] {
-            Token::Ctype(s) => {
+            Token::LeftSquareBracket => {
                 self.step();
-                Node::ctype(&s)
+                let len = if self.consume(tokens, Token::RightSquareBracket, 0) {
+                    0
+                } else {
+                    let e = self.bit_or(tokens)?;
+                    match Sema::array_len(e) {
+                        Ok(n) => n,
+                        Err(e) => {
+                            self.error = Some(e);
+                            return Err(());
+                        }
+                    }
+                };
+                self.expect(tokens, Token::RightSquareBracket);
+                let typ = self.type_suffix(tokens, typ)?;
+                Ok(Ctype::Array(Box::new(typ), len))
             }
+            Token::LeftParen => {
+                self.step();
+                let params = self.args_def(tokens)?;
+                self.expect(tokens, Token::RightParen);
+                let typ = self.type_suffix(tokens, typ)?;
+                let types = params.iter().map(|(t, _)| t.clone()).collect();
+                self.params = params;
+                Ok(Ctype::Func(Box::new(typ), types))
+            }
+            _ => Ok(typ),
+        }
+    }
+
+    // `(` starts a nested declarator rather than a parameter list
+    fn is_nested_declarator(&self, tokens: &[Token]) -> bool {
+        matches!(
+            tokens[self.pos + 1],
+            Token::Asterisk | Token::LeftParen | Token::Ident(_)
+        )
+    }
+
+    // type name of a cast or sizeof, e.g. `int (*)[4]`
+    fn type_name(&mut self, tokens: &[Token]) -> Result<Ctype, ()> {
+        let typ = self.ctype(tokens)?;
+        match self.declarator(tokens, typ)? {
+            (typ, None) => Ok(typ),
             _ => Err(()),
         }
     }
+
+    // pointer part of a declarator: `* const * volatile ...`
+    fn pointer(&mut self, tokens: &[Token], mut typ: Ctype) -> Result<Ctype, ()> {
+        for quals in self.pointer_quals(tokens)? {
+            typ = Ctype::qualified(Ctype::Ptr(Box::new(typ)), quals);
+        }
+        Ok(typ)
+    }
+
+    fn pointer_quals(&mut self, tokens: &[Token]) -> Result<Vec<Quals>, ()> {
+        let mut v = vec![];
+        while self.consume(tokens, Token::Asterisk, 0) {
+            self.step();
+            let mut quals = vec![];
+            while let Token::Ctype(s) = &tokens[self.pos] {
+                if !Node::is_qualifier(s) {
+                    return Err(());
+                }
+                quals.push(s.to_string());
+                self.step();
+            }
+            v.push(Node::quals(&quals));
+        }
+        Ok(v)
+    }
+
+    fn is_ctype(&self, tokens: &[Token], n: usize) -> bool {
+        matches!(tokens[self.pos + n], Token::Ctype(_) | Token::Struct)
+    }
+
+    fn is_decl(&self, tokens: &[Token]) -> bool {
+        matches!(
+            tokens[self.pos],
+            Token::Ctype(_) | Token::Static | Token::Struct
+        )
+    }
 }
 
 impl Parser {
@@ -217,21 +863,211 @@ impl Parser {
         self.pos += 1;
     }
 
-    fn consume(&self, tokens: &Vec<Token>, token: Token, n: usize) -> bool {
+    fn consume(&self, tokens: &[Token], token: Token, n: usize) -> bool {
         if tokens[self.pos + n] == token {
             return true;
         }
         false
     }
 
-    fn expect(&mut self, tokens: &Vec<Token>, token: Token) {
+    fn expect(&mut self, tokens: &[Token], token: Token) {
         if tokens[self.pos] != token {
             panic!("{:?} expected, but got {:?}", token, tokens[self.pos]);
         }
         self.step();
     }
 
-    fn is_eof(&self, tokens: &Vec<Token>) -> bool {
+    fn is_eof(&self, tokens: &[Token]) -> bool {
         tokens[self.pos] == Token::EOF
     }
 }
+
+#[cfg(test)]
+fn parse_type(code: &str) -> Ctype {
+    use lexer::Lexer;
+    let tokens = Lexer::new(code).run().unwrap();
+    let mut parser = Parser::new();
+    let typ = parser.ctype(&tokens).unwrap();
+    parser.declarator(&tokens, typ).unwrap().0
+}
+
+#[test]
+fn declarator_test() {
+    let int = || Box::new(Ctype::Int);
+    assert_eq!(
+        parse_type("int **p"),
+        Ctype::Ptr(Box::new(Ctype::Ptr(int())))
+    );
+    assert_eq!(
+        parse_type("int a[2][3]"),
+        Ctype::Array(Box::new(Ctype::Array(int(), 3)), 2)
+    );
+    assert_eq!(
+        parse_type("int *a[4]"),
+        Ctype::Array(Box::new(Ctype::Ptr(int())), 4)
+    );
+    assert_eq!(
+        parse_type("int (*a)[4]"),
+        Ctype::Ptr(Box::new(Ctype::Array(int(), 4)))
+    );
+    // pointer to a function taking int and returning a pointer to int[4]
+    assert_eq!(
+        parse_type("int (*(*fp)(int))[4]"),
+        Ctype::Ptr(Box::new(Ctype::Func(
+            Box::new(Ctype::Ptr(Box::new(Ctype::Array(int(), 4)))),
+            vec![Ctype::Int]
+        )))
+    );
+    // a size may be any integer constant expression
+    assert_eq!(
+        parse_type("int a[2 * 3][sizeof(int) * 4]"),
+        Ctype::Array(Box::new(Ctype::Array(int(), 16)), 6)
+    );
+    // array parameters are adjusted to pointers
+    assert_eq!(
+        parse_type("int f(char s[], int (*g)(int))"),
+        Ctype::Func(
+            int(),
+            vec![
+                Ctype::Ptr(Box::new(Ctype::Char)),
+                Ctype::Ptr(Box::new(Ctype::Func(int(), vec![Ctype::Int])))
+            ]
+        )
+    );
+}
+
+#[test]
+fn array_size_test() {
+    use lexer::Lexer;
+    let error = |code: &str| {
+        let tokens = Lexer::new(code).run().unwrap();
+        let mut parser = Parser::new();
+        assert!(parser.run(tokens).is_err(), "{}", code);
+        parser.error.unwrap()
+    };
+    assert_eq!(
+        error("int main() { int n; int a[n]; return 0; }"),
+        "undefined variable 'n'"
+    );
+    assert_eq!(error("int a[1 - 2];"), "size of array is negative");
+    assert_eq!(
+        error("int a[(char *)4];"),
+        "size of array has non-integer type"
+    );
+}
+
+#[test]
+fn abstract_declarator_test() {
+    let int = || Box::new(Ctype::Int);
+    assert_eq!(
+        parse_type("int (*)(int, char *)"),
+        Ctype::Ptr(Box::new(Ctype::Func(
+            int(),
+            vec![Ctype::Int, Ctype::Ptr(Box::new(Ctype::Char))]
+        )))
+    );
+    assert_eq!(
+        parse_type("int *[3]"),
+        Ctype::Array(Box::new(Ctype::Ptr(int())), 3)
+    );
+    assert_eq!(
+        parse_type("void (*)(void)"),
+        Ctype::Ptr(Box::new(Ctype::Func(Box::new(Ctype::Void), vec![])))
+    );
+}
+
+#[test]
+fn struct_test() {
+    use node::BitField;
+    let t = parse_type("struct node { unsigned v : 3; struct node *next; } n");
+    let next = t.member("next").unwrap();
+    assert_eq!(next.ctype, Ctype::Ptr(Box::new(t.clone())));
+    assert_eq!(next.offset, 8);
+    assert_eq!(
+        t.member("v").unwrap().bits,
+        Some(BitField {
+            offset: 0,
+            width: 3
+        })
+    );
+    assert_eq!(t.size(), 16);
+    assert!(parse_type("const struct node *p")
+        .pointee()
+        .unwrap()
+        .is_const());
+}
+
+#[test]
+fn control_test() {
+    use lexer::Lexer;
+    let tokens = Lexer::new("if (a) if (b) x; else y; for (;;) ; while (c) {}")
+        .run()
+        .unwrap();
+    let mut parser = Parser::new();
+    // a dangling else belongs to the innermost if
+    match parser.statement(&tokens).unwrap().base {
+        NodeBase::If(_, then, None) => match then.base {
+            NodeBase::If(_, _, Some(_)) => {}
+            _ => panic!(),
+        },
+        _ => panic!(),
+    }
+    match parser.statement(&tokens).unwrap().base {
+        NodeBase::For(None, None, None, body) => {
+            assert_eq!(body.base, NodeBase::Statements(vec![]))
+        }
+        _ => panic!(),
+    }
+    match parser.statement(&tokens).unwrap().base {
+        NodeBase::While(..) => {}
+        _ => panic!(),
+    }
+    assert!(parser.is_eof(&tokens));
+}
+
+#[test]
+fn declaration_list_test() {
+    use lexer::Lexer;
+    let tokens = Lexer::new(
+        "int a, *p = 0, f(int);
+        int main() { int x = 1, y[2]; for (int i = 0, j = 1; i; ) ; }",
+    )
+    .run()
+    .unwrap();
+    let nodes = Parser::new().run(tokens).unwrap();
+    let names: Vec<&str> = nodes
+        .iter()
+        .map(|n| match &n.base {
+            NodeBase::VarDef(_, name, ..) => name.as_str(),
+            NodeBase::DecFun(..) => "f",
+            NodeBase::DefFun(..) => "main",
+            _ => panic!(),
+        })
+        .collect();
+    assert_eq!(names, ["a", "p", "f", "main"]);
+    let stmts = match &nodes[3].base {
+        NodeBase::DefFun(_, _, _, stmts, ..) => match &stmts.base {
+            NodeBase::Statements(v) => v,
+            _ => panic!(),
+        },
+        _ => panic!(),
+    };
+    // x and y are declared in the block itself
+    match (&stmts[0].base, &stmts[1].base) {
+        (
+            NodeBase::VarDef(Ctype::Int, x, Some(_), _),
+            NodeBase::VarDef(Ctype::Array(..), y, None, _),
+        ) => {
+            assert_eq!((x.as_str(), y.as_str()), ("x", "y"))
+        }
+        _ => panic!(),
+    }
+    // and i and j in a block around the loop
+    match &stmts[2].base {
+        NodeBase::Statements(v) => {
+            assert_eq!(v.len(), 3);
+            assert!(matches!(v[2].base, NodeBase::For(None, Some(_), None, _)));
+        }
+        _ => panic!(),
+    }
+}
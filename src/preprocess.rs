@@ -0,0 +1,140 @@
+// A minimal preprocessor: `#include` of the bundled headers and
+// object-like `#define`s. It works on lines of text before lexing.
+
+use std::collections::HashMap;
+
+const STDBOOL_H: &str = include_str!("../include/stdbool.h");
+
+pub struct Preprocessor {
+    macros: HashMap<String, String>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Preprocessor {
+            macros: HashMap::new(),
+        }
+    }
+
+    pub fn run(&mut self, code: &str) -> Result<String, String> {
+        let mut out = String::new();
+        for line in code.lines() {
+            if let Some(directive) = line.trim_start().strip_prefix('#') {
+                self.directive(directive.trim(), &mut out)?;
+            } else {
+                out.push_str(&self.expand(line, &mut vec![]));
+                out.push('\n');
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl Preprocessor {
+    fn directive(&mut self, line: &str, out: &mut String) -> Result<(), String> {
+        let (name, rest) = Preprocessor::split(line);
+        match name {
+            "include" => {
+                let header = Preprocessor::header(rest)?;
+                out.push_str(&self.run(header)?);
+            }
+            "define" => {
+                let (name, body) = Preprocessor::split(rest);
+                if !Preprocessor::is_ident(name) {
+                    return Err("macro names must be identifiers".to_string());
+                }
+                self.macros.insert(name.to_string(), body.to_string());
+            }
+            // null directive
+            "" => {}
+            _ => return Err(format!("invalid preprocessing directive #{}", name)),
+        }
+        Ok(())
+    }
+
+    fn header(spec: &str) -> Result<&'static str, String> {
+        match spec {
+            "<stdbool.h>" => Ok(STDBOOL_H),
+            _ => Err(format!("{} file not found", spec)),
+        }
+    }
+
+    // Replaces macro names in `text`. A macro is not expanded again inside
+    // its own expansion.
+    fn expand(&self, text: &str, hidden: &mut Vec<String>) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if !Preprocessor::is_ident_char(chars[i]) {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < chars.len() && Preprocessor::is_ident_char(chars[i]) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            match self.macros.get(&word) {
+                Some(body) if Preprocessor::is_ident(&word) && !hidden.contains(&word) => {
+                    hidden.push(word);
+                    out.push_str(&self.expand(body, hidden));
+                    hidden.pop();
+                }
+                _ => out.push_str(&word),
+            }
+        }
+        out
+    }
+
+    fn split(line: &str) -> (&str, &str) {
+        match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        }
+    }
+
+    fn is_ident(s: &str) -> bool {
+        match s.chars().next() {
+            Some(c) => !c.is_ascii_digit() && s.chars().all(Preprocessor::is_ident_char),
+            None => false,
+        }
+    }
+
+    fn is_ident_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_'
+    }
+}
+
+#[test]
+fn define_test() {
+    let code = "#define N 3\n#define M N * N\nint main() { return M + N1; }\n";
+    assert_eq!(
+        Preprocessor::new().run(code),
+        Ok("int main() { return 3 * 3 + N1; }\n".to_string())
+    );
+    assert_eq!(
+        Preprocessor::new().run("#define f f + 1\nf\n"),
+        Ok("f + 1\n".to_string())
+    );
+}
+
+#[test]
+fn include_test() {
+    let code = "#include <stdbool.h>\nbool b = true;\n";
+    assert_eq!(
+        Preprocessor::new().run(code),
+        Ok("_Bool b = 1;\n".to_string())
+    );
+    assert_eq!(
+        Preprocessor::new().run("#include <stdio.h>\n"),
+        Err("<stdio.h> file not found".to_string())
+    );
+}
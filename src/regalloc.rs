@@ -1,104 +1,485 @@
 // Register allocator
+//
+// A linear scan over live intervals. Instructions are numbered in block
+// order; an instruction reads its operands at an even position and
+// writes its result at the odd one after it, so a register dying at an
+// instruction can be reused for its result. Each virtual register gets
+// a single interval from its first definition or use to its last,
+// widened to the blocks it is live into or out of. The intervals take
+// the lowest free register in order of their start; when none is left,
+// the one ending last is spilled to a frame slot, every access to it goes
+// through a short-lived register instead, and allocation starts over.
+//
+// Before that, the two registers of a move whose intervals do not
+// overlap are merged, so the move disappears. A value whose last use is
+// as an argument of a call prefers the register passing that argument,
+// so it is computed right where the call needs it.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use gen_ir::{Ir, Op};
+use cfg::Function;
+use gen_ir::{Ir, Op, Operand, Program};
+use liveness::Liveness;
+use ssa::from_ssa;
 
-const reg_map_size: usize = 8192;
-const num_regs: isize = 7;
+pub const NUM_REGS: isize = 11;
+
+// registers a callee may clobber (r10, r11, rdi, rsi, r8 and r9); the
+// others are callee-saved
+pub const CALLER_SAVED: [isize; 6] = [0, 1, 7, 8, 9, 10];
+
+// the register passing each argument, if it is allocated; rdx and rcx
+// serve as scratch registers instead
+const ARG_REGS: [Option<isize>; 6] = [Some(7), Some(8), None, None, Some(9), Some(10)];
+
+struct Interval {
+    reg: isize,
+    start: usize,
+    end: usize,
+}
 
 pub struct RegAlloc {
-    map: HashMap<isize, isize>,
-    used: Vec<isize>,
+    // registers holding spilled values for a single instruction, which
+    // must not be spilled again
+    temps: HashSet<isize>,
+}
+
+impl Default for RegAlloc {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RegAlloc {
     pub fn new() -> Self {
         RegAlloc {
-            map: HashMap::new(),
-            used: vec![],
+            temps: HashSet::new(),
         }
     }
 
-    pub fn run(&mut self, irvv: Vec<Vec<Ir>>) -> Result<Vec<Vec<Ir>>, ()> {
-        let mut vv = vec![];
-        let mut v = vec![];
-        for irv in irvv {
-            for ir in irv {
-                if let Ok(i) = self.reg_alloc(ir) {
-                    v.push(i);
+    // Functions still in SSA form are taken out of it first. After
+    // allocation a function's `used` holds a bitmask of the registers it
+    // uses, and every call is bracketed by `Save` and `Restore` of the
+    // caller-saved registers live across it.
+    #[allow(clippy::result_unit_err)]
+    pub fn run(&mut self, mut prog: Program) -> Result<Program, ()> {
+        for func in prog.funcs.iter_mut() {
+            from_ssa(func);
+            for b in func.blocks.iter_mut() {
+                b.ins.retain(|ir| ir.op != Op::Kill);
+            }
+            self.temps = HashSet::new();
+            coalesce(func);
+            let mut next = func.next_reg();
+            loop {
+                let intervals = intervals(func);
+                let hints = hints(func, &intervals);
+                match self.scan(&intervals, &hints) {
+                    Ok(map) => {
+                        assign(func, &map, &intervals);
+                        break;
+                    }
+                    Err(spilled) => {
+                        for r in spilled {
+                            self.spill(func, r, &mut next);
+                        }
+                    }
                 }
             }
-            vv.push(v);
-            v = vec![];
         }
-        Ok(vv)
+        Ok(prog)
     }
 }
 
 impl RegAlloc {
-    fn reg_alloc(&mut self, mut ir: Ir) -> Result<Ir, ()> {
-        match ir.op {
-            Op::Imm => {
-                let a = self.alloc(ir.lhs)?;
-                Ok(Ir::new(Op::Imm, a, ir.rhs))
-            }
-            Op::StoreArg => {
-                let a = self.alloc(ir.lhs)?;
-                Ok(Ir::new(Op::StoreArg, a, ir.rhs))
-            }
-            Op::Load => {
-                let a = self.alloc(ir.lhs)?;
-                Ok(Ir::new(Op::Load, a, ir.rhs))
-            }
-            Op::Return => {
-                ir.lhs = self.alloc(ir.lhs)?;
-                Ok(ir)
-            }
-            Op::Call(ref s, ref mut v) => {
-                let mut alloced_v = vec![];
-                for i in v.iter() {
-                    alloced_v.push(self.alloc(*i)?);
+    // Maps virtual registers to physical ones, or returns the registers
+    // to spill.
+    fn scan(
+        &self,
+        intervals: &[Interval],
+        hints: &HashMap<isize, isize>,
+    ) -> Result<HashMap<isize, isize>, Vec<isize>> {
+        let mut map = HashMap::new();
+        let mut spilled = vec![];
+        // intervals holding a register, with the register
+        let mut active: Vec<(&Interval, isize)> = vec![];
+        for iv in intervals.iter() {
+            active.retain(|(a, _)| a.end >= iv.start);
+            let hint = hints.get(&iv.reg).into_iter().cloned();
+            if let Some(r) = hint
+                .chain(0..NUM_REGS)
+                .find(|r| active.iter().all(|(_, p)| p != r))
+            {
+                map.insert(iv.reg, r);
+                active.push((iv, r));
+                continue;
+            }
+            let victim = active
+                .iter()
+                .enumerate()
+                .filter(|(_, (a, _))| !self.temps.contains(&a.reg))
+                .max_by_key(|(_, (a, _))| a.end)
+                .map(|(i, _)| i);
+            match victim {
+                Some(i) if active[i].0.end >= iv.end || self.temps.contains(&iv.reg) => {
+                    let (a, r) = active.remove(i);
+                    spilled.push(a.reg);
+                    map.insert(iv.reg, r);
+                    active.push((iv, r));
                 }
-                *v = alloced_v;
-                let a = self.alloc(ir.lhs)?;
-                Ok(Ir::new(Op::Call((*s).to_string(), v.to_vec()), a, ir.rhs))
+                _ if !self.temps.contains(&iv.reg) => spilled.push(iv.reg),
+                _ => panic!("out of registers"),
             }
-            Op::Mov | Op::Add | Op::Sub | Op::Mul | Op::Div => {
-                ir.lhs = self.alloc(ir.lhs)?;
-                ir.rhs = self.alloc(ir.rhs)?;
-                Ok(ir)
+        }
+        if spilled.is_empty() {
+            Ok(map)
+        } else {
+            Err(spilled)
+        }
+    }
+
+    // Moves register `r` to a new frame slot: it is loaded into a fresh
+    // register before every use and stored from one after every write.
+    fn spill(&mut self, func: &mut Function, r: isize, next: &mut isize) {
+        let slot = func.frame + 8;
+        func.frame = (slot + 15) / 16 * 16;
+        let mut fresh = || {
+            *next += 1;
+            *next - 1
+        };
+        for b in func.blocks.iter_mut() {
+            let mut v = vec![];
+            for mut ir in std::mem::take(&mut b.ins) {
+                if ir.uses().contains(&r) {
+                    let t = fresh();
+                    self.temps.insert(t);
+                    v.push(Ir::new(Op::Load, Some(t), vec![Operand::Slot(slot)], 8));
+                    for a in ir.args.iter_mut().filter(|a| **a == Operand::Reg(r)) {
+                        *a = Operand::Reg(t);
+                    }
+                }
+                if ir.dst == Some(r) {
+                    let t = fresh();
+                    self.temps.insert(t);
+                    ir.dst = Some(t);
+                    v.push(ir);
+                    let args = vec![Operand::Slot(slot), Operand::Reg(t)];
+                    v.push(Ir::new(Op::Store, None, args, 8));
+                } else {
+                    v.push(ir);
+                }
             }
-            Op::Kill => {
-                self.kill(ir.lhs);
-                ir.op = Op::Nop;
-                Ok(ir)
+            if let Some(a) = b.term.arg_mut() {
+                if *a == Operand::Reg(r) {
+                    let t = fresh();
+                    self.temps.insert(t);
+                    v.push(Ir::new(Op::Load, Some(t), vec![Operand::Slot(slot)], 8));
+                    *a = Operand::Reg(t);
+                }
             }
-            _ => Ok(ir),
+            b.ins = v;
         }
     }
+}
 
-    fn alloc(&mut self, ir_reg: isize) -> Result<isize, ()> {
-        if reg_map_size <= ir_reg as usize {
-            return Err(());
+// live intervals ordered by their start
+fn intervals(func: &Function) -> Vec<Interval> {
+    let live = Liveness::new(func);
+    let mut ranges: HashMap<isize, (usize, usize)> = HashMap::new();
+    let mut extend = |r: isize, pos: usize| {
+        let e = ranges.entry(r).or_insert((pos, pos));
+        e.0 = e.0.min(pos);
+        e.1 = e.1.max(pos);
+    };
+    let mut pos = 0;
+    for (i, b) in func.blocks.iter().enumerate() {
+        for r in live.live_in[i].iter() {
+            extend(*r, pos);
+        }
+        for ir in b.ins.iter() {
+            for r in ir.uses() {
+                extend(r, pos);
+            }
+            if let Some(d) = ir.dst {
+                extend(d, pos + 1);
+            }
+            pos += 2;
+        }
+        if let Some(Operand::Reg(r)) = b.term.arg() {
+            extend(*r, pos);
+        }
+        for r in live.live_out[i].iter() {
+            extend(*r, pos + 1);
+        }
+        pos += 2;
+    }
+    let mut v: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(r, (start, end))| Interval { reg: r, start, end })
+        .collect();
+    v.sort_by_key(|iv| (iv.start, iv.reg));
+    v
+}
+
+// Merges the registers of each full-width move between two registers
+// whose intervals are disjoint, and drops the moves left copying a
+// register to itself.
+fn coalesce(func: &mut Function) {
+    let mut ranges: HashMap<isize, (usize, usize)> = intervals(func)
+        .into_iter()
+        .map(|iv| (iv.reg, (iv.start, iv.end)))
+        .collect();
+    // the register each merged one was merged into
+    let mut merged: HashMap<isize, isize> = HashMap::new();
+    let find = |merged: &HashMap<isize, isize>, mut r: isize| {
+        while let Some(m) = merged.get(&r) {
+            r = *m;
+        }
+        r
+    };
+    for b in func.blocks.iter() {
+        for ir in b.ins.iter() {
+            let (d, s) = match (&ir.op, ir.dst, ir.size, ir.args.first()) {
+                (Op::Mov, Some(d), 8, Some(Operand::Reg(s))) => {
+                    (find(&merged, d), find(&merged, *s))
+                }
+                _ => continue,
+            };
+            let (a, b) = (ranges[&d], ranges[&s]);
+            if d != s && (a.1 < b.0 || b.1 < a.0) {
+                merged.insert(s, d);
+                ranges.insert(d, (a.0.min(b.0), a.1.max(b.1)));
+            }
         }
+    }
+    if merged.is_empty() {
+        return;
+    }
+    for b in func.blocks.iter_mut() {
+        for ir in b.ins.iter_mut() {
+            for a in ir.args.iter_mut() {
+                if let Operand::Reg(r) = a {
+                    *r = find(&merged, *r);
+                }
+            }
+            if let Some(d) = ir.dst.as_mut() {
+                *d = find(&merged, *d);
+            }
+        }
+        b.ins.retain(|ir| {
+            !(ir.op == Op::Mov
+                && ir.size == 8
+                && ir.dst.map(Operand::Reg).as_ref() == ir.args.first())
+        });
+        if let Some(Operand::Reg(r)) = b.term.arg_mut() {
+            *r = find(&merged, *r);
+        }
+    }
+}
 
-        if let Some(ir) = self.map.get(&ir_reg) {
-            return Ok(*ir);
+// the argument register preferred by each register whose interval ends
+// as an argument of a call
+fn hints(func: &Function, intervals: &[Interval]) -> HashMap<isize, isize> {
+    let ends: HashMap<isize, usize> = intervals.iter().map(|iv| (iv.reg, iv.end)).collect();
+    let mut hints = HashMap::new();
+    let mut pos = 0;
+    for b in func.blocks.iter() {
+        for ir in b.ins.iter() {
+            if ir.op == Op::Call {
+                for (a, p) in ir.args.iter().skip(1).zip(ARG_REGS.iter()) {
+                    if let (Operand::Reg(r), Some(p)) = (a, p) {
+                        if ends[r] == pos {
+                            hints.entry(*r).or_insert(*p);
+                        }
+                    }
+                }
+            }
+            pos += 2;
         }
+        pos += 2;
+    }
+    hints
+}
 
-        for i in 0..num_regs {
-            if self.used.iter().any(|&c| c == i) {
-                continue;
+// rewrites the function with physical registers and saves the
+// caller-saved ones holding values that outlive a call
+fn assign(func: &mut Function, map: &HashMap<isize, isize>, intervals: &[Interval]) {
+    let phys = |r: &mut isize| *r = map[r];
+    let mut pos = 0;
+    for b in func.blocks.iter_mut() {
+        let mut v = vec![];
+        for mut ir in std::mem::take(&mut b.ins) {
+            let mut live = vec![];
+            if ir.op == Op::Call {
+                live = intervals
+                    .iter()
+                    .filter(|iv| iv.start <= pos && iv.end > pos + 1 && Some(iv.reg) != ir.dst)
+                    .map(|iv| map[&iv.reg])
+                    .filter(|r| CALLER_SAVED.contains(r))
+                    .collect();
+                live.sort();
+                live.dedup();
             }
-            self.used.push(i);
-            self.map.insert(ir_reg, i);
-            return Ok(i);
+            for a in ir.args.iter_mut() {
+                if let Operand::Reg(r) = a {
+                    phys(r);
+                }
+            }
+            if let Some(r) = ir.dst.as_mut() {
+                phys(r);
+            }
+            for r in live.iter() {
+                v.push(Ir::new(Op::Save, None, vec![Operand::Reg(*r)], 8));
+            }
+            v.push(ir);
+            for r in live.iter() {
+                v.push(Ir::new(Op::Restore, Some(*r), vec![], 8));
+            }
+            pos += 2;
         }
-        return Err(());
+        if let Some(Operand::Reg(r)) = b.term.arg_mut() {
+            phys(r);
+        }
+        b.ins = v;
+        pos += 2;
     }
+    func.used = Some(map.values().fold(0, |mask, r| mask | 1 << r));
+}
+
+#[cfg(test)]
+fn allocate(text: &str) -> String {
+    use ir_text::{parse_program, print};
+    let prog = parse_program(text).unwrap();
+    print(&RegAlloc::new().run(prog).unwrap().funcs)
+}
 
-    fn kill(&mut self, ir_reg: isize) {
-        self.used.retain(|&i| i != ir_reg);
+#[test]
+fn reg_alloc_test() {
+    // r5 is dead at once and r6 takes r10; r11 is free again after the
+    // call's argument and is reused for its result
+    assert_eq!(
+        allocate(
+            "def f, 0
+            B0:
+              r5 = mov 1
+              r6 = mov 2
+              kill r5
+              r7 = mov 3
+              r7 = call @g, r7
+              r6 = add r6, r7
+              kill r7
+              br r6, B1, B2
+            B1:
+              r8 = mov s8
+              ret r8
+            B2:
+              ret"
+        ),
+        "def f, 0, 3
+B0:
+  r0 = mov 1
+  r0 = mov 2
+  r1 = mov 3
+  save r0
+  r1 = call @g, r1
+  r0 = restore
+  r0 = add r0, r1
+  br r0, B1, B2
+B1:
+  r0 = mov s8
+  ret r0
+B2:
+  ret
+"
+    );
+}
+
+#[test]
+fn live_range_test() {
+    // r0 lives through the loop and keeps its register; r3 is only
+    // written there
+    assert_eq!(
+        allocate(
+            "def f, 0
+            B0:
+              r0 = mov 1
+              r1 = mov 0
+              jmp B1
+            B1:
+              r2 = cmp.lt r1, 10
+              br r2, B2, B3
+            B2:
+              r3 = add r1, r0
+              r1 = mov r3
+              jmp B1
+            B3:
+              ret r1"
+        ),
+        "def f, 0, 7
+B0:
+  r0 = mov 1
+  r1 = mov 0
+  jmp B1
+B1:
+  r2 = cmp.lt r1, 10
+  br r2, B2, B3
+B2:
+  r2 = add r1, r0
+  r1 = mov r2
+  jmp B1
+B3:
+  ret r1
+"
+    );
+}
+
+#[test]
+fn spill_test() {
+    use interp::Interp;
+    use ir_text::parse_program;
+    // one value more than there are registers live at once: the sum,
+    // which lives longest, goes to a slot
+    let mut text = "def f, 0\nB0:\n".to_string();
+    for i in 0..=NUM_REGS {
+        text.push_str(&format!("r{} = mov {}\n", i, i));
     }
+    for i in 1..=NUM_REGS {
+        text.push_str(&format!("r0 = add r0, r{}\n", i));
+    }
+    text.push_str("ret r0\n");
+    let out = allocate(&text);
+    assert!(out.contains("store.8 s8, r"), "{}", out);
+    assert!(out.contains("= load.8 s8\n"), "{}", out);
+    let text = out.replacen("def f", "def main", 1);
+    let prog = parse_program(&text).unwrap();
+    assert_eq!(Interp::new(&prog).run(), Ok(66));
+}
+
+#[test]
+fn coalesce_test() {
+    // the copies vanish, and the sum is computed right in rdi (r7) for
+    // the call
+    assert_eq!(
+        allocate(
+            "def f, 0
+            B0:
+              r0 = mov 1
+              r1 = add r0, 2
+              r2 = mov r1
+              r3 = call @g, r2
+              r4 = mov r3
+              r5 = add r4, r0
+              ret r5"
+        ),
+        "def f, 0, 131
+B0:
+  r0 = mov 1
+  r7 = add r0, 2
+  save r0
+  r1 = call @g, r7
+  r0 = restore
+  r0 = add r1, r0
+  ret r0
+"
+    );
 }
@@ -0,0 +1,311 @@
+// Sparse conditional constant propagation, after Wegman and Zadeck.
+//
+// Every register starts out unknown and only moves down the lattice
+// unknown > constant > varying. Blocks are visited once an edge into
+// them is found to be executable, starting from the entry, and a branch
+// only marks the edges its condition allows, so a value that is constant
+// on every path that can actually run is found even through phis of
+// loops. Registers proved constant are replaced by their value, branches
+// on constants become jumps, and blocks no path reaches end in
+// `unreachable`, which is what the IR dump shows.
+
+use std::collections::{HashMap, HashSet};
+
+use cfg::{Function, Term};
+use fold::overflows;
+use gen_ir::{Ir, Op, Operand};
+use interp::evaluate;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Value {
+    Unknown,
+    Const(i64),
+    Varying,
+}
+
+// where a register is read: an instruction or the terminator of a block
+#[derive(Clone, Copy)]
+enum Use {
+    Ins(usize, usize),
+    Term(usize),
+}
+
+struct Sccp {
+    values: HashMap<isize, Value>,
+    uses: HashMap<isize, Vec<Use>>,
+    reached: Vec<bool>,
+    edges: HashSet<(usize, usize)>,
+    // edges found executable and registers whose value changed, yet to
+    // be followed
+    flow: Vec<(usize, usize)>,
+    changed: Vec<isize>,
+}
+
+pub fn sccp(f: &mut Function) {
+    if f.blocks.is_empty() {
+        return;
+    }
+    let mut s = Sccp {
+        values: HashMap::new(),
+        uses: HashMap::new(),
+        reached: vec![false; f.blocks.len()],
+        edges: HashSet::new(),
+        flow: vec![],
+        changed: vec![],
+    };
+    for (b, block) in f.blocks.iter().enumerate() {
+        for (i, ir) in block.ins.iter().enumerate() {
+            for r in ir.uses() {
+                s.uses.entry(r).or_default().push(Use::Ins(b, i));
+            }
+        }
+        if let Some(Operand::Reg(r)) = block.term.arg() {
+            s.uses.entry(*r).or_default().push(Use::Term(b));
+        }
+    }
+    s.reach(f, 0);
+    loop {
+        if let Some((p, b)) = s.flow.pop() {
+            if !s.edges.insert((p, b)) {
+                continue;
+            }
+            if s.reached[b] {
+                for ir in f.blocks[b].ins.iter().take_while(|ir| is_phi(ir)) {
+                    s.visit(ir, b);
+                }
+            } else {
+                s.reach(f, b);
+            }
+        } else if let Some(r) = s.changed.pop() {
+            for u in s.uses.get(&r).cloned().unwrap_or_default() {
+                match u {
+                    Use::Ins(b, i) if s.reached[b] => s.visit(&f.blocks[b].ins[i], b),
+                    Use::Term(b) if s.reached[b] => s.terminate(&f.blocks[b].term, b),
+                    _ => {}
+                }
+            }
+        } else {
+            break;
+        }
+    }
+    s.rewrite(f);
+}
+
+fn is_phi(ir: &Ir) -> bool {
+    matches!(ir.op, Op::Phi(_))
+}
+
+impl Sccp {
+    fn reach(&mut self, f: &Function, b: usize) {
+        self.reached[b] = true;
+        for ir in f.blocks[b].ins.iter() {
+            self.visit(ir, b);
+        }
+        self.terminate(&f.blocks[b].term, b);
+    }
+
+    fn value(&self, a: &Operand) -> Value {
+        match a {
+            Operand::Imm(n) => Value::Const(*n),
+            Operand::Reg(r) => *self.values.get(r).unwrap_or(&Value::Unknown),
+            _ => Value::Varying,
+        }
+    }
+
+    fn visit(&mut self, ir: &Ir, b: usize) {
+        let d = match ir.dst {
+            Some(d) => d,
+            None => return,
+        };
+        let v = match &ir.op {
+            // the meet of the values coming in over executable edges
+            Op::Phi(preds) => {
+                let mut v = Value::Unknown;
+                for (p, a) in preds.iter().zip(ir.args.iter()) {
+                    if !self.edges.contains(&(*p, b)) {
+                        continue;
+                    }
+                    v = match (v, self.value(a)) {
+                        (Value::Unknown, x) | (x, Value::Unknown) => x,
+                        (Value::Const(m), Value::Const(n)) if m == n => v,
+                        _ => Value::Varying,
+                    };
+                }
+                v
+            }
+            Op::Mov
+            | Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Udiv
+            | Op::Mod
+            | Op::Umod
+            | Op::And
+            | Op::Or
+            | Op::Xor
+            | Op::Shl
+            | Op::Shr
+            | Op::Sar
+            | Op::Mulh
+            | Op::Umulh
+            | Op::Cmp(_)
+            | Op::Neg
+            | Op::Sext
+            | Op::Zext => {
+                let values: Vec<Value> = ir.args.iter().map(|a| self.value(a)).collect();
+                if values.contains(&Value::Varying) {
+                    Value::Varying
+                } else if values.contains(&Value::Unknown) {
+                    Value::Unknown
+                } else {
+                    let args: Vec<u64> = values
+                        .iter()
+                        .map(|v| match v {
+                            Value::Const(n) => *n as u64,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    // an operation that traps or overflows is left to run
+                    // time, as in `fold`
+                    match evaluate(&ir.op, &args, ir.size) {
+                        Ok(n) if !overflows(ir, &args) => Value::Const(n as i64),
+                        _ => Value::Varying,
+                    }
+                }
+            }
+            _ => Value::Varying,
+        };
+        let old = *self.values.get(&d).unwrap_or(&Value::Unknown);
+        if v != old && old != Value::Varying {
+            self.values.insert(d, v);
+            self.changed.push(d);
+        }
+    }
+
+    fn terminate(&mut self, term: &Term, b: usize) {
+        match term {
+            Term::Br(a, then, els) => match self.value(a) {
+                Value::Const(n) => self.flow.push((b, if n != 0 { *then } else { *els })),
+                Value::Varying => {
+                    self.flow.push((b, *then));
+                    self.flow.push((b, *els));
+                }
+                Value::Unknown => {}
+            },
+            _ => {
+                for s in term.succs() {
+                    self.flow.push((b, s));
+                }
+            }
+        }
+    }
+
+    fn rewrite(&self, f: &mut Function) {
+        let constant = |a: &mut Operand| {
+            if let Operand::Reg(r) = a {
+                if let Some(Value::Const(n)) = self.values.get(r) {
+                    *a = Operand::Imm(*n);
+                }
+            }
+        };
+        for (b, block) in f.blocks.iter_mut().enumerate() {
+            if !self.reached[b] {
+                block.ins.clear();
+                block.term = Term::Unreachable;
+                continue;
+            }
+            for ir in block.ins.iter_mut() {
+                // the backend needs the callee of a call in a register
+                let skip = if ir.op == Op::Call { 1 } else { 0 };
+                for a in ir.args.iter_mut().skip(skip) {
+                    constant(a);
+                }
+                if let (Some(d), false) = (ir.dst, is_phi(ir)) {
+                    if let Some(Value::Const(n)) = self.values.get(&d) {
+                        *ir = Ir::new(Op::Mov, Some(d), vec![Operand::Imm(*n)], 8);
+                    }
+                }
+            }
+            if let Some(a) = block.term.arg_mut() {
+                constant(a);
+            }
+            if let Term::Br(Operand::Imm(n), then, els) = block.term {
+                block.term = Term::Jmp(if n != 0 { then } else { els });
+            }
+        }
+        f.compute_edges();
+
+        // phis only keep the operands of the edges left
+        for b in 0..f.blocks.len() {
+            let preds = f.blocks[b].preds.clone();
+            for ir in f.blocks[b].ins.iter_mut().take_while(|ir| is_phi(ir)) {
+                let mut args = vec![];
+                let mut kept = vec![];
+                if let Op::Phi(ps) = &ir.op {
+                    for (p, a) in ps.iter().zip(ir.args.iter()) {
+                        if preds.contains(p) && !kept.contains(p) {
+                            kept.push(*p);
+                            args.push(a.clone());
+                        }
+                    }
+                }
+                ir.op = Op::Phi(kept);
+                ir.args = args;
+            }
+        }
+    }
+}
+
+#[test]
+fn sccp_test() {
+    use ir_text::{parse, print};
+    // x = 1; while (g()) { if (x != 1) x = 2; } return x;
+    let mut funcs = parse(
+        "def f, 0
+        B0:
+          r0 = mov 1
+          jmp B1
+        B1:
+          r1 = phi B0: r0, B4: r4
+          r5 = call @g
+          br r5, B2, B5
+        B2:
+          r2 = cmp.ne r1, 1
+          br r2, B3, B4
+        B3:
+          r3 = mov 2
+          jmp B4
+        B4:
+          r4 = phi B2: r1, B3: r3
+          jmp B1
+        B5:
+          r6 = add.4 r1, 41
+          ret.4 r6",
+    )
+    .unwrap();
+    sccp(&mut funcs[0]);
+    assert_eq!(
+        print(&funcs),
+        "def f, 0
+B0:
+  r0 = mov 1
+  jmp B1
+B1:
+  r1 = phi B0: 1, B4: 1
+  r5 = call @g
+  br r5, B2, B5
+B2:
+  r2 = mov 0
+  jmp B4
+B3:
+  unreachable
+B4:
+  r4 = phi B2: 1
+  jmp B1
+B5:
+  r6 = mov 42
+  ret.4 42
+"
+    );
+}
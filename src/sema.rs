@@ -0,0 +1,1510 @@
+// Semantic analysis: name resolution and type checking.
+//
+// Every `Ident` is resolved to a `Lvar` of the enclosing function or a
+// `Gvar` label, every expression gets a `Ctype`, and implicit conversions
+// are made explicit with `Cast` nodes. The resulting typed AST is what
+// `GenIr` consumes. Static locals are hoisted to file-scope `VarDef`s
+// under unique labels. Diagnostics that do not stop compilation are
+// collected in `warnings`.
+
+use std::collections::HashMap;
+
+use node::{BinOp, Ctype, Node, NodeBase, Storage, StructBody, UnOp, Var};
+
+// what a name in block scope refers to
+#[derive(Clone)]
+enum Sym {
+    Local(usize),
+    Static(String, Ctype),
+}
+
+pub struct Sema {
+    funcs: HashMap<String, (Ctype, bool, Storage)>,
+    gvars: HashMap<String, Ctype>,
+    scopes: Vec<HashMap<String, Sym>>,
+    vars: Vec<Var>,
+    statics: Vec<Node>,
+    nlabel: usize,
+    ret: Ctype,
+    pub warnings: Vec<String>,
+}
+
+impl Default for Sema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sema {
+    pub fn new() -> Self {
+        Sema {
+            funcs: HashMap::new(),
+            gvars: HashMap::new(),
+            scopes: vec![],
+            vars: vec![],
+            statics: vec![],
+            nlabel: 0,
+            ret: Ctype::Int,
+            warnings: vec![],
+        }
+    }
+
+    pub fn run(&mut self, nodes: Vec<Node>) -> Result<Vec<Node>, String> {
+        let mut v = vec![];
+        for node in nodes {
+            v.push(self.global_def(node)?);
+        }
+        v.append(&mut self.statics);
+        Ok(v)
+    }
+}
+
+impl Sema {
+    fn global_def(&mut self, node: Node) -> Result<Node, String> {
+        match node.base {
+            NodeBase::DefFun(ret, id, args, stmts, _, storage) => {
+                let name = Sema::ident(&id)?;
+                let params = args.iter().map(|(t, _)| t.clone()).collect();
+                let t = Ctype::Func(Box::new(ret.clone()), params);
+                Sema::check_type(&t)?;
+                let storage = self.declare_fun(&name, t, true, storage)?;
+
+                self.scopes = vec![HashMap::new()];
+                self.vars = vec![];
+                self.ret = ret.clone();
+
+                let mut typed_args = vec![];
+                for (t, arg) in args {
+                    let argname = Sema::ident(&arg)?;
+                    if argname.is_empty() {
+                        return Err(format!("parameter name omitted in '{}'", name));
+                    }
+                    let i = self.declare_local(&argname, t.clone())?;
+                    typed_args.push((t.clone(), Node::typed(NodeBase::Lvar(i), t)));
+                }
+                let mut stmts = self.statement(*stmts)?;
+                if *ret.unqual() != Ctype::Void && Sema::falls_through(&stmts) {
+                    // reaching the end of main returns 0; using the value
+                    // of another function that does so is undefined
+                    if name == "main" {
+                        let zero = Node::typed(NodeBase::Number(0), Ctype::Int);
+                        let ret = Node::new(NodeBase::Return(Some(Box::new(zero))));
+                        if let NodeBase::Statements(v) = &mut stmts.base {
+                            v.push(Box::new(ret));
+                        }
+                    } else {
+                        self.warnings.push(format!(
+                            "control may reach end of non-void function '{}'",
+                            name
+                        ));
+                    }
+                }
+                let vars = ::std::mem::take(&mut self.vars);
+                self.scopes = vec![];
+
+                Ok(Node::new(NodeBase::DefFun(
+                    ret,
+                    id,
+                    typed_args,
+                    Box::new(stmts),
+                    vars,
+                    storage,
+                )))
+            }
+            NodeBase::DecFun(ret, id, args, storage) => {
+                let name = Sema::ident(&id)?;
+                let params = args.iter().map(|(t, _)| t.clone()).collect();
+                let t = Ctype::Func(Box::new(ret.clone()), params);
+                Sema::check_type(&t)?;
+                let storage = self.declare_fun(&name, t, false, storage)?;
+                Ok(Node::new(NodeBase::DecFun(ret, id, args, storage)))
+            }
+            NodeBase::VarDef(t, name, init, storage) => {
+                Sema::check_var_type(&t, &name)?;
+                if self.funcs.contains_key(&name) {
+                    return Err(format!("'{}' redeclared as different kind of symbol", name));
+                }
+                if self.gvars.contains_key(&name) {
+                    return Err(format!("redefinition of '{}'", name));
+                }
+                self.gvars.insert(name.clone(), t.clone());
+                let init = self.const_init(init, &t)?;
+                Ok(Node::new(NodeBase::VarDef(t, name, init, storage)))
+            }
+            // declaration of a struct type only
+            NodeBase::Statements(v) if v.is_empty() => Ok(Node::new(NodeBase::Statements(v))),
+            _ => Err("expected a declaration".to_string()),
+        }
+    }
+
+    fn statement(&mut self, node: Node) -> Result<Node, String> {
+        match node.base {
+            NodeBase::Return(e) => {
+                let void = *self.ret.unqual() == Ctype::Void;
+                let e = match e {
+                    Some(_) if void => {
+                        return Err("'return' with a value, in function returning void".to_string())
+                    }
+                    Some(e) => *e,
+                    None if void => return Ok(Node::new(NodeBase::Return(None))),
+                    None => {
+                        return Err(
+                            "'return' with no value, in function returning non-void".to_string()
+                        )
+                    }
+                };
+                let e = self.rvalue(e)?;
+                let ret = self.ret.clone();
+                let e = Sema::convert(e, &ret, "return")?;
+                Ok(Node::new(NodeBase::Return(Some(Box::new(e)))))
+            }
+            NodeBase::Statements(ndv) => {
+                self.scopes.push(HashMap::new());
+                let mut v = vec![];
+                for nd in ndv {
+                    v.push(Box::new(self.statement(*nd)?));
+                }
+                self.scopes.pop();
+                Ok(Node::new(NodeBase::Statements(v)))
+            }
+            NodeBase::If(cond, then, els) => {
+                let cond = self.condition(*cond)?;
+                let then = self.statement(*then)?;
+                let els = match els {
+                    Some(e) => Some(Box::new(self.statement(*e)?)),
+                    None => None,
+                };
+                Ok(Node::new(NodeBase::If(Box::new(cond), Box::new(then), els)))
+            }
+            NodeBase::While(cond, body) => {
+                let cond = self.condition(*cond)?;
+                let body = self.statement(*body)?;
+                Ok(Node::new(NodeBase::While(Box::new(cond), Box::new(body))))
+            }
+            // the loop is a scope of its own for a declaration in init
+            NodeBase::For(init, cond, inc, body) => {
+                self.scopes.push(HashMap::new());
+                let init = match init {
+                    Some(e) => Some(Box::new(self.statement(*e)?)),
+                    None => None,
+                };
+                let cond = match cond {
+                    Some(e) => Some(Box::new(self.condition(*e)?)),
+                    None => None,
+                };
+                let inc = match inc {
+                    Some(e) => Some(Box::new(self.rvalue(*e)?)),
+                    None => None,
+                };
+                let body = self.statement(*body)?;
+                self.scopes.pop();
+                Ok(Node::new(NodeBase::For(init, cond, inc, Box::new(body))))
+            }
+            // A static local lives in .data/.bss under a unique label and is
+            // initialized once, at load time.
+            NodeBase::VarDef(t, name, init, storage) if storage.is_static => {
+                Sema::check_var_type(&t, &name)?;
+                let label = format!("{}.{}", name, self.nlabel);
+                self.nlabel += 1;
+                self.declare(&name, Sym::Static(label.clone(), t.clone()))?;
+                let init = self.const_init(init, &t)?;
+                self.statics
+                    .push(Node::new(NodeBase::VarDef(t, label, init, storage)));
+                Ok(Node::new(NodeBase::Statements(vec![])))
+            }
+            NodeBase::VarDef(t, name, init, _) => {
+                Sema::check_var_type(&t, &name)?;
+                let i = self.declare_local(&name, t.clone())?;
+                match init {
+                    Some(e) => {
+                        // initialization, unlike assignment, may target a const object
+                        let lhs = Node::typed(NodeBase::Lvar(i), t.clone());
+                        let rhs = self.rvalue(*e)?;
+                        let rhs = Sema::convert(rhs, &t, "initialization")?;
+                        Ok(Node::typed(
+                            NodeBase::Assign(Box::new(lhs), Box::new(rhs)),
+                            t,
+                        ))
+                    }
+                    None => Ok(Node::new(NodeBase::Statements(vec![]))),
+                }
+            }
+            // the value of an expression statement is discarded, so it
+            // may be void; a cast keeps its target type
+            base => {
+                let e = Node {
+                    base,
+                    ctype: node.ctype,
+                };
+                Ok(Sema::decay(self.expr(e)?))
+            }
+        }
+    }
+
+    fn expr(&mut self, node: Node) -> Result<Node, String> {
+        match node.base {
+            NodeBase::Number(n) => Ok(Node::typed(NodeBase::Number(n), Sema::literal_type(n))),
+            NodeBase::Ident(s) => {
+                match self.find_var(&s) {
+                    Some(Sym::Local(i)) => {
+                        let t = self.vars[i].ctype.clone();
+                        return Ok(Node::typed(NodeBase::Lvar(i), t));
+                    }
+                    Some(Sym::Static(label, t)) => {
+                        return Ok(Node::typed(NodeBase::Gvar(label), t));
+                    }
+                    None => {}
+                }
+                if let Some(t) = self.gvars.get(&s) {
+                    return Ok(Node::typed(NodeBase::Gvar(s), t.clone()));
+                }
+                match self.funcs.get(&s) {
+                    Some((t, _, _)) => Ok(Node::typed(NodeBase::Func(s), t.clone())),
+                    None => Err(format!("undefined variable '{}'", s)),
+                }
+            }
+            NodeBase::Call(s, args) => {
+                // a function pointer variable shadows a function of the same name
+                if self.find_var(&s).is_some() || self.gvars.contains_key(&s) {
+                    let f = Box::new(Node::new(NodeBase::Ident(s)));
+                    return self.expr(Node::new(NodeBase::CallPtr(f, args)));
+                }
+                let t = match self.funcs.get(&s) {
+                    Some((t, _, _)) => t.clone(),
+                    None => return Err(format!("undefined function '{}'", s)),
+                };
+                let (ret, args) = self.call_args(&s, &t, args)?;
+                Ok(Node::typed(NodeBase::Call(s, args), ret))
+            }
+            NodeBase::CallPtr(f, args) => {
+                let f = self.rvalue(*f)?;
+                let t = match f.ty().pointee() {
+                    Some(t) => t.unqual().clone(),
+                    None => return Err("called object is not a function".to_string()),
+                };
+                let name = match &f.base {
+                    NodeBase::Addr(e) => match &e.base {
+                        NodeBase::Func(s) => Some(s.to_string()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let (ret, args) =
+                    self.call_args(name.as_ref().map_or("function pointer", |s| s), &t, args)?;
+                match name {
+                    // calling a function designator directly, e.g. `(*f)(x)`
+                    Some(s) => Ok(Node::typed(NodeBase::Call(s, args), ret)),
+                    None => Ok(Node::typed(NodeBase::CallPtr(Box::new(f), args), ret)),
+                }
+            }
+            NodeBase::UnaryOp(op, e) => {
+                let e = self.rvalue(*e)?;
+                if !e.ty().is_arithmetic() {
+                    return Err(format!("invalid operand to unary {:?}", op));
+                }
+                let t = Sema::promote(e.ty());
+                let e = Sema::convert(e, &t, "unary operation")?;
+                Ok(Node::typed(NodeBase::UnaryOp(op, Box::new(e)), t))
+            }
+            NodeBase::BinaryOp(op, lhs, rhs) => self.binary_op(op, *lhs, *rhs),
+            NodeBase::Cast(e) => {
+                let t = node.ctype.expect("cast without a type");
+                // any value may be discarded with a cast to void
+                if *t.unqual() == Ctype::Void {
+                    let e = Sema::decay(self.expr(*e)?);
+                    return Ok(Node::typed(NodeBase::Cast(Box::new(e)), Ctype::Void));
+                }
+                let e = self.rvalue(*e)?;
+                if !e.ty().is_scalar() || !t.is_scalar() {
+                    return Err("invalid cast".to_string());
+                }
+                Ok(Sema::cast(e, t.unqual()))
+            }
+            NodeBase::Deref(e) => {
+                let e = self.rvalue(*e)?;
+                let t = match e.ty().pointee() {
+                    Some(t) => t.clone(),
+                    None => return Err("invalid type argument of unary '*'".to_string()),
+                };
+                Ok(Node::typed(NodeBase::Deref(Box::new(e)), t))
+            }
+            NodeBase::Member(e, name) => {
+                let e = self.expr(*e)?;
+                let st = match e.ty().unqual() {
+                    Ctype::Struct(st) => st.clone(),
+                    _ => {
+                        return Err(format!(
+                            "request for member '{}' in something not a structure",
+                            name
+                        ))
+                    }
+                };
+                let body = match st.body() {
+                    Some(body) => body,
+                    None => {
+                        return Err(format!("invalid use of undefined type 'struct {}'", st.tag))
+                    }
+                };
+                Sema::check_struct(&body)?;
+                let t = match body.member(&name) {
+                    // members of a qualified struct are qualified too
+                    Some(m) => Ctype::qualified(m.ctype.clone(), e.ty().quals()),
+                    None => {
+                        return Err(format!(
+                            "'struct {}' has no member named '{}'",
+                            st.tag, name
+                        ))
+                    }
+                };
+                Ok(Node::typed(NodeBase::Member(Box::new(e), name), t))
+            }
+            NodeBase::Addr(e) => {
+                let e = self.expr(*e)?;
+                if let Ctype::Func(..) = e.ty() {
+                    return Ok(Sema::decay(e));
+                }
+                if e.bit_field().is_some() {
+                    return Err("cannot take address of bit-field".to_string());
+                }
+                if !Sema::is_lvalue(&e) {
+                    return Err("lvalue required as unary '&' operand".to_string());
+                }
+                let t = Ctype::Ptr(Box::new(e.ty().clone()));
+                Ok(Node::typed(NodeBase::Addr(Box::new(e)), t))
+            }
+            NodeBase::Sizeof(e) => {
+                let e = self.expr(*e)?;
+                if let Ctype::Func(..) = e.ty() {
+                    return Err("invalid application of 'sizeof' to a function type".to_string());
+                }
+                if e.bit_field().is_some() {
+                    return Err("'sizeof' applied to a bit-field".to_string());
+                }
+                if *e.ty().unqual() == Ctype::Void {
+                    return Err("invalid application of 'sizeof' to a void type".to_string());
+                }
+                let size = Node::typed(NodeBase::Number(e.ty().size()), Ctype::Int);
+                Ok(Node::typed(NodeBase::Cast(Box::new(size)), Ctype::ULong))
+            }
+            NodeBase::OpAssign(op, lhs, rhs) => {
+                let lhs = self.expr(*lhs)?;
+                Sema::check_assign(&lhs)?;
+                let t = lhs.ty().clone();
+                let cur = Node::typed(NodeBase::Loaded, t.unqual().clone());
+                let rhs = self.rvalue(*rhs)?;
+                let value = Sema::typed_binary_op(op, cur, rhs)?;
+                let value = Sema::convert(value, &t, "assignment")?;
+                Ok(Node::typed(
+                    NodeBase::OpAssign(op, Box::new(lhs), Box::new(value)),
+                    t,
+                ))
+            }
+            NodeBase::Assign(lhs, rhs) => {
+                let lhs = self.expr(*lhs)?;
+                Sema::check_assign(&lhs)?;
+                let t = lhs.ty().clone();
+                let rhs = self.rvalue(*rhs)?;
+                let rhs = Sema::convert(rhs, &t, "assignment")?;
+                Ok(Node::typed(
+                    NodeBase::Assign(Box::new(lhs), Box::new(rhs)),
+                    t,
+                ))
+            }
+            _ => Err("expected an expression".to_string()),
+        }
+    }
+
+    fn check_assign(lhs: &Node) -> Result<(), String> {
+        if let Ctype::Array(..) = lhs.ty().unqual() {
+            return Err("assignment to expression with array type".to_string());
+        }
+        if !Sema::is_lvalue(lhs) {
+            return Err("lvalue required as left operand of assignment".to_string());
+        }
+        if lhs.ty().is_const() {
+            return Err("assignment of read-only location".to_string());
+        }
+        Ok(())
+    }
+
+    // an expression used for its value; function designators decay to
+    // pointers
+    fn rvalue(&mut self, node: Node) -> Result<Node, String> {
+        let node = self.expr(node)?;
+        if *node.ty().unqual() == Ctype::Void {
+            return Err("void value not ignored as it ought to be".to_string());
+        }
+        // an unsigned bit-field narrower than int promotes to int
+        if let Some(bits) = node.bit_field() {
+            if bits.width < Ctype::Int.size() * 8 && node.ty().is_unsigned() {
+                return Ok(Sema::cast(node, &Ctype::Int));
+            }
+        }
+        Ok(Sema::decay(node))
+    }
+
+    // the controlling expression of an `if` or a loop
+    fn condition(&mut self, node: Node) -> Result<Node, String> {
+        let node = self.rvalue(node)?;
+        if !node.ty().is_scalar() {
+            return Err("used struct type value where scalar is required".to_string());
+        }
+        Ok(node)
+    }
+
+    // Whether control can reach the end of a statement. Without `break`
+    // a loop whose condition is a nonzero constant never finishes.
+    fn falls_through(node: &Node) -> bool {
+        match &node.base {
+            NodeBase::Return(_) => false,
+            NodeBase::Statements(v) => v.iter().all(|s| Sema::falls_through(s)),
+            NodeBase::If(_, then, Some(els)) => {
+                Sema::falls_through(then) || Sema::falls_through(els)
+            }
+            NodeBase::While(cond, _) | NodeBase::For(_, Some(cond), _, _) => {
+                Sema::eval(cond).is_none_or(|v| v == 0)
+            }
+            NodeBase::For(_, None, _, _) => false,
+            _ => true,
+        }
+    }
+
+    fn call_args(
+        &mut self,
+        name: &str,
+        t: &Ctype,
+        args: Vec<Node>,
+    ) -> Result<(Ctype, Vec<Node>), String> {
+        let (ret, params) = match t {
+            Ctype::Func(ret, params) => (ret.unqual().clone(), params.clone()),
+            _ => return Err(format!("called object '{}' is not a function", name)),
+        };
+        if args.len() != params.len() {
+            return Err(format!(
+                "wrong number of arguments to '{}': expected {}, got {}",
+                name,
+                params.len(),
+                args.len()
+            ));
+        }
+        let mut v = vec![];
+        for (arg, param) in args.into_iter().zip(params.iter()) {
+            let arg = self.rvalue(arg)?;
+            v.push(Sema::convert(arg, param, "argument")?);
+        }
+        Ok((ret, v))
+    }
+
+    fn binary_op(&mut self, op: BinOp, lhs: Node, rhs: Node) -> Result<Node, String> {
+        let lhs = self.rvalue(lhs)?;
+        let rhs = self.rvalue(rhs)?;
+        Sema::typed_binary_op(op, lhs, rhs)
+    }
+
+    fn typed_binary_op(op: BinOp, lhs: Node, rhs: Node) -> Result<Node, String> {
+        if lhs.ty().is_pointer() || rhs.ty().is_pointer() {
+            return Sema::pointer_op(op, lhs, rhs);
+        }
+        if !lhs.ty().is_arithmetic() || !rhs.ty().is_arithmetic() {
+            return Err(format!("invalid operands to binary {:?}", op));
+        }
+        let integer_only = matches!(
+            op,
+            BinOp::Mod | BinOp::And | BinOp::Or | BinOp::Xor | BinOp::Shl | BinOp::Shr
+        );
+        if integer_only && (!lhs.ty().is_integer() || !rhs.ty().is_integer()) {
+            return Err(format!("invalid operands to binary {:?}", op));
+        }
+        // the operands of a shift are promoted separately
+        if let BinOp::Shl | BinOp::Shr = op {
+            let t = Sema::promote(lhs.ty());
+            let rt = Sema::promote(rhs.ty());
+            let lhs = Sema::convert(lhs, &t, "binary operation")?;
+            let rhs = Sema::convert(rhs, &rt, "binary operation")?;
+            return Ok(Sema::fold(Node::typed(
+                NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)),
+                t,
+            )));
+        }
+        let t = Sema::arith_conv(lhs.ty(), rhs.ty());
+        let lhs = Sema::convert(lhs, &t, "binary operation")?;
+        let rhs = Sema::convert(rhs, &t, "binary operation")?;
+        let t = match op {
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge | BinOp::Eq | BinOp::Ne => Ctype::Int,
+            _ => t,
+        };
+        Ok(Sema::fold(Node::typed(
+            NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)),
+            t,
+        )))
+    }
+
+    // Replaces an operation on integer constants by its value. Unsigned
+    // results wrap around; a signed result that overflows, a division by
+    // zero or a shift out of range is undefined and left to run time.
+    fn fold(node: Node) -> Node {
+        let v = match &node.base {
+            NodeBase::BinaryOp(op, lhs, rhs) => match (Sema::constant(lhs), Sema::constant(rhs)) {
+                (Some(l), Some(r)) => Sema::fold_binary(*op, l, r, lhs.ty(), node.ty()),
+                _ => None,
+            },
+            _ => None,
+        };
+        match v {
+            Some(v) => Node::typed(NodeBase::Number(v as usize), node.ty().clone()),
+            None => node,
+        }
+    }
+
+    // value of an integer constant, possibly converted or negated
+    fn constant(node: &Node) -> Option<i64> {
+        if !node.ty().is_integer() {
+            return None;
+        }
+        match &node.base {
+            NodeBase::Number(_) => Sema::eval(node),
+            NodeBase::Cast(e) => Sema::constant(e).and(Sema::eval(node)),
+            NodeBase::UnaryOp(UnOp::Neg, e) => {
+                let t = node.ty();
+                Sema::fold_binary(BinOp::Sub, 0, Sema::constant(e)?, t, t)
+            }
+            _ => None,
+        }
+    }
+
+    // `l op r` for operands of type `t`, computed exactly and then
+    // brought into the result type `rt`
+    fn fold_binary(op: BinOp, l: i64, r: i64, t: &Ctype, rt: &Ctype) -> Option<i64> {
+        let exact = |v: i64| {
+            if t.is_unsigned() {
+                v as u64 as i128
+            } else {
+                v as i128
+            }
+        };
+        let (l0, r0) = (l, r);
+        let (l, r) = (exact(l), exact(r));
+        let bits = rt.size() * 8;
+        let count = || {
+            if (0..bits as i128).contains(&r) {
+                Some(r as u32)
+            } else {
+                None
+            }
+        };
+        let v = match op {
+            BinOp::Add => l + r,
+            BinOp::Sub => l - r,
+            BinOp::Mul => l.checked_mul(r)?,
+            BinOp::Div | BinOp::Mod if r == 0 => return None,
+            BinOp::Div => l / r,
+            // INT_MIN % -1 is undefined like INT_MIN / -1
+            BinOp::Mod if Sema::fold_binary(BinOp::Div, l0, r0, t, rt).is_none() => return None,
+            BinOp::Mod => l % r,
+            BinOp::And => l & r,
+            BinOp::Or => l | r,
+            BinOp::Xor => l ^ r,
+            BinOp::Shl if l < 0 => return None,
+            BinOp::Shl => l << count()?,
+            BinOp::Shr => l >> count()?,
+            BinOp::Eq => (l == r) as i128,
+            BinOp::Ne => (l != r) as i128,
+            BinOp::Lt => (l < r) as i128,
+            BinOp::Le => (l <= r) as i128,
+            BinOp::Gt => (l > r) as i128,
+            BinOp::Ge => (l >= r) as i128,
+        };
+        if rt.is_unsigned() {
+            let v = v & ((1i128 << bits) - 1);
+            return Some(v as u64 as i64);
+        }
+        let max = (1i128 << (bits - 1)) - 1;
+        if v < -max - 1 || v > max {
+            return None;
+        }
+        Some(v as i64)
+    }
+}
+
+impl Sema {
+    fn pointer_op(op: BinOp, lhs: Node, rhs: Node) -> Result<Node, String> {
+        let long = |base| Node::typed(base, Ctype::Long);
+        match op {
+            BinOp::Add if lhs.ty().is_integer() => Sema::pointer_op(op, rhs, lhs),
+            BinOp::Add | BinOp::Sub if rhs.ty().is_integer() => {
+                let t = lhs.ty().unqual().clone();
+                let size = t.pointee().unwrap().size();
+                if size == 0 {
+                    return Err("arithmetic on a pointer to an incomplete type".to_string());
+                }
+                let rhs = Sema::cast(rhs, &Ctype::Long);
+                let rhs = if size == 1 {
+                    rhs
+                } else {
+                    let size = long(NodeBase::Number(size));
+                    long(NodeBase::BinaryOp(
+                        BinOp::Mul,
+                        Box::new(rhs),
+                        Box::new(size),
+                    ))
+                };
+                Ok(Node::typed(
+                    NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)),
+                    t,
+                ))
+            }
+            BinOp::Sub if rhs.ty().is_pointer() => {
+                let size = match (lhs.ty().pointee(), rhs.ty().pointee()) {
+                    (Some(l), Some(r)) if l.unqual() == r.unqual() => l.size(),
+                    _ => return Err("invalid operands to binary -".to_string()),
+                };
+                if size == 0 {
+                    return Err("arithmetic on a pointer to an incomplete type".to_string());
+                }
+                let diff = long(NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)));
+                let size = long(NodeBase::Number(size));
+                Ok(long(NodeBase::BinaryOp(
+                    BinOp::Div,
+                    Box::new(diff),
+                    Box::new(size),
+                )))
+            }
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge | BinOp::Eq | BinOp::Ne => {
+                let (lhs, rhs) = if lhs.ty().is_pointer() {
+                    let t = lhs.ty().clone();
+                    (lhs, Sema::convert(rhs, &t, "comparison")?)
+                } else {
+                    let t = rhs.ty().clone();
+                    (Sema::convert(lhs, &t, "comparison")?, rhs)
+                };
+                Ok(Node::typed(
+                    NodeBase::BinaryOp(op, Box::new(lhs), Box::new(rhs)),
+                    Ctype::Int,
+                ))
+            }
+            _ => Err(format!("invalid operands to binary {:?}", op)),
+        }
+    }
+
+    // Declares a function and returns its storage merged with earlier
+    // declarations: once static, a function keeps internal linkage.
+    fn declare_fun(
+        &mut self,
+        name: &str,
+        t: Ctype,
+        def: bool,
+        storage: Storage,
+    ) -> Result<Storage, String> {
+        if self.gvars.contains_key(name) {
+            return Err(format!("'{}' redeclared as different kind of symbol", name));
+        }
+        let mut storage = storage;
+        let mut def = def;
+        if let Some((prev, defined, prev_storage)) = self.funcs.get(name) {
+            if *prev != t {
+                return Err(format!("conflicting types for '{}'", name));
+            }
+            if *defined && def {
+                return Err(format!("redefinition of '{}'", name));
+            }
+            if storage.is_static && !prev_storage.is_static {
+                return Err(format!(
+                    "static declaration of '{}' follows non-static declaration",
+                    name
+                ));
+            }
+            storage.is_static = prev_storage.is_static;
+            def = def || *defined;
+        }
+        self.funcs.insert(name.to_string(), (t, def, storage));
+        Ok(storage)
+    }
+
+    fn declare(&mut self, name: &str, sym: Sym) -> Result<(), String> {
+        let scope = self.scopes.last_mut().expect("no scope");
+        if scope.contains_key(name) {
+            return Err(format!("redefinition of '{}'", name));
+        }
+        scope.insert(name.to_string(), sym);
+        Ok(())
+    }
+
+    fn declare_local(&mut self, name: &str, t: Ctype) -> Result<usize, String> {
+        let i = self.vars.len();
+        self.declare(name, Sym::Local(i))?;
+        self.vars.push(Var {
+            name: name.to_string(),
+            ctype: t,
+        });
+        Ok(i)
+    }
+
+    fn find_var(&self, name: &str) -> Option<Sym> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(sym) = scope.get(name) {
+                return Some(sym.clone());
+            }
+        }
+        None
+    }
+
+    fn check_var_type(t: &Ctype, name: &str) -> Result<(), String> {
+        Sema::check_type(t)?;
+        match t.unqual() {
+            Ctype::Struct(_) if t.size() == 0 => {
+                Err(format!("storage size of '{}' isn't known", name))
+            }
+            Ctype::Void => Err(format!("variable or field '{}' declared void", name)),
+            Ctype::Array(_, 0) => Err(format!("array size missing in '{}'", name)),
+            Ctype::Func(..) => Err(format!("'{}' declared as a function", name)),
+            _ => Ok(()),
+        }
+    }
+
+    // Initializer of an object with static storage duration; it must be a
+    // constant and is replaced by its value.
+    fn const_init(
+        &mut self,
+        init: Option<Box<Node>>,
+        t: &Ctype,
+    ) -> Result<Option<Box<Node>>, String> {
+        let e = match init {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+        let e = self.rvalue(*e)?;
+        let e = Sema::convert(e, t, "initialization")?;
+        match Sema::eval(&e) {
+            Some(n) => Ok(Some(Box::new(Node::typed(
+                NodeBase::Number(n as usize),
+                t.unqual().clone(),
+            )))),
+            None => Err("initializer element is not constant".to_string()),
+        }
+    }
+
+    // Length of an array declarator, which must be an integer constant
+    // expression; the parser calls this before any scope exists.
+    pub fn array_len(node: Node) -> Result<usize, String> {
+        let e = Sema::new().rvalue(node)?;
+        if !e.ty().is_integer() {
+            return Err("size of array has non-integer type".to_string());
+        }
+        match Sema::eval(&e) {
+            Some(n) if n < 0 => Err("size of array is negative".to_string()),
+            Some(n) => Ok(n as usize),
+            None => Err("array size is not an integer constant".to_string()),
+        }
+    }
+
+    // value of an integer constant expression, truncated to its type
+    fn eval(node: &Node) -> Option<i64> {
+        let v = match &node.base {
+            NodeBase::Number(n) => *n as i64,
+            NodeBase::Cast(e) if *node.ty().unqual() == Ctype::Bool => {
+                return Some((Sema::eval(e)? != 0) as i64)
+            }
+            NodeBase::Cast(e) => Sema::eval(e)?,
+            NodeBase::UnaryOp(_, e) => Sema::eval(e)?.wrapping_neg(),
+            NodeBase::BinaryOp(op, lhs, rhs) => {
+                let unsigned = lhs.ty().is_unsigned() || lhs.ty().is_pointer();
+                let (l, r) = (Sema::eval(lhs)?, Sema::eval(rhs)?);
+                // shifting by the width or more is undefined
+                let count = || {
+                    if (0..64).contains(&r) {
+                        Some(r as u32)
+                    } else {
+                        None
+                    }
+                };
+                match op {
+                    BinOp::Add => l.wrapping_add(r),
+                    BinOp::Sub => l.wrapping_sub(r),
+                    BinOp::Mul => l.wrapping_mul(r),
+                    BinOp::Div | BinOp::Mod if r == 0 => return None,
+                    BinOp::Div if unsigned => ((l as u64) / (r as u64)) as i64,
+                    BinOp::Div => l.wrapping_div(r),
+                    BinOp::Mod if unsigned => ((l as u64) % (r as u64)) as i64,
+                    BinOp::Mod => l.wrapping_rem(r),
+                    BinOp::And => l & r,
+                    BinOp::Or => l | r,
+                    BinOp::Xor => l ^ r,
+                    BinOp::Shl => l.wrapping_shl(count()?),
+                    BinOp::Shr if unsigned => ((l as u64) >> count()?) as i64,
+                    BinOp::Shr => l >> count()?,
+                    BinOp::Eq => (l == r) as i64,
+                    BinOp::Ne => (l != r) as i64,
+                    BinOp::Lt if unsigned => ((l as u64) < (r as u64)) as i64,
+                    BinOp::Le if unsigned => ((l as u64) <= (r as u64)) as i64,
+                    BinOp::Gt if unsigned => ((l as u64) > (r as u64)) as i64,
+                    BinOp::Ge if unsigned => ((l as u64) >= (r as u64)) as i64,
+                    BinOp::Lt => (l < r) as i64,
+                    BinOp::Le => (l <= r) as i64,
+                    BinOp::Gt => (l > r) as i64,
+                    BinOp::Ge => (l >= r) as i64,
+                }
+            }
+            _ => return None,
+        };
+        let bits = node.ty().size() * 8;
+        if bits >= 64 {
+            return Some(v);
+        }
+        let v = v & ((1i64 << bits) - 1);
+        if !node.ty().is_unsigned() && v >> (bits - 1) == 1 {
+            Some(v - (1i64 << bits))
+        } else {
+            Some(v)
+        }
+    }
+
+    fn ident(node: &Node) -> Result<String, String> {
+        match &node.base {
+            NodeBase::Ident(s) => Ok(s.to_string()),
+            _ => Err("expected an identifier".to_string()),
+        }
+    }
+
+    fn is_lvalue(node: &Node) -> bool {
+        let object = !matches!(node.ty(), Ctype::Func(..));
+        object
+            && matches!(
+                node.base,
+                NodeBase::Lvar(_) | NodeBase::Gvar(_) | NodeBase::Deref(_) | NodeBase::Member(..)
+            )
+    }
+
+    // function designators decay to function pointers and arrays to
+    // pointers to their first element
+    fn decay(node: Node) -> Node {
+        let t = match node.ty().unqual() {
+            Ctype::Func(..) => Ctype::Ptr(Box::new(node.ty().clone())),
+            Ctype::Array(t, _) => Ctype::Ptr(t.clone()),
+            _ => return node,
+        };
+        Node::typed(NodeBase::Addr(Box::new(node)), t)
+    }
+
+    // Struct bodies are not checked through pointers, which may point to
+    // incomplete or self-referential structs.
+    fn check_type(t: &Ctype) -> Result<(), String> {
+        match t.unqual() {
+            Ctype::Ptr(t) if matches!(t.unqual(), Ctype::Struct(_)) => Ok(()),
+            Ctype::Ptr(t) => Sema::check_type(t),
+            Ctype::Struct(st) => match st.body() {
+                Some(body) => Sema::check_struct(&body),
+                None => Ok(()),
+            },
+            Ctype::Array(t, _) => match t.unqual() {
+                Ctype::Func(..) => Err("declaration of an array of functions".to_string()),
+                Ctype::Void => Err("declaration of an array of voids".to_string()),
+                _ => Sema::check_type(t),
+            },
+            Ctype::Func(ret, params) => {
+                match ret.unqual() {
+                    Ctype::Array(..) => return Err("function returning an array".to_string()),
+                    Ctype::Func(..) => return Err("function returning a function".to_string()),
+                    _ => {}
+                }
+                Sema::check_type(ret)?;
+                for param in params {
+                    if *param.unqual() == Ctype::Void {
+                        return Err("'void' must be the only parameter".to_string());
+                    }
+                    Sema::check_type(param)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_struct(body: &StructBody) -> Result<(), String> {
+        for (i, m) in body.members.iter().enumerate() {
+            let name = &m.name;
+            if body.members[..i]
+                .iter()
+                .any(|n| !name.is_empty() && n.name == *name)
+            {
+                return Err(format!("duplicate member '{}'", name));
+            }
+            if let Ctype::Func(..) = m.ctype.unqual() {
+                return Err(format!("field '{}' declared as a function", name));
+            }
+            Sema::check_type(&m.ctype)?;
+            let bits = match m.bits {
+                Some(bits) => bits,
+                None if m.ctype.size() == 0 => {
+                    return Err(format!("field '{}' has incomplete type", name))
+                }
+                None => continue,
+            };
+            if !m.ctype.is_integer() {
+                return Err(format!("bit-field '{}' has invalid type", name));
+            }
+            if bits.width > m.ctype.size() * 8 {
+                return Err(format!("width of '{}' exceeds its type", name));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_null(node: &Node) -> bool {
+        matches!(node.base, NodeBase::Number(0))
+    }
+
+    // An unsuffixed decimal constant has the first of int, long and
+    // unsigned long in which its value fits.
+    fn literal_type(n: usize) -> Ctype {
+        if n <= i32::MAX as usize {
+            Ctype::Int
+        } else if n <= i64::MAX as usize {
+            Ctype::Long
+        } else {
+            Ctype::ULong
+        }
+    }
+
+    // integer promotions
+    fn promote(t: &Ctype) -> Ctype {
+        let t = t.unqual();
+        if t.is_integer() && t.rank() < Ctype::Int.rank() {
+            Ctype::Int
+        } else {
+            t.clone()
+        }
+    }
+
+    // usual arithmetic conversions
+    fn arith_conv(lhs: &Ctype, rhs: &Ctype) -> Ctype {
+        let lhs = Sema::promote(lhs);
+        let rhs = Sema::promote(rhs);
+        if lhs == rhs {
+            return lhs;
+        }
+        if lhs.is_unsigned() == rhs.is_unsigned() {
+            return if lhs.rank() >= rhs.rank() { lhs } else { rhs };
+        }
+        let (u, s) = if lhs.is_unsigned() {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        if u.rank() >= s.rank() {
+            u
+        } else if s.size() > u.size() {
+            s
+        } else {
+            s.to_unsigned()
+        }
+    }
+
+    fn cast(node: Node, to: &Ctype) -> Node {
+        if node.ty().unqual() == to.unqual() {
+            return node;
+        }
+        Node::typed(NodeBase::Cast(Box::new(node)), to.unqual().clone())
+    }
+
+    // implicit conversion as if by assignment
+    fn convert(node: Node, to: &Ctype, ctx: &str) -> Result<Node, String> {
+        let to = to.unqual();
+        if let Ctype::Struct(_) = to {
+            return Err(format!("{} of a struct is not supported", ctx));
+        }
+        if node.ty().unqual() == to {
+            return Ok(node);
+        }
+        if node.ty().is_arithmetic() && to.is_arithmetic() {
+            return Ok(Sema::cast(node, to));
+        }
+        if *to == Ctype::Bool && node.ty().is_pointer() {
+            return Ok(Sema::cast(node, to));
+        }
+        if let (Some(from), Some(target)) = (node.ty().pointee(), to.pointee()) {
+            // void * converts to and from any object pointer type
+            let object = |t: &Ctype| !matches!(t.unqual(), Ctype::Func(..));
+            let void = |t: &Ctype| *t.unqual() == Ctype::Void;
+            let generic = (void(from) && object(target)) || (void(target) && object(from));
+            if from.unqual() != target.unqual() && !generic {
+                return Err(format!("incompatible pointer types in {}", ctx));
+            }
+            if !target.quals().contains(from.quals()) {
+                return Err(format!(
+                    "{} discards qualifiers from pointer target type",
+                    ctx
+                ));
+            }
+            return Ok(Sema::cast(node, to));
+        }
+        if to.is_pointer() && Sema::is_null(&node) {
+            return Ok(Sema::cast(node, to));
+        }
+        Err(format!("incompatible types in {}", ctx))
+    }
+}
+
+#[cfg(test)]
+fn sema(code: &str) -> Result<Vec<Node>, String> {
+    use lexer::Lexer;
+    use parser::Parser;
+    let tokens = Lexer::new(code).run().unwrap();
+    let nodes = Parser::new().run(tokens).unwrap();
+    Sema::new().run(nodes)
+}
+
+#[test]
+fn undefined_variable_test() {
+    assert_eq!(
+        sema("int main() { return x; }"),
+        Err("undefined variable 'x'".to_string())
+    );
+}
+
+#[test]
+fn undefined_function_test() {
+    assert_eq!(
+        sema("int main() { return f(1); }"),
+        Err("undefined function 'f'".to_string())
+    );
+}
+
+#[test]
+fn argument_count_test() {
+    assert_eq!(
+        sema("int f(int a, int b) { return a; } int main() { return f(1); }"),
+        Err("wrong number of arguments to 'f': expected 2, got 1".to_string())
+    );
+    assert!(sema("int f(int a); int main() { return f(1); }").is_ok());
+}
+
+#[test]
+fn redefinition_test() {
+    assert_eq!(
+        sema("int main() { int a; int a; return 0; }"),
+        Err("redefinition of 'a'".to_string())
+    );
+    assert_eq!(
+        sema("int f() { return 0; } int f() { return 1; }"),
+        Err("redefinition of 'f'".to_string())
+    );
+}
+
+#[test]
+fn arith_conv_test() {
+    assert_eq!(Sema::arith_conv(&Ctype::Char, &Ctype::Short), Ctype::Int);
+    assert_eq!(Sema::arith_conv(&Ctype::UChar, &Ctype::Char), Ctype::Int);
+    assert_eq!(Sema::arith_conv(&Ctype::Int, &Ctype::UInt), Ctype::UInt);
+    assert_eq!(Sema::arith_conv(&Ctype::UInt, &Ctype::Long), Ctype::Long);
+    assert_eq!(
+        Sema::arith_conv(&Ctype::ULong, &Ctype::LongLong),
+        Ctype::ULongLong
+    );
+    assert_eq!(
+        Sema::arith_conv(&Ctype::Long, &Ctype::LongLong),
+        Ctype::LongLong
+    );
+    assert_eq!(Sema::arith_conv(&Ctype::UShort, &Ctype::Int), Ctype::Int);
+}
+
+#[test]
+fn literal_type_test() {
+    assert_eq!(Sema::literal_type(2147483647), Ctype::Int);
+    assert_eq!(Sema::literal_type(2147483648), Ctype::Long);
+    assert_eq!(Sema::literal_type(9223372036854775808), Ctype::ULong);
+}
+
+#[test]
+fn const_test() {
+    assert_eq!(
+        sema("int main() { const int a = 1; a = 2; return a; }"),
+        Err("assignment of read-only location".to_string())
+    );
+    assert_eq!(
+        sema("int main() { int a = 1; const int *p = &a; *p = 2; return a; }"),
+        Err("assignment of read-only location".to_string())
+    );
+    assert_eq!(
+        sema("int main() { int a = 1; int * const p = &a; p = &a; return a; }"),
+        Err("assignment of read-only location".to_string())
+    );
+    assert!(sema("int main() { int a = 1; int * const p = &a; *p = 2; return a; }").is_ok());
+}
+
+#[test]
+fn discard_qualifiers_test() {
+    assert_eq!(
+        sema("int main() { const int a = 1; int *p = &a; return *p; }"),
+        Err("initialization discards qualifiers from pointer target type".to_string())
+    );
+    assert_eq!(
+        sema("int f(int *p) { return *p; } int main() { volatile int a; return f(&a); }"),
+        Err("argument discards qualifiers from pointer target type".to_string())
+    );
+    assert!(sema("int main() { int a = 1; const volatile int *p = &a; return *p; }").is_ok());
+    assert!(sema("int main() { const int a = 1; int *p = (int *)&a; return *p; }").is_ok());
+    assert!(sema("int f(int * restrict p) { return *p; } int main() { return 0; }").is_ok());
+    assert_eq!(
+        sema("int main() { long a; int *p = &a; return 0; }"),
+        Err("incompatible pointer types in initialization".to_string())
+    );
+}
+
+#[test]
+fn function_pointer_test() {
+    let prelude = "int add(int a, int b) { return a + b; } ";
+    assert!(sema(&format!(
+        "{}int main() {{ int (*f)(int, int) = add; return f(1, 2) + (*f)(3, 4); }}",
+        prelude
+    ))
+    .is_ok());
+    assert!(sema(&format!(
+        "{}int main() {{ int (*f)(int, int) = &add; return (**f)(1, 2); }}",
+        prelude
+    ))
+    .is_ok());
+    assert_eq!(
+        sema(&format!(
+            "{}int main() {{ int (*f)(int) = add; return 0; }}",
+            prelude
+        )),
+        Err("incompatible pointer types in initialization".to_string())
+    );
+    assert_eq!(
+        sema(&format!(
+            "{}int main() {{ int (*f)(int, int) = add; return f(1); }}",
+            prelude
+        )),
+        Err("wrong number of arguments to 'function pointer': expected 2, got 1".to_string())
+    );
+    assert_eq!(
+        sema("int main() { int a = 1; return a(2); }"),
+        Err("called object is not a function".to_string())
+    );
+    assert_eq!(
+        sema(&format!("{}int main() {{ add = add; return 0; }}", prelude)),
+        Err("lvalue required as left operand of assignment".to_string())
+    );
+}
+
+#[test]
+fn array_test() {
+    assert!(sema("int main() { int a[3]; int *p = a; a[1] = 2; return *(p + 1); }").is_ok());
+    assert_eq!(
+        sema("int main() { int a[3]; int b[3]; a = b; return 0; }"),
+        Err("assignment to expression with array type".to_string())
+    );
+    assert_eq!(
+        sema("int main() { int a[]; return 0; }"),
+        Err("array size missing in 'a'".to_string())
+    );
+    assert_eq!(
+        sema("int f()[3];"),
+        Err("function returning an array".to_string())
+    );
+    assert_eq!(
+        sema("int f(int) { return 0; }"),
+        Err("parameter name omitted in 'f'".to_string())
+    );
+}
+
+#[test]
+fn sizeof_test() {
+    let nodes =
+        sema("int main() { int a[3][4]; return sizeof a + sizeof(a[0]) + sizeof(int (*)[4]); }")
+            .unwrap();
+    assert!(nodes.len() == 1);
+    assert_eq!(
+        sema("int f(int a) { return sizeof f; }"),
+        Err("invalid application of 'sizeof' to a function type".to_string())
+    );
+}
+
+#[test]
+fn static_test() {
+    let nodes = sema("int f() { static int n = 2 * 3; n = n + 1; return n; }").unwrap();
+    assert_eq!(nodes.len(), 2);
+    match &nodes[1].base {
+        NodeBase::VarDef(Ctype::Int, label, Some(init), storage) => {
+            assert_eq!(label, "n.0");
+            assert_eq!(init.base, NodeBase::Number(6));
+            assert!(storage.is_static);
+        }
+        _ => panic!(),
+    }
+    assert_eq!(
+        sema("int f() { int a = 1; static int n = a; return n; }"),
+        Err("initializer element is not constant".to_string())
+    );
+    assert_eq!(
+        sema("int f(); static int f() { return 0; }"),
+        Err("static declaration of 'f' follows non-static declaration".to_string())
+    );
+    match &sema("static int f(); int f() { return 0; }").unwrap()[1].base {
+        NodeBase::DefFun(_, _, _, _, _, storage) => assert!(storage.is_static),
+        _ => panic!(),
+    }
+    assert_eq!(
+        sema("int g; int g() { return 0; }"),
+        Err("'g' redeclared as different kind of symbol".to_string())
+    );
+}
+
+#[test]
+fn const_init_test() {
+    let init = |code: &str| match &sema(code).unwrap()[0].base {
+        NodeBase::VarDef(_, _, Some(init), _) => match init.base {
+            NodeBase::Number(n) => n,
+            _ => panic!(),
+        },
+        _ => panic!(),
+    };
+    assert_eq!(init("char c = 300;"), 44);
+    assert_eq!(init("int i = -7 / 2;"), -3i64 as usize);
+    assert_eq!(init("unsigned u = -1;"), 4294967295);
+    assert_eq!(init("_Bool b = 256;"), 1);
+    assert_eq!(init("int m = -7 % 2;"), -1i64 as usize);
+    assert_eq!(init("unsigned s = -1 >> 28;"), 4294967295);
+    assert_eq!(init("unsigned s = 4294967295 >> 28;"), 15);
+    assert_eq!(init("int t = -16 >> 2 | 1 << 4 ^ 3;"), -1i64 as usize);
+}
+
+#[test]
+fn compound_assign_test() {
+    assert!(sema("int f(int *p, char c) { p += 2; c <<= 1; return *p %= c; }").is_ok());
+    assert_eq!(
+        sema("int f(int *p) { return p % 2; }"),
+        Err("invalid operands to binary Mod".to_string())
+    );
+    assert_eq!(
+        sema("int f(int *p) { p *= 2; return 0; }"),
+        Err("invalid operands to binary Mul".to_string())
+    );
+    assert_eq!(
+        sema("int f(const int a) { a += 1; return a; }"),
+        Err("assignment of read-only location".to_string())
+    );
+    // the result of a shift has the promoted type of its left operand
+    let nodes = sema("long f(char c, long n) { return c << n; }").unwrap();
+    match &nodes[0].base {
+        NodeBase::DefFun(_, _, _, body, _, _) => match &body.base {
+            NodeBase::Statements(v) => match &v[0].base {
+                NodeBase::Return(Some(e)) => match &e.base {
+                    NodeBase::Cast(e) => assert_eq!(e.ty(), &Ctype::Int),
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            },
+            _ => panic!(),
+        },
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn struct_test() {
+    let s = "struct s { int a; unsigned b : 3; };";
+    assert!(sema(&format!(
+        "{} int f(struct s *p) {{ return p->a + p->b; }}",
+        s
+    ))
+    .is_ok());
+    assert_eq!(
+        sema(&format!("{} int f(struct s *p) {{ return p->c; }}", s)),
+        Err("'struct s' has no member named 'c'".to_string())
+    );
+    assert_eq!(
+        sema(&format!(
+            "{} int f(struct s *p) {{ return &p->b != 0; }}",
+            s
+        )),
+        Err("cannot take address of bit-field".to_string())
+    );
+    assert_eq!(
+        sema("struct t { int x : 33; }; struct t v;"),
+        Err("width of 'x' exceeds its type".to_string())
+    );
+    assert_eq!(
+        sema("struct t { int *x : 3; }; struct t v;"),
+        Err("bit-field 'x' has invalid type".to_string())
+    );
+    assert_eq!(
+        sema("struct t { int x; char x; }; struct t v;"),
+        Err("duplicate member 'x'".to_string())
+    );
+    assert_eq!(
+        sema("struct t v;"),
+        Err("storage size of 'v' isn't known".to_string())
+    );
+    assert_eq!(
+        sema("int f(struct t *p) { return p->x; }"),
+        Err("invalid use of undefined type 'struct t'".to_string())
+    );
+    assert_eq!(
+        sema("int f(int a) { return a.x; }"),
+        Err("request for member 'x' in something not a structure".to_string())
+    );
+}
+
+#[test]
+fn shadowing_test() {
+    let nodes = sema("int main() { int a = 1; { int a = 2; a; } return a; }").unwrap();
+    match &nodes[0].base {
+        NodeBase::DefFun(_, _, _, body, vars, _) => {
+            assert_eq!(vars.len(), 2);
+            let stmts = match &body.base {
+                NodeBase::Statements(v) => v,
+                _ => panic!(),
+            };
+            let inner = match &stmts[1].base {
+                NodeBase::Statements(v) => v,
+                _ => panic!(),
+            };
+            assert_eq!(inner[1].base, NodeBase::Lvar(1));
+            match &stmts[2].base {
+                NodeBase::Return(Some(e)) => assert_eq!(e.base, NodeBase::Lvar(0)),
+                _ => panic!(),
+            }
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn return_test() {
+    use lexer::Lexer;
+    use parser::Parser;
+    assert!(sema("int f(int a) { if (a) return 1; else return 2; }").is_ok());
+    assert!(sema("int f(int a) { while (1) if (a) return a; }").is_ok());
+    assert!(sema("int f(int *p) { for (;;) if (p) return *p; }").is_ok());
+    // falling off the end is only diagnosed
+    for code in [
+        "int f(int a) { if (a) return 1; }",
+        "int f(int a) { while (a) { return 2; } }",
+        "void exit(int); int f(int a) { if (a) return 1; exit(1); }",
+    ] {
+        let tokens = Lexer::new(code).run().unwrap();
+        let nodes = Parser::new().run(tokens).unwrap();
+        let mut sema = Sema::new();
+        assert!(sema.run(nodes).is_ok());
+        assert_eq!(
+            sema.warnings,
+            ["control may reach end of non-void function 'f'"]
+        );
+    }
+    assert_eq!(
+        sema("struct s { int a; }; int f(struct s *p) { if (*p) return 1; return 0; }"),
+        Err("used struct type value where scalar is required".to_string())
+    );
+    // the loop variable is scoped to the loop
+    assert_eq!(
+        sema("int f() { for (int i = 0; i < 3; i += 1) ; return i; }"),
+        Err("undefined variable 'i'".to_string())
+    );
+    let nodes = sema("int main() { int a = 1; }").unwrap();
+    match &nodes[0].base {
+        NodeBase::DefFun(_, _, _, body, _, _) => match &body.base {
+            NodeBase::Statements(v) => {
+                assert_eq!(v.len(), 2);
+                match &v[1].base {
+                    NodeBase::Return(Some(e)) => assert_eq!(e.base, NodeBase::Number(0)),
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        },
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn void_test() {
+    assert!(
+        sema("void f(int *p) { if (*p) return; *p = 1; } int main(void) { return 0; }").is_ok()
+    );
+    assert!(sema("void f(void); int main() { f(); (void)1; return 0; }").is_ok());
+    assert!(sema("int main() { int a; void *p = &a; int *q = p; return *q; }").is_ok());
+    assert_eq!(
+        sema("void f() {} int main() { return f(); }"),
+        Err("void value not ignored as it ought to be".to_string())
+    );
+    assert_eq!(
+        sema("void f() {} int main() { int a = f() + 1; return a; }"),
+        Err("void value not ignored as it ought to be".to_string())
+    );
+    assert_eq!(
+        sema("void f() { return 1; }"),
+        Err("'return' with a value, in function returning void".to_string())
+    );
+    assert_eq!(
+        sema("int f() { return; }"),
+        Err("'return' with no value, in function returning non-void".to_string())
+    );
+    assert_eq!(
+        sema("int f(void a) { return 0; }"),
+        Err("'void' must be the only parameter".to_string())
+    );
+    assert_eq!(
+        sema("int main() { void v; return 0; }"),
+        Err("variable or field 'v' declared void".to_string())
+    );
+    assert_eq!(
+        sema("int f(void *p) { return *(p + 1); }"),
+        Err("arithmetic on a pointer to an incomplete type".to_string())
+    );
+    assert_eq!(
+        sema("int f(int (*p)(void)) { void *q = p; return 0; }"),
+        Err("incompatible pointer types in initialization".to_string())
+    );
+}
+
+// the value of `expr` once folded, or `None` if it is left to run time
+#[cfg(test)]
+fn folded(t: &str, expr: &str) -> Option<i64> {
+    let code = format!("{} f() {{ return {}; }}", t, expr);
+    let nodes = sema(&code).unwrap();
+    let body = match &nodes[0].base {
+        NodeBase::DefFun(_, _, _, body, _, _) => body,
+        _ => panic!("not a function"),
+    };
+    let ret = match &body.base {
+        NodeBase::Statements(v) => &v[0],
+        _ => panic!("not a block"),
+    };
+    match &ret.base {
+        NodeBase::Return(Some(e)) => match e.base {
+            NodeBase::Number(n) => Some(n as i64),
+            _ => None,
+        },
+        _ => panic!("not a return"),
+    }
+}
+
+#[test]
+fn fold_test() {
+    assert_eq!(folded("int", "2+9*3-6/3"), Some(27));
+    // division truncates toward zero
+    assert_eq!(folded("int", "-7 / 2 + -7 % 2"), Some(-4));
+    assert_eq!(folded("int", "7 / 2 + 7 % -2"), Some(4));
+    assert_eq!(folded("int", "(1 < 2) + (3 >= 4) + (5 == 5)"), Some(2));
+    // unsigned arithmetic wraps around at its width
+    assert_eq!(folded("unsigned", "(unsigned)0 - 1"), Some(4294967295));
+    assert_eq!(
+        folded("unsigned", "(unsigned)4294967295 * 2"),
+        Some(4294967294)
+    );
+    assert_eq!(folded("unsigned long", "(unsigned long)0 - 1"), Some(-1));
+    assert_eq!(folded("unsigned", "(unsigned)1 << 31"), Some(2147483648));
+    // int operands are converted first
+    assert_eq!(folded("long", "2147483647 + (long)1"), Some(2147483648));
+    // signed overflow and undefined operations are not folded
+    assert_eq!(folded("int", "2147483647 + 1"), None);
+    assert_eq!(folded("long", "9223372036854775807 * 2"), None);
+    assert_eq!(folded("int", "1 / 0"), None);
+    assert_eq!(folded("int", "1 << 32"), None);
+    assert_eq!(folded("int", "1 << 31"), None);
+    assert_eq!(folded("int", "(0 - 2147483647 - 1) % (0 - 1)"), None);
+    assert_eq!(folded("int", "-(0 - 2147483647 - 1) + 0"), None);
+    // right shifts of negative values are arithmetic
+    assert_eq!(folded("int", "-8 >> 1"), Some(-4));
+}
@@ -0,0 +1,434 @@
+// Static single assignment form.
+//
+// `to_ssa` first turns the locals that are only ever loaded and stored
+// into registers. It then places a phi for each register at the
+// iterated dominance frontier of its definitions wherever the register
+// is live, and renames every definition to a fresh register while
+// walking the dominator tree, so each register is written exactly once.
+//
+// `from_ssa` lowers the phis of a block to a parallel copy at the end of
+// each predecessor. Edges from blocks with a conditional terminator are
+// split first, so a copy never runs on the wrong path or clobbers the
+// branch operand.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use cfg::{Block, DomTree, Function, Term};
+use gen_ir::{Ir, Op, Operand};
+use liveness::Liveness;
+
+pub fn to_ssa(f: &mut Function) {
+    for b in f.blocks.iter_mut() {
+        b.ins.retain(|ir| ir.op != Op::Kill);
+    }
+    // nothing can define the registers an unreachable block reads
+    f.remove_unreachable();
+
+    let mut next = f.next_reg();
+    promote(f, &mut next);
+    let dom = DomTree::new(f);
+    insert_phis(f, &dom);
+    let orig = f
+        .blocks
+        .iter()
+        .map(|b| {
+            b.ins
+                .iter()
+                .take_while(|ir| is_phi(ir))
+                .map(|ir| ir.dst.unwrap())
+                .collect()
+        })
+        .collect();
+    let mut stacks = HashMap::new();
+    if !f.blocks.is_empty() {
+        rename(f, &dom, 0, &orig, &mut stacks, &mut next);
+    }
+}
+
+pub fn from_ssa(f: &mut Function) {
+    if !f.blocks.iter().any(|b| b.ins.iter().any(is_phi)) {
+        return;
+    }
+    for s in 0..f.blocks.len() {
+        if !f.blocks[s].ins.first().is_some_and(is_phi) {
+            continue;
+        }
+        for p in f.blocks[s].preds.clone() {
+            if let Term::Jmp(_) = f.blocks[p].term {
+                continue;
+            }
+            let m = f.blocks.len();
+            f.blocks.push(Block::new(Term::Jmp(s)));
+            f.blocks[p].term.retarget(s, m);
+            for ir in f.blocks[s].ins.iter_mut().take_while(|ir| is_phi(ir)) {
+                if let Op::Phi(preds) = &mut ir.op {
+                    for q in preds.iter_mut().filter(|q| **q == p) {
+                        *q = m;
+                    }
+                }
+            }
+        }
+    }
+    f.compute_edges();
+
+    let mut copies: BTreeMap<usize, Vec<(isize, Operand)>> = BTreeMap::new();
+    for b in f.blocks.iter_mut() {
+        let n = b.ins.iter().take_while(|ir| is_phi(ir)).count();
+        for ir in b.ins.drain(..n) {
+            if let Op::Phi(preds) = ir.op {
+                for (p, a) in preds.into_iter().zip(ir.args) {
+                    copies.entry(p).or_default().push((ir.dst.unwrap(), a));
+                }
+            }
+        }
+    }
+    let mut next = f.next_reg();
+    for (p, copies) in copies {
+        let seq = sequentialize(copies, &mut next);
+        f.blocks[p].ins.extend(seq);
+    }
+}
+
+fn is_phi(ir: &Ir) -> bool {
+    matches!(ir.op, Op::Phi(_))
+}
+
+// Replaces the frame slots of scalar locals by registers. A slot can be
+// promoted if it only ever appears as the address of loads and stores of
+// one width, none of them volatile. The prologue stores a parameter to
+// its slot, so a promoted parameter is defined by a single load of it at
+// the start of the entry block; its loads must all extend alike, and the
+// entry block must not be a loop header.
+fn promote(f: &mut Function, next: &mut isize) {
+    let mut sizes: HashMap<isize, Option<usize>> = HashMap::new();
+    let mut loads: HashMap<isize, Op> = HashMap::new();
+    let entry = f.blocks.first().is_some_and(|b| b.preds.is_empty());
+    for p in f.params.iter() {
+        sizes.insert(p.offset, if entry { Some(p.size) } else { None });
+    }
+    for b in f.blocks.iter() {
+        for ir in b.ins.iter() {
+            for (i, a) in ir.args.iter().enumerate() {
+                if let Operand::Slot(n) = a {
+                    let direct =
+                        i == 0 && !ir.volatile && matches!(ir.op, Op::Load | Op::LoadU | Op::Store);
+                    let size = sizes.entry(*n).or_insert(Some(ir.size));
+                    if !direct || *size != Some(ir.size) {
+                        *size = None;
+                    }
+                    if direct && ir.op != Op::Store {
+                        let op = loads.entry(*n).or_insert_with(|| ir.op.clone());
+                        if *op != ir.op {
+                            *size = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let mut regs = HashMap::new();
+    let mut slots: Vec<isize> = sizes.keys().cloned().collect();
+    slots.sort();
+    for n in slots {
+        if sizes[&n].is_some() {
+            regs.insert(n, *next);
+            *next += 1;
+        }
+    }
+
+    // Values are kept extended according to their type, so a load gives
+    // back exactly the value that was stored.
+    for b in f.blocks.iter_mut() {
+        for ir in b.ins.iter_mut() {
+            let r = match ir.args.first() {
+                Some(Operand::Slot(n)) => match regs.get(n) {
+                    Some(r) => *r,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            *ir = match ir.op {
+                Op::Store => Ir::new(Op::Mov, Some(r), vec![ir.args[1].clone()], 8),
+                _ => Ir::new(Op::Mov, ir.dst, vec![Operand::Reg(r)], 8),
+            };
+        }
+    }
+    let defs: Vec<Ir> = f
+        .params
+        .iter()
+        .filter_map(|p| {
+            let r = regs.get(&p.offset)?;
+            let op = loads.get(&p.offset)?.clone();
+            let slot = vec![Operand::Slot(p.offset)];
+            Some(Ir::new(op, Some(*r), slot, p.size))
+        })
+        .collect();
+    if let Some(b) = f.blocks.first_mut() {
+        b.ins.splice(0..0, defs);
+    }
+}
+
+// pruned SSA: a phi is only placed where its register is live
+fn insert_phis(f: &mut Function, dom: &DomTree) {
+    let live = Liveness::new(f);
+    let df = dom.frontiers(f);
+    let mut defs: BTreeMap<isize, BTreeSet<usize>> = BTreeMap::new();
+    for (i, b) in f.blocks.iter().enumerate() {
+        for ir in b.ins.iter() {
+            if let Some(d) = ir.dst {
+                defs.entry(d).or_default().insert(i);
+            }
+        }
+    }
+    for (r, blocks) in defs {
+        let mut work: Vec<usize> = blocks.iter().cloned().collect();
+        let mut placed = BTreeSet::new();
+        while let Some(b) = work.pop() {
+            for d in df[b].iter() {
+                if placed.contains(d) || !live.live_in[*d].contains(&r) {
+                    continue;
+                }
+                placed.insert(*d);
+                let preds = f.blocks[*d].preds.clone();
+                let args = vec![Operand::Reg(r); preds.len()];
+                let phi = Ir::new(Op::Phi(preds), Some(r), args, 8);
+                f.blocks[*d].ins.insert(0, phi);
+                if !blocks.contains(d) {
+                    work.push(*d);
+                }
+            }
+        }
+    }
+}
+
+// Renames the registers of block `b` and the blocks it dominates.
+// `stacks` holds the current names of each original register and `orig`
+// the original registers of the phis of each block. A register read
+// before any definition reaches it is undefined and reads as 0.
+fn rename(
+    f: &mut Function,
+    dom: &DomTree,
+    b: usize,
+    orig: &Vec<Vec<isize>>,
+    stacks: &mut HashMap<isize, Vec<isize>>,
+    next: &mut isize,
+) {
+    fn name(stacks: &HashMap<isize, Vec<isize>>, r: isize) -> Operand {
+        match stacks.get(&r).and_then(|s| s.last()) {
+            Some(n) => Operand::Reg(*n),
+            None => Operand::Imm(0),
+        }
+    }
+
+    let mut defined = vec![];
+    let block = &mut f.blocks[b];
+    for ir in block.ins.iter_mut() {
+        if !is_phi(ir) {
+            for a in ir.args.iter_mut() {
+                if let Operand::Reg(r) = a {
+                    *a = name(stacks, *r);
+                }
+            }
+        }
+        if let Some(d) = ir.dst {
+            stacks.entry(d).or_default().push(*next);
+            defined.push(d);
+            ir.dst = Some(*next);
+            *next += 1;
+        }
+    }
+    if let Some(a) = block.term.arg_mut() {
+        if let Operand::Reg(r) = a {
+            *a = name(stacks, *r);
+        }
+    }
+
+    for s in block.succs.clone() {
+        for (ir, r) in f.blocks[s].ins.iter_mut().zip(orig[s].iter()) {
+            if let Op::Phi(preds) = &ir.op {
+                for (i, p) in preds.iter().enumerate() {
+                    if *p == b {
+                        ir.args[i] = name(stacks, *r);
+                    }
+                }
+            }
+        }
+    }
+    for c in dom.children[b].iter() {
+        rename(f, dom, *c, orig, stacks, next);
+    }
+    for d in defined {
+        stacks.get_mut(&d).unwrap().pop();
+    }
+}
+
+// Orders a parallel copy into moves. A move is emitted once no pending
+// copy still reads its destination; when only cycles are left, one
+// destination is first saved in a fresh register.
+fn sequentialize(mut copies: Vec<(isize, Operand)>, next: &mut isize) -> Vec<Ir> {
+    copies.retain(|(d, a)| *a != Operand::Reg(*d));
+    let mut v = vec![];
+    while !copies.is_empty() {
+        let free = copies
+            .iter()
+            .position(|(d, _)| copies.iter().all(|(_, a)| *a != Operand::Reg(*d)));
+        match free {
+            Some(i) => {
+                let (d, a) = copies.remove(i);
+                v.push(Ir::new(Op::Mov, Some(d), vec![a], 8));
+            }
+            None => {
+                let d = copies[0].0;
+                let t = *next;
+                *next += 1;
+                v.push(Ir::new(Op::Mov, Some(t), vec![Operand::Reg(d)], 8));
+                for c in copies.iter_mut() {
+                    if c.1 == Operand::Reg(d) {
+                        c.1 = Operand::Reg(t);
+                    }
+                }
+            }
+        }
+    }
+    v
+}
+
+#[cfg(test)]
+use cfg::function;
+
+#[test]
+fn to_ssa_test() {
+    use ir_text::print;
+    // int i = 0; while (i < 10) i = i + 1; return i;
+    let mut f = function(
+        "def f, 16
+        B0:
+          r0 = mov 0
+          store.4 s4, r0
+          kill r0
+          jmp B1
+        B1:
+          r1 = load.4 s4
+          r1 = cmp.lt.4 r1, 10
+          br r1, B2, B3
+        B2:
+          r2 = load.4 s4
+          r2 = add.4 r2, 1
+          store.4 s4, r2
+          kill r2
+          jmp B1
+        B3:
+          r3 = load.4 s4
+          ret.4 r3",
+    );
+    to_ssa(&mut f);
+    assert_eq!(
+        print(&[f]),
+        "def f, 16
+B0:
+  r5 = mov 0
+  r6 = mov r5
+  jmp B1
+B1:
+  r7 = phi B0: r6, B2: r13
+  r8 = mov r7
+  r9 = cmp.lt.4 r8, 10
+  br r9, B2, B3
+B2:
+  r11 = mov r7
+  r12 = add.4 r11, 1
+  r13 = mov r12
+  jmp B1
+B3:
+  r10 = mov r7
+  ret.4 r10
+"
+    );
+}
+
+#[test]
+fn param_test() {
+    use ir_text::print;
+    // while (n) n = n - 1; return n;
+    let mut f = function(
+        "def f, 16
+          param.4 4
+        B0:
+          jmp B1
+        B1:
+          r0 = load.4 s4
+          br r0, B2, B3
+        B2:
+          r1 = load.4 s4
+          r1 = sub.4 r1, 1
+          store.4 s4, r1
+          kill r1
+          jmp B1
+        B3:
+          r2 = load.4 s4
+          ret.4 r2",
+    );
+    to_ssa(&mut f);
+    // the parameter is loaded once and then lives in registers
+    assert_eq!(
+        print(&[f]),
+        "def f, 16
+  param.4 4
+B0:
+  r4 = load.4 s4
+  jmp B1
+B1:
+  r5 = phi B0: r4, B2: r10
+  r6 = mov r5
+  br r6, B2, B3
+B2:
+  r8 = mov r5
+  r9 = sub.4 r8, 1
+  r10 = mov r9
+  jmp B1
+B3:
+  r7 = mov r5
+  ret.4 r7
+"
+    );
+}
+
+#[test]
+fn from_ssa_test() {
+    use ir_text::print;
+    // a loop swapping two values, whose back edge is critical
+    let mut f = function(
+        "def f, 0
+        B0:
+          r0 = mov 1
+          r1 = mov 2
+          jmp B1
+        B1:
+          r2 = phi B0: r0, B1: r3
+          r3 = phi B0: r1, B1: r2
+          br r2, B1, B2
+        B2:
+          ret r3",
+    );
+    from_ssa(&mut f);
+    assert_eq!(
+        print(&[f]),
+        "def f, 0
+B0:
+  r0 = mov 1
+  r1 = mov 2
+  r2 = mov r0
+  r3 = mov r1
+  jmp B1
+B1:
+  br r2, B3, B2
+B2:
+  ret r3
+B3:
+  r4 = mov r2
+  r2 = mov r3
+  r3 = mov r4
+  jmp B1
+"
+    );
+}
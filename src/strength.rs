@@ -0,0 +1,317 @@
+// Strength reduction of division by constants.
+//
+// A division or modulo by a constant becomes a few shifts, adds and a
+// multiplication keeping the high half of the product, after Granlund
+// and Montgomery and Hacker's Delight. Unsigned division by a power of
+// two is a shift and the modulo a mask; a signed one first adds the
+// divisor minus one to negative dividends so the quotient rounds toward
+// zero. Other divisors multiply by a fixed-point reciprocal, the magic
+// number, and correct the result. A modulo is the dividend minus the
+// quotient times the divisor, so value numbering shares the quotient
+// with a division of the same operands.
+//
+// The multiplication by a constant left in a modulo, like any other, is
+// turned into `lea` and shifts by the backend.
+
+use cfg::Function;
+use gen_ir::{Cond, Ir, Op, Operand};
+
+pub fn reduce(f: &mut Function) {
+    let mut next = f.next_reg();
+    for b in f.blocks.iter_mut() {
+        let mut v = vec![];
+        for ir in std::mem::take(&mut b.ins) {
+            let reducible = matches!(ir.op, Op::Div | Op::Udiv | Op::Mod | Op::Umod)
+                && (ir.size == 4 || ir.size == 8)
+                && !ir.volatile;
+            let (d, n) = match (ir.dst, &ir.args[..]) {
+                (Some(d), [_, Operand::Imm(n)]) if reducible => (d, *n),
+                _ => {
+                    v.push(ir);
+                    continue;
+                }
+            };
+            let mut seq = Seq {
+                ins: vec![],
+                next: &mut next,
+                size: ir.size,
+            };
+            let x = ir.args[0].clone();
+            let res = match ir.op {
+                Op::Udiv => seq.udiv(x, n),
+                Op::Umod => seq.umod(x, n),
+                Op::Div => seq.sdiv(x, n),
+                _ => seq.smod(x, n),
+            };
+            if res.is_none() {
+                v.push(ir);
+                continue;
+            }
+            // the last instruction computes the result
+            let mut ins = seq.ins;
+            ins.last_mut().unwrap().dst = Some(d);
+            v.extend(ins);
+        }
+        b.ins = v;
+    }
+}
+
+// instructions computing a value at width `size` in fresh registers
+struct Seq<'a> {
+    ins: Vec<Ir>,
+    next: &'a mut isize,
+    size: usize,
+}
+
+impl<'a> Seq<'a> {
+    fn emit(&mut self, op: Op, args: Vec<Operand>) -> Operand {
+        let r = *self.next;
+        *self.next += 1;
+        self.ins.push(Ir::new(op, Some(r), args, self.size));
+        Operand::Reg(r)
+    }
+
+    fn bits(&self) -> u32 {
+        self.size as u32 * 8
+    }
+
+    // the divisor as an unsigned number of the width
+    fn unsigned(&self, n: i64) -> u64 {
+        (n as u64) & (u64::MAX >> (64 - self.bits()))
+    }
+
+    // and as a signed one
+    fn signed(&self, n: i64) -> i64 {
+        let shift = 64 - self.bits();
+        n << shift >> shift
+    }
+
+    fn udiv(&mut self, x: Operand, n: i64) -> Option<Operand> {
+        let d = self.unsigned(n);
+        let bits = self.bits();
+        if d == 0 {
+            return None;
+        }
+        if d == 1 {
+            return Some(self.emit(Op::Mov, vec![x]));
+        }
+        if d.is_power_of_two() {
+            let k = Operand::Imm(d.trailing_zeros() as i64);
+            return Some(self.emit(Op::Shr, vec![x, k]));
+        }
+        // a divisor with the top bit set goes at most once
+        if d >> (bits - 1) == 1 {
+            return Some(self.emit(Op::Cmp(Cond::Uge), vec![x, Operand::Imm(d as i64)]));
+        }
+        // With l = ceil(log2 d) and m = 2^bits * (2^l - d) / d + 1, the
+        // quotient is (t + (x - t) / 2) >> (l - 1) for t = (m * x) >> bits.
+        let l = 64 - (d - 1).leading_zeros();
+        let m = ((1u128 << bits) * ((1u128 << l) - d as u128) / d as u128 + 1) as u64;
+        let t = self.emit(Op::Umulh, vec![x.clone(), Operand::Imm(m as i64)]);
+        let s = self.emit(Op::Sub, vec![x, t.clone()]);
+        let s = self.emit(Op::Shr, vec![s, Operand::Imm(1)]);
+        let s = self.emit(Op::Add, vec![t, s]);
+        Some(self.emit(Op::Shr, vec![s, Operand::Imm(l as i64 - 1)]))
+    }
+
+    fn umod(&mut self, x: Operand, n: i64) -> Option<Operand> {
+        let d = self.unsigned(n);
+        if d.is_power_of_two() {
+            let mask = Operand::Imm((d - 1) as i64);
+            return Some(self.emit(Op::And, vec![x, mask]));
+        }
+        let q = self.udiv(x.clone(), n)?;
+        self.remainder(x, q, n)
+    }
+
+    fn sdiv(&mut self, x: Operand, n: i64) -> Option<Operand> {
+        let d = self.signed(n);
+        let bits = self.bits();
+        match d {
+            0 => return None,
+            1 => return Some(self.emit(Op::Mov, vec![x])),
+            -1 => return Some(self.emit(Op::Neg, vec![x])),
+            _ => {}
+        }
+        let q = if self.unsigned(d.unsigned_abs() as i64).is_power_of_two() {
+            // x + (2^k - 1 if x is negative), shifted right by k
+            let k = d.unsigned_abs().trailing_zeros() as i64;
+            let t = self.emit(Op::Sar, vec![x.clone(), Operand::Imm(bits as i64 - 1)]);
+            let t = self.emit(Op::Shr, vec![t, Operand::Imm(bits as i64 - k)]);
+            let t = self.emit(Op::Add, vec![x, t]);
+            let q = self.emit(Op::Sar, vec![t, Operand::Imm(k)]);
+            if d > 0 {
+                return Some(q);
+            }
+            q
+        } else {
+            let (m, s) = signed_magic(d, bits);
+            let mut q = self.emit(Op::Mulh, vec![x.clone(), Operand::Imm(m)]);
+            if d > 0 && m < 0 {
+                q = self.emit(Op::Add, vec![q, x]);
+            } else if d < 0 && m > 0 {
+                q = self.emit(Op::Sub, vec![q, x]);
+            }
+            if s > 0 {
+                q = self.emit(Op::Sar, vec![q, Operand::Imm(s as i64)]);
+            }
+            // plus one if negative, to round toward zero
+            let t = self.emit(Op::Shr, vec![q.clone(), Operand::Imm(bits as i64 - 1)]);
+            return Some(self.emit(Op::Add, vec![q, t]));
+        };
+        Some(self.emit(Op::Neg, vec![q]))
+    }
+
+    fn smod(&mut self, x: Operand, n: i64) -> Option<Operand> {
+        if self.signed(n).unsigned_abs() == 1 {
+            return Some(self.emit(Op::Mov, vec![Operand::Imm(0)]));
+        }
+        let q = self.sdiv(x.clone(), n)?;
+        self.remainder(x, q, n)
+    }
+
+    // x - q * n
+    fn remainder(&mut self, x: Operand, q: Operand, n: i64) -> Option<Operand> {
+        let p = self.emit(Op::Mul, vec![q, Operand::Imm(n)]);
+        Some(self.emit(Op::Sub, vec![x, p]))
+    }
+}
+
+// The magic number and shift for signed division by `d` at a width of
+// `bits`, as computed in Hacker's Delight, figure 10-1. Arithmetic is
+// unsigned at that width.
+fn signed_magic(d: i64, bits: u32) -> (i64, u32) {
+    let mask = u128::MAX >> (128 - bits);
+    let two = 1u128 << (bits - 1);
+    let ad = d.unsigned_abs() as u128 & mask;
+    let t = two + ((d as u128 & mask) >> (bits - 1));
+    let anc = t - 1 - t % ad;
+    let mut p = bits - 1;
+    let (mut q1, mut r1) = (two / anc, two % anc);
+    let (mut q2, mut r2) = (two / ad, two % ad);
+    loop {
+        p += 1;
+        q1 = (2 * q1) & mask;
+        r1 = (2 * r1) & mask;
+        if r1 >= anc {
+            q1 = (q1 + 1) & mask;
+            r1 -= anc;
+        }
+        q2 = (2 * q2) & mask;
+        r2 = (2 * r2) & mask;
+        if r2 >= ad {
+            q2 = (q2 + 1) & mask;
+            r2 -= ad;
+        }
+        let delta = ad - r2;
+        if q1 > delta || (q1 == delta && r1 != 0) {
+            break;
+        }
+    }
+    // q2 + 1 as a signed number of the width, negated for a negative d
+    let shift = 128 - bits;
+    let m = (((q2 + 1) << shift) as i128 >> shift) as i64;
+    let m = if d < 0 { m.wrapping_neg() } else { m };
+    let m = m << (64 - bits) >> (64 - bits);
+    (m, p - bits)
+}
+
+#[test]
+fn reduce_test() {
+    use ir_text::{parse, print};
+    let mut funcs = parse(
+        "def f, 0
+        B0:
+          r1 = udiv.4 r0, 8
+          r2 = umod.4 r0, 8
+          r3 = div.4 r0, 7
+          r4 = mod r0, -4
+          r5 = div.4 r0, r1
+          ret r5",
+    )
+    .unwrap();
+    reduce(&mut funcs[0]);
+    assert_eq!(
+        print(&funcs),
+        "def f, 0
+B0:
+  r1 = shr.4 r0, 3
+  r2 = and.4 r0, 7
+  r8 = mulh.4 r0, -1840700269
+  r9 = add.4 r8, r0
+  r10 = sar.4 r9, 2
+  r11 = shr.4 r10, 31
+  r3 = add.4 r10, r11
+  r13 = sar r0, 63
+  r14 = shr r13, 62
+  r15 = add r0, r14
+  r16 = sar r15, 2
+  r17 = neg r16
+  r18 = mul r17, -4
+  r4 = sub r0, r18
+  r5 = div.4 r0, r1
+  ret r5
+"
+    );
+}
+
+// Checks the reduced division and modulo against the interpreter for
+// every pair of divisors and dividends near zero, near the powers of two
+// and at the ends of the range.
+#[test]
+fn reduce_exhaustive_test() {
+    use interp::evaluate;
+    for size in [4, 8] {
+        let bits = size * 8;
+        let mut values: Vec<i64> = (-40..=40).collect();
+        for k in 1..bits {
+            let p = 1i64 << k;
+            let n = p.wrapping_neg();
+            values.extend([p.wrapping_sub(1), p, p + 1, n.wrapping_sub(1), n, n + 1]);
+        }
+        values.extend([i64::MIN, i64::MAX, 7, 641, 1000003, 0x5555_5555_5555_5555]);
+        for op in [Op::Div, Op::Udiv, Op::Mod, Op::Umod] {
+            for n in values.iter() {
+                let mut next = 1;
+                let mut seq = Seq {
+                    ins: vec![],
+                    next: &mut next,
+                    size,
+                };
+                let x = Operand::Reg(0);
+                let res = match op {
+                    Op::Udiv => seq.udiv(x, *n),
+                    Op::Umod => seq.umod(x, *n),
+                    Op::Div => seq.sdiv(x, *n),
+                    _ => seq.smod(x, *n),
+                };
+                let res = match res {
+                    Some(Operand::Reg(r)) => r as usize,
+                    _ => continue,
+                };
+                let ins = seq.ins;
+                let mut regs = vec![0u64; next as usize];
+                for x in values.iter() {
+                    let expected = match evaluate(&op, &[*x as u64, *n as u64], size) {
+                        Ok(v) => v,
+                        Err(_) => continue,
+                    };
+                    regs[0] = *x as u64;
+                    for ir in ins.iter() {
+                        let args: Vec<u64> = ir
+                            .args
+                            .iter()
+                            .map(|a| match a {
+                                Operand::Reg(r) => regs[*r as usize],
+                                Operand::Imm(n) => *n as u64,
+                                _ => unreachable!(),
+                            })
+                            .collect();
+                        regs[ir.dst.unwrap() as usize] = evaluate(&ir.op, &args, size).unwrap();
+                    }
+                    assert_eq!(regs[res], expected, "{:?}.{} {}, {}", op, size, x, n);
+                }
+            }
+        }
+    }
+}
@@ -3,9 +3,11 @@
 try() {
   expected="$1"
   input="$2"
+  # the rest are extra gcc arguments, e.g. helpers to link with
+  shift 2
 
   ./target/debug/c "$input" > tmp.s
-  gcc -static -o tmp tmp.s
+  gcc -static -o tmp tmp.s "$@"
   ./tmp
   actual="$?"
 
@@ -20,5 +22,24 @@ try() {
 try 0 test/main.c
 try 27 test/addsubmuldiv.c
 try 12 test/func.c
+try 17 test/var.c
+try 14 test/inttypes.c
+try 21 test/qualifier.c
+try 32 test/funcptr.c
+try 86 test/declarator.c
+try 66 test/static.c
+try 15 test/bitfield.c
+try 17 test/bool.c
+try 133 test/manyargs.c
+try 147 test/abi.c -mno-red-zone test/abi_helper.c
+try 22 test/bitops.c
+try 0 test/return.c
+try 132 test/void.c
+try 92 test/loop.c
+try 15 test/sccp.c
+try 87 test/cse.c
+try 104 test/args.c
+try 29 test/strength.c
+try 73 test/inline.c
 
 echo ok
@@ -0,0 +1,24 @@
+long clobber(long x);
+int preserves(int (*f)());
+
+int busy() {
+  int a = 1;
+  int b = 2;
+  int c = 3;
+  int d = 4;
+  int e = 5;
+  int f = 6;
+  int g = 7;
+  return a + (b + (c + (d + (e + (f + g)))));
+}
+
+int twice(int x) {
+  return x * 2;
+}
+
+int main() {
+  int x = 3;
+  long live = x * 10 + clobber(5);
+  int nested = twice(1) + (twice(2) + twice(3));
+  return live + nested + preserves(busy) * 100;
+}
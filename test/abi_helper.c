@@ -0,0 +1,31 @@
+// built by gcc and linked with test/abi.c
+
+long clobber(long x) {
+  __asm__ volatile("mov $-1, %%r10\n\tmov $-1, %%r11" ::: "r10", "r11");
+  return x;
+}
+
+// calls f with rbx and r12-r15 set and returns 1 if f preserved them
+int preserves(int (*f)(void)) {
+  long ok;
+  __asm__ volatile(
+      "mov $11, %%rbx\n\t"
+      "mov $12, %%r12\n\t"
+      "mov $13, %%r13\n\t"
+      "mov $14, %%r14\n\t"
+      "mov $15, %%r15\n\t"
+      "call *%1\n\t"
+      "xor %0, %0\n\t"
+      "cmp $11, %%rbx\n\tjne 1f\n\t"
+      "cmp $12, %%r12\n\tjne 1f\n\t"
+      "cmp $13, %%r13\n\tjne 1f\n\t"
+      "cmp $14, %%r14\n\tjne 1f\n\t"
+      "cmp $15, %%r15\n\tjne 1f\n\t"
+      "mov $1, %0\n"
+      "1:"
+      : "=&a"(ok)
+      : "m"(f)
+      : "rbx", "r12", "r13", "r14", "r15", "rcx", "rdx", "rsi", "rdi",
+        "r8", "r9", "r10", "r11", "memory", "cc");
+  return ok;
+}
@@ -0,0 +1,27 @@
+int sub(int a, int b) {
+  return a - b;
+}
+
+int pick(int a, int b, int c, int d, int e, int f) {
+  return a * 100000 + b * 10000 + c * 1000 + d * 100 + e * 10 + f;
+}
+
+int swap(int a, int b) {
+  return sub(b, a);
+}
+
+int rotate(int a, int b, int c, int d, int e, int f) {
+  return pick(f, a, b, c, d, e);
+}
+
+int apply(int (*fn)(int, int), int a, int b) {
+  return fn(a, b) + fn(b, a);
+}
+
+int main() {
+  int x = 7;
+  int y = 3;
+  int n = swap(x, y) + sub(sub(x, y), sub(y, x));
+  int r = rotate(1, 2, 3, 4, 5, 6) - 612345;
+  return n + r + apply(sub, x * 2, y * 5) + 100;
+}
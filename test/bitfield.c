@@ -0,0 +1,40 @@
+struct ipv4 {
+  unsigned version : 4;
+  unsigned ihl : 4;
+  unsigned tos : 8;
+  unsigned len : 16;
+  int delta : 5;
+  int : 0;
+  unsigned char ttl;
+};
+
+struct node {
+  int value;
+  struct node *next;
+};
+
+int version(struct ipv4 *h) {
+  return h->version;
+}
+
+int main() {
+  struct ipv4 h;
+  h.version = 4;
+  h.ihl = 21;
+  h.tos = 0;
+  h.len = 65535;
+  h.delta = -3;
+  h.ttl = 64;
+
+  struct node a;
+  struct node b;
+  a.value = 1;
+  a.next = &b;
+  b.value = 2;
+  a.next->value = a.next->value + 10;
+
+  int v = version(&h);
+  int d = h.delta - 1;
+  int wrap = (h.delta = 17);
+  return v + h.ihl + (h.len == 65535) + d + wrap + b.value + sizeof(h) + h.ttl - 64;
+}
@@ -0,0 +1,65 @@
+struct packet {
+  unsigned flags : 4;
+  int delta : 6;
+};
+
+int main() {
+  int a = -7;
+  unsigned u = 4294967289;
+  long l = -1;
+  char c = 120;
+  int arr[4];
+  int *p = arr;
+  struct packet pk;
+  int r = 0;
+
+  r = r + (a / 2 == -3);
+  r = r + (a % 2 == -1);
+  r = r + (7 % -2 == 1);
+  r = r + (u / 2 == 2147483644);
+  r = r + (u % 10 == 9);
+  r = r + ((a >> 1) == -4);
+  r = r + ((u >> 28) == 15);
+  r = r + ((l << 4) == -16);
+  r = r + ((12 & 10) == 8);
+  r = r + ((12 | 3) == 15);
+  r = r + ((12 ^ 10) == 6);
+  r = r + ((1 << 3 | 1 & 3 ^ 2) == 11);
+
+  a += 10;
+  a -= 1;
+  a *= 4;
+  a /= 3;
+  a %= 5;
+  r = r + (a == 2);
+
+  c += 10;
+  r = r + (c == -126);
+  c >>= 1;
+  r = r + (c == -63);
+
+  u <<= 1;
+  u >>= 29;
+  u ^= 5;
+  u |= 8;
+  u &= 14;
+  r = r + (u == 10);
+
+  arr[2] = 5;
+  p += 2;
+  *p += 3;
+  p -= 1;
+  r = r + (arr[2] == 8) + (p == arr + 1);
+
+  pk.flags = 14;
+  pk.flags += 3;
+  pk.delta = 30;
+  pk.delta += 3;
+  r = r + (pk.flags == 1) + (pk.delta == -31);
+
+  int x = 1;
+  int y = 2;
+  x += y += 3;
+  r = r + (x == 6) + (y == 5);
+  return r;
+}
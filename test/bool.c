@@ -0,0 +1,29 @@
+#include <stdbool.h>
+
+struct flags {
+  bool ready : 1;
+  bool done : 1;
+};
+
+bool is_positive(int x) {
+  return x > 0;
+}
+
+bool not_null(int *p) {
+  return p;
+}
+
+int main() {
+  bool a = 256;
+  bool b = false;
+  _Bool c = -1;
+  int x = 5;
+  bool d = &x;
+  struct flags f;
+  f.ready = 2;
+  f.done = 0;
+  b = a + c;
+  int p = is_positive(-3);
+  int q = not_null(&x) * 10;
+  return a + b + c + d + sizeof(a) + p + q + f.ready + f.done + true;
+}
@@ -0,0 +1,24 @@
+int g;
+
+int area(int a, int b) {
+  int x = a * b;
+  int y = b * a;
+  return x + y;
+}
+
+int reload(int *p) {
+  int a = g;
+  *p = 5;
+  int b = g;
+  return a + b;
+}
+
+int main() {
+  g = 1;
+  int r = reload(&g);
+  int s = 0;
+  int *q = &s;
+  int t = s;
+  *q = 3;
+  return area(3, 4) + r * 10 + t + s;
+}
@@ -0,0 +1,33 @@
+#define N 3
+
+int ga, *gp, gb[2];
+
+int (*pick(int (*a)[4], int i))[4] {
+  return a + i;
+}
+
+int sum(int n, int v[]) {
+  int s = 0;
+  s = v[0] + v[1] + v[n - 1];
+  return s;
+}
+
+int main() {
+  int m[N][2 * 2];
+  m[1][2] = 7;
+  int (*(*fp)(int (*)[4], int))[4] = pick;
+  int (*row)[4] = fp(m, 1);
+  int v[sizeof(int) - 1];
+  v[0] = 1;
+  v[1] = 2;
+  v[2] = 3;
+  int s = sum(3, v);
+  int a = 2, *pa = &a, b[2];
+  gp = &ga;
+  *gp = 3;
+  b[1] = 5;
+  for (int i = 0, j = 3; i < j; i = i + 1)
+    s = s + *pa;
+  s = s + ga + b[1] - 14;
+  return (*row)[2] + sizeof(m) + sizeof m[0] + sizeof(int (*)[4]) + sizeof(char) + s;
+}
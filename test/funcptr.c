@@ -0,0 +1,20 @@
+int add(int a, int b) {
+  return a + b;
+}
+
+int mul(int a, int b) {
+  return a * b;
+}
+
+int apply(int (*op)(int, int), int a, int b) {
+  return op(a, b);
+}
+
+int main() {
+  int (*f)(int, int) = add;
+  int x = apply(mul, 3, 4);
+  int y = (*f)(x, 1);
+  f = &mul;
+  int z = f(2, 3);
+  return x + y + z + (f == mul);
+}
@@ -0,0 +1,33 @@
+static int sq(int x) {
+  return x * x;
+}
+
+inline int max(int a, int b) {
+  if (a > b)
+    return a;
+  return b;
+}
+
+static int fact(int n) {
+  if (n <= 1)
+    return 1;
+  return n * fact(n - 1);
+}
+
+static int add3(int a, int b, int c) {
+  return a + b + c;
+}
+
+static int twice(int x) {
+  return add3(x, x, 0);
+}
+
+static void set(int *p, int v) {
+  *p = v;
+}
+
+int main() {
+  int a = 4;
+  set(&a, 5);
+  return sq(3) + max(4, 7) + fact(4) + twice(sq(2)) + max(sq(a), 20);
+}
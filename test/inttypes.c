@@ -0,0 +1,25 @@
+int sc(char c) {
+  return c;
+}
+
+unsigned short us(unsigned short s) {
+  return s;
+}
+
+int main() {
+  char c = 200;
+  unsigned char uc = 200;
+  short s = 70000;
+  unsigned u = -1;
+  int i = -1;
+  unsigned x = 4294967295;
+  long l = 2147483648;
+  long long ll = -1;
+  unsigned long ul = ll;
+  int r1 = sc(255) == -1;
+  int r2 = us(-1) == 65535;
+  return (c == -56) + (uc == 200) + (s == 4464) + (u > 0) + (i < 0)
+    + (-7 / 2 == -3) + (x / 2 == 2147483647) + (l > 2147483647)
+    + (u + 1 == 0) + ((char)300 == 44) + ((i < u) == 0) + (ul > 0)
+    + r1 + r2;
+}
@@ -0,0 +1,32 @@
+int set(int *p, int v) {
+  *p = v;
+  return v;
+}
+
+int fib(int n) {
+  int a = 0;
+  int b = 1;
+  for (int i = 0; i < n; i += 1) {
+    int t = a + b;
+    a = b;
+    b = t;
+  }
+  return a;
+}
+
+int main() {
+  int x = 1;
+  int y = 2;
+  int k = 0;
+  while (k < 5) {
+    int t = x;
+    x = y;
+    y = t;
+    k += 1;
+  }
+  int m;
+  set(&m, 7);
+  if (x == 2)
+    m += 10;
+  return fib(10) + x * 10 + m;
+}
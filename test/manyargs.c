@@ -0,0 +1,20 @@
+long sum8(long a, long b, long c, long d, long e, long f, long g, long h) {
+  return a + b + c + d + e + f + g + h;
+}
+
+int mix(char a, short b, int c, long d, char e, short f, char g, int h, long i) {
+  return a + b + c + d + e + f + g * 10 + h * 100 - i;
+}
+
+int seven(int a, int b, int c, int d, int e, int f, int g) {
+  return g - a;
+}
+
+int main() {
+  int (*p)(int, int, int, int, int, int, int) = seven;
+  long s = sum8(1, 2, 3, 4, 5, 6, 7, sum8(1, 1, 1, 1, 1, 1, 1, 1));
+  int m = mix(1, 1, 1, 1, 1, 1, -1, 1, 7);
+  int k = seven(0, 0, 0, 0, 0, 0, 9);
+  int n = p(1, 0, 0, 0, 0, 0, k);
+  return s + m + n;
+}
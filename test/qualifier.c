@@ -0,0 +1,17 @@
+int store(volatile int *reg, int v) {
+  *reg = v;
+  return *reg;
+}
+
+int main() {
+  int a = 3;
+  const int b = 4;
+  int * const p = &a;
+  const int *q = &b;
+  volatile int mmio = 0;
+  long l = 7;
+  long *lp = &l;
+  *p = *p + *q;
+  int r = store(&mmio, 5);
+  return a + r + *lp + (lp + 1 - lp) + (&mmio == &mmio);
+}
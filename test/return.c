@@ -0,0 +1,52 @@
+int sign(int x) {
+  if (x < 0)
+    return -1;
+  else if (x == 0)
+    return 0;
+  return 1;
+}
+
+int find(int *a, int n, int v) {
+  for (int i = 0; i < n; i += 1)
+    if (a[i] == v)
+      return i;
+  return -1;
+}
+
+int sum_to(int n) {
+  int s = 0;
+  int i = 0;
+  while (1) {
+    if (i > n)
+      return s;
+    s += i;
+    i += 1;
+  }
+}
+
+int fallback() {
+  return 2;
+  return 3;
+}
+
+int count() {
+  int n = 0;
+  for (;;) {
+    n += 1;
+    if (n == 7)
+      return n;
+  }
+}
+
+int main() {
+  int a[4];
+  a[0] = 5;
+  a[1] = 8;
+  a[2] = 13;
+  a[3] = 21;
+  int r = sign(-4) + sign(0) + sign(9);
+  r += find(a, 4, 13) * 10 + find(a, 4, 4);
+  r += sum_to(10) + fallback() + count();
+  if (r != 83)
+    return 1;
+}
@@ -0,0 +1,18 @@
+int count(int n) {
+  int x = 1;
+  int k = 0;
+  while (k < n) {
+    if (x != 1)
+      x = 2;
+    k += 1;
+  }
+  return x * 10 + k;
+}
+
+int main() {
+  int debug = 0;
+  int r = count(5);
+  if (debug)
+    r = 99;
+  return r;
+}
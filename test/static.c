@@ -0,0 +1,24 @@
+int total = 10;
+static char small = 300;
+static long big;
+
+int counter() {
+  static int n;
+  n = n + 1;
+  return n;
+}
+
+static int twice(int x) {
+  static int calls = 100;
+  calls = calls + 1;
+  return x * 2;
+}
+
+int main() {
+  int a = counter();
+  int b = counter();
+  int c = counter();
+  big = twice(c);
+  total = total + big;
+  return a + b + c + total + small;
+}
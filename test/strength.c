@@ -0,0 +1,22 @@
+int digits(unsigned n) {
+  int sum = 0;
+  while (n != 0) {
+    sum += n % 10;
+    n = n / 10;
+  }
+  return sum;
+}
+
+int scale(int x) {
+  return x * 3 + x * 10 - x * 36 + x * 8;
+}
+
+int main() {
+  int a = -47;
+  long b = 1000000007;
+  unsigned c = 4000000000;
+  int r = a / 7 + a % 7 + a / 8 + a % -16;
+  r = r + (int)(b / 1000 % 1000) + (int)(c / 3 % 100) + (int)(c % 64);
+  r = r + digits(4294967295) + scale(2);
+  return r;
+}
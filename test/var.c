@@ -0,0 +1,16 @@
+int add(int a, int b);
+
+int main() {
+  int a = 3;
+  int b;
+  b = 4;
+  {
+    int a = 10;
+    b = a + b;
+  }
+  return add(a, b);
+}
+
+int add(int a, int b) {
+  return a + b;
+}
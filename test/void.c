@@ -0,0 +1,28 @@
+int n;
+
+void add(int *p, int v) {
+  if (v < 0)
+    return;
+  *p += v;
+}
+
+void bump(void) {
+  n += 1;
+}
+
+void *addr(void) {
+  return &n;
+}
+
+int main(void) {
+  int s = 0;
+  add(&s, 5);
+  add(&s, -3);
+  add(&s, 7);
+  bump();
+  bump();
+  (void)add;
+  int *p = addr();
+  *p += 10;
+  return s * 10 + n;
+}